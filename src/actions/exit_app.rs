@@ -1,10 +1,12 @@
+use crate::dispatcher::UiAsyncMessage;
 use crate::gui::event::Event;
 use crate::gui::msg::Msg;
 use crate::gui::Gui;
 use failure::Error;
 use winapi::um::winuser::MSG;
 
-pub fn exit_app(_event: Event, _gui: &mut Gui) -> Result<(), Error> {
+pub fn exit_app(_event: Event, gui: &mut Gui) -> Result<(), Error> {
+    gui.dispatcher().send_async_msg(UiAsyncMessage::Exit);
     MSG::post_quit(0);
     Ok(())
 }
@@ -5,11 +5,13 @@ use crate::actions::new_input_query::new_input_query;
 use crate::actions::new_plugin_state::new_plugin_state;
 use crate::actions::new_settings::new_settings;
 use crate::actions::restore_columns_position::restore_columns_position;
+use crate::actions::restore_sort_order::restore_sort_order;
 use crate::actions::restore_windows_position::restore_windows_position;
 use crate::actions::save_columns_position::save_columns_position;
 use crate::actions::save_windows_position::save_windows_position;
 use crate::actions::shortcuts::Shortcut;
 use crate::actions::show_files_window::show_files_window;
+use crate::actions::sort_results::sort_results;
 use crate::errors::failure_to_string;
 use crate::gui::event::Event;
 use crate::gui::Gui;
@@ -22,11 +24,13 @@ mod new_input_query;
 mod new_plugin_state;
 mod new_settings;
 mod restore_columns_position;
+mod restore_sort_order;
 mod restore_windows_position;
 mod save_columns_position;
 mod save_windows_position;
 pub mod shortcuts;
 mod show_files_window;
+mod sort_results;
 
 #[derive(Copy, Clone, Debug)]
 pub enum Action {
@@ -67,6 +71,8 @@ pub enum SimpleAction {
     RestoreWindowPosition,
     SaveColumnsPosition,
     RestoreColumnsPosition,
+    SortResults,
+    RestoreSortOrder,
     NewPluginState,
     NewSettings,
     //    FocusOnItemList,
@@ -84,6 +90,8 @@ impl SimpleAction {
             SimpleAction::RestoreWindowPosition => restore_windows_position,
             SimpleAction::SaveColumnsPosition => save_columns_position,
             SimpleAction::RestoreColumnsPosition => restore_columns_position,
+            SimpleAction::SortResults => sort_results,
+            SimpleAction::RestoreSortOrder => restore_sort_order,
             SimpleAction::NewPluginState => new_plugin_state,
             SimpleAction::NewSettings => new_settings,
             SimpleAction::DoNothing => do_nothing,
@@ -103,9 +111,10 @@ impl ComposedAction {
             SimpleAction::ShowFilesWindow,
             SimpleAction::FocusOnInputField,
         ];
-        static RESIZE_WINDOW_FROM_SETTINGS: [SimpleAction; 2] = [
+        static RESIZE_WINDOW_FROM_SETTINGS: [SimpleAction; 3] = [
             SimpleAction::RestoreWindowPosition,
             SimpleAction::RestoreColumnsPosition,
+            SimpleAction::RestoreSortOrder,
         ];
         match self {
             ComposedAction::RestoreWindow => &RESTORE_WINDOW,
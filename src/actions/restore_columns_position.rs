@@ -28,6 +28,11 @@ pub fn restore_columns_position(_event: Event, gui: &mut Gui) -> Result<(), Erro
         setting_to_int(Setting::ColumnFileSizeWidth, settings),
         wnd,
     )?;
+    restore_column(
+        3,
+        setting_to_int(Setting::ColumnFileModifiedWidth, settings),
+        wnd,
+    )?;
     Ok(())
 }
 
@@ -0,0 +1,10 @@
+use crate::file_listing::sort::Sort;
+use crate::gui::event::Event;
+use crate::gui::Gui;
+use failure::Error;
+
+pub fn restore_sort_order(_event: Event, gui: &mut Gui) -> Result<(), Error> {
+    let sort = Sort::from_settings(gui.settings());
+    gui.item_list_mut().header_mut().show_sort(sort);
+    Ok(())
+}
@@ -15,7 +15,7 @@ use winapi::um::commctrl::LVM_GETCOLUMNWIDTH;
 
 pub fn save_columns_position(_event: Event, gui: &mut Gui) -> Result<(), Error> {
     let item_count = get_column_count(gui.item_list().header().wnd())?;
-    assert_eq!(item_count, 3);
+    assert_eq!(item_count, 4);
     let mut properties = HashMap::new();
     for index in 0..item_count {
         let (setting, value) = get_item(index, gui.item_list().wnd())?;
@@ -44,6 +44,7 @@ fn get_item(index: isize, wnd: &Wnd) -> Result<(Setting, String), Error> {
         0 => Ok((Setting::ColumnFileNameWidth, width?.to_string())),
         1 => Ok((Setting::ColumnFilePathWidth, width?.to_string())),
         2 => Ok((Setting::ColumnFileSizeWidth, width?.to_string())),
+        3 => Ok((Setting::ColumnFileModifiedWidth, width?.to_string())),
         _ => bail!("Wrong index - nonexistent column {}", index),
     }
 }
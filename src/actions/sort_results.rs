@@ -0,0 +1,10 @@
+use crate::dispatcher::UiAsyncMessage;
+use crate::gui::event::Event;
+use crate::gui::Gui;
+use failure::Error;
+
+pub fn sort_results(event: Event, gui: &mut Gui) -> Result<(), Error> {
+    let sort = gui.item_list_mut().on_header_click(event);
+    gui.dispatcher().send_async_msg(UiAsyncMessage::Sort(sort));
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use failure::Error;
+use std::io;
+use std::path::Path;
+
+pub trait CompletionPort: Send + Sync {
+    type File: Send;
+    type Output: CompletedOperation;
+
+    fn associate_file<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+        completion_key: usize,
+    ) -> Result<Self::File, Error>;
+    fn submit_read(
+        &self,
+        file: &Self::File,
+        buffer: Vec<u8>,
+        offset: u64,
+        content_len: usize,
+    ) -> Result<(), Error>;
+    fn post_empty(&self, completion_key: usize) -> Result<(), Error>;
+    /// Waits for the next operation, a failed read comes back with its buffer and the error in
+    /// `bytes_read`.
+    fn get_completed(&self) -> Result<Self::Output, Error>;
+}
+
+pub trait CompletedOperation {
+    fn buffer_mut(&mut self) -> &mut [u8];
+    fn into_buffer(self) -> Vec<u8>;
+    fn content_len(&self) -> usize;
+    /// What the read got, less than the buffer at the end of the file.
+    fn bytes_read(&self) -> io::Result<usize>;
+    fn completion_key(&self) -> usize;
+}
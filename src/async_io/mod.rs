@@ -0,0 +1,6 @@
+mod buffer_pool;
+mod completion_port;
+mod thread_port;
+pub use self::buffer_pool::BufferPool;
+pub use self::completion_port::{CompletedOperation, CompletionPort};
+pub use self::thread_port::{ThreadFile, ThreadOperation, ThreadPort};
@@ -0,0 +1,236 @@
+use crate::async_io::{CompletedOperation, CompletionPort};
+use crate::errors::MyErrorKind::AsyncReadError;
+use crossbeam_channel as channel;
+use failure::{Error, ResultExt};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
+pub struct ThreadPort {
+    requests: channel::Sender<ReadRequest>,
+    completed_snd: channel::Sender<ThreadOperation>,
+    completed: channel::Receiver<ThreadOperation>,
+}
+
+pub struct ThreadFile {
+    file: Arc<File>,
+    completion_key: usize,
+}
+
+struct ReadRequest {
+    file: Arc<File>,
+    completion_key: usize,
+    buffer: Vec<u8>,
+    offset: u64,
+    content_len: usize,
+}
+
+pub struct ThreadOperation {
+    buffer: Vec<u8>,
+    content_len: usize,
+    completion_key: usize,
+    result: io::Result<usize>,
+}
+
+impl ThreadPort {
+    pub fn new(threads: u32) -> Result<Self, Error> {
+        let (requests, requests_rcv) = channel::unbounded::<ReadRequest>();
+        let (completed_snd, completed) = channel::unbounded();
+        for i in 0..threads.max(1) {
+            let requests_rcv = requests_rcv.clone();
+            let completed_snd = completed_snd.clone();
+            thread::Builder::new()
+                .name(format!("thread port {}", i))
+                .spawn(move || {
+                    while let Some(mut request) = requests_rcv.recv() {
+                        let result = read_at(&request.file, &mut request.buffer, request.offset);
+                        completed_snd.send(ThreadOperation {
+                            buffer: request.buffer,
+                            content_len: request.content_len,
+                            completion_key: request.completion_key,
+                            result,
+                        });
+                    }
+                })
+                .context(AsyncReadError)?;
+        }
+        Ok(ThreadPort {
+            requests,
+            completed_snd,
+            completed,
+        })
+    }
+}
+
+#[cfg(unix)]
+fn read_at_offset(file: &File, buffer: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::unix::fs::FileExt;
+    file.read_at(buffer, offset)
+}
+
+#[cfg(windows)]
+fn read_at_offset(file: &File, buffer: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::windows::fs::FileExt;
+    file.seek_read(buffer, offset)
+}
+
+fn read_at(file: &File, buffer: &mut [u8], offset: u64) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match read_at_offset(file, &mut buffer[read..], offset + read as u64) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+impl CompletionPort for ThreadPort {
+    type File = ThreadFile;
+    type Output = ThreadOperation;
+
+    fn associate_file<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+        completion_key: usize,
+    ) -> Result<ThreadFile, Error> {
+        let file = File::open(file_path).context(AsyncReadError)?;
+        Ok(ThreadFile {
+            file: Arc::new(file),
+            completion_key,
+        })
+    }
+
+    fn submit_read(
+        &self,
+        file: &ThreadFile,
+        buffer: Vec<u8>,
+        offset: u64,
+        content_len: usize,
+    ) -> Result<(), Error> {
+        self.requests.send(ReadRequest {
+            file: file.file.clone(),
+            completion_key: file.completion_key,
+            buffer,
+            offset,
+            content_len,
+        });
+        Ok(())
+    }
+
+    fn post_empty(&self, completion_key: usize) -> Result<(), Error> {
+        self.completed_snd.send(ThreadOperation {
+            buffer: Vec::new(),
+            content_len: 0,
+            completion_key,
+            result: Ok(0),
+        });
+        Ok(())
+    }
+
+    fn get_completed(&self) -> Result<ThreadOperation, Error> {
+        match self.completed.recv() {
+            Some(operation) => Ok(operation),
+            None => Err(AsyncReadError)?,
+        }
+    }
+}
+
+impl CompletedOperation for ThreadOperation {
+    fn buffer_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer
+    }
+
+    fn into_buffer(self) -> Vec<u8> {
+        self.buffer
+    }
+
+    fn content_len(&self) -> usize {
+        self.content_len
+    }
+
+    fn bytes_read(&self) -> io::Result<usize> {
+        match self.result {
+            Ok(read) => Ok(read),
+            Err(ref e) => Err(match e.raw_os_error() {
+                Some(code) => io::Error::from_raw_os_error(code),
+                None => io::Error::new(e.kind(), e.to_string()),
+            }),
+        }
+    }
+
+    fn completion_key(&self) -> usize {
+        self.completion_key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempFile;
+
+    fn hello_world() -> TempFile {
+        TempFile::with_content("thread_port", b"hello world")
+    }
+
+    #[test]
+    fn test_thread_port_read() {
+        let tmp = hello_world();
+        let port = ThreadPort::new(1).unwrap();
+        let file = port.associate_file(tmp.path(), 42).unwrap();
+
+        port.submit_read(&file, vec![0u8; 20], 0, 3).unwrap();
+
+        let output_operation = port.get_completed().unwrap();
+        let bytes_read = output_operation.bytes_read().unwrap();
+
+        assert_eq!(bytes_read, "hello world".as_bytes().len());
+        assert_eq!(output_operation.completion_key(), 42);
+        assert_eq!(output_operation.content_len(), 3);
+        assert_eq!(
+            &output_operation.into_buffer()[..bytes_read],
+            "hello world".as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_thread_port_read_at_offset() {
+        let tmp = hello_world();
+        let port = ThreadPort::new(2).unwrap();
+        let file = port.associate_file(tmp.path(), 42).unwrap();
+
+        port.submit_read(&file, vec![0u8; 5], 6, 1).unwrap();
+
+        let output_operation = port.get_completed().unwrap();
+        assert_eq!(5, output_operation.bytes_read().unwrap());
+        assert_eq!(&output_operation.into_buffer()[..], "world".as_bytes());
+    }
+
+    #[test]
+    fn test_thread_port_short_read() {
+        let tmp = hello_world();
+        let port = ThreadPort::new(1).unwrap();
+        let file = port.associate_file(tmp.path(), 42).unwrap();
+
+        port.submit_read(&file, vec![0xFFu8; 20], 6, 4).unwrap();
+
+        let output_operation = port.get_completed().unwrap();
+        assert_eq!(5, output_operation.bytes_read().unwrap());
+        assert_eq!(4, output_operation.content_len());
+    }
+
+    #[test]
+    fn test_thread_port_post() {
+        let port = ThreadPort::new(1).unwrap();
+
+        port.post_empty(42).unwrap();
+        let output_operation = port.get_completed().unwrap();
+
+        assert_eq!(output_operation.completion_key(), 42);
+        assert_eq!(output_operation.bytes_read().unwrap(), 0);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::file_listing::sort::Sort;
 use crate::file_listing::FilesMsg;
 use crate::gui::event::Event;
 use crate::gui::Wnd;
@@ -66,6 +67,9 @@ pub enum UiAsyncMessage {
     Start(Wnd),
     Ui(String),
     Files(FilesMsg),
+    Sort(Option<Sort>),
+    /// The window is closing, the files are saved before returning.
+    Exit,
     UpdateSettings(HashMap<Setting, String>),
 }
 
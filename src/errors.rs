@@ -16,6 +16,24 @@ pub enum MyErrorKind {
     UsnJournalError,
     #[fail(display = "UsnRecord v{} is not supported", _0)]
     UsnRecordVersionUnsupported(u16),
+    #[fail(display = "Invalid NTFS boot sector: {}", _0)]
+    InvalidBootSector(&'static str),
+    #[fail(display = "Error while reading NTFS image.")]
+    ImageReadError,
+    #[fail(display = "Error while reading $MFT file.")]
+    MftFileReadError,
+    #[fail(display = "Error while reading from thread port.")]
+    AsyncReadError,
+    #[fail(display = "Invalid file record: {}", _0)]
+    InvalidFileRecord(&'static str),
+    #[fail(display = "Invalid query: {}", _0)]
+    InvalidQuery(&'static str),
+    #[fail(display = "Invalid index snapshot: {}", _0)]
+    InvalidSnapshot(&'static str),
+    #[fail(display = "Can't resume reading the change journal: {}", _0)]
+    StaleJournalPosition(&'static str),
+    #[fail(display = "Error while accessing the index store: {}", _0)]
+    StoreError(&'static str),
 }
 
 //Boilerplate start
@@ -72,3 +90,18 @@ pub fn failure_to_string(e: Error) -> String {
     }
     result
 }
+
+pub fn failure_to_line(e: &Error) -> String {
+    e.iter_chain()
+        .filter_map(|cause| {
+            let cause = cause.to_string();
+            cause
+                .lines()
+                .rev()
+                .map(str::trim)
+                .find(|l| !l.is_empty())
+                .map(str::to_string)
+        })
+        .collect::<Vec<_>>()
+        .join(": ")
+}
@@ -1,3 +1,5 @@
+use crate::ntfs::attributes::FileAttributes;
+use crate::ntfs::attributes::StandardAttr;
 use crate::ntfs::change_journal::UsnRecord;
 use crate::ntfs::file_record::FileRecord;
 use rusqlite::Result;
@@ -8,12 +10,38 @@ const DOS_NAMESPACE: u8 = 2;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct FileEntity {
-    name: String,
-    parent_id: FileId,
+    names: Vec<FileName>,
     size: i64,
     id: FileId,
     _id: u32,
     flags: u16,
+    times: FileTimes,
+    attributes: FileAttributes,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FileTimes {
+    pub created: i64,
+    pub modified: i64,
+    pub mft_modified: i64,
+    pub accessed: i64,
+}
+
+impl<'a> From<&'a StandardAttr> for FileTimes {
+    fn from(attr: &StandardAttr) -> Self {
+        FileTimes {
+            created: attr.created,
+            modified: attr.modified,
+            mft_modified: attr.mft_modified,
+            accessed: attr.accessed,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileName {
+    name: String,
+    parent_id: FileId,
 }
 
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialOrd, PartialEq, Hash)]
@@ -22,6 +50,12 @@ pub struct FileId {
     f_type: FileType,
 }
 
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialOrd, PartialEq, Hash)]
+pub struct LinkId {
+    id: FileId,
+    link: u16,
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord, Hash)]
 pub enum FileType {
     DIRECTORY,
@@ -60,16 +94,55 @@ impl FileId {
     }
 }
 
+impl LinkId {
+    pub fn new(id: FileId, link: u16) -> LinkId {
+        LinkId { id, link }
+    }
+
+    pub fn id(self) -> FileId {
+        self.id
+    }
+
+    pub fn link(self) -> u16 {
+        self.link
+    }
+}
+
+impl From<FileId> for LinkId {
+    fn from(id: FileId) -> Self {
+        LinkId::new(id, 0)
+    }
+}
+
+impl FileName {
+    pub fn new<T: Into<String>>(name: T, parent_id: FileId) -> FileName {
+        FileName {
+            name: name.into(),
+            parent_id,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn parent_id(&self) -> FileId {
+        self.parent_id
+    }
+}
+
 impl From<FileRecord> for FileEntity {
     fn from(file: FileRecord) -> Self {
         let fr_number = file.fr_number();
-        let name = file
+        let names = file
             .name_attrs
             .into_iter()
             .filter(|n| n.namespace != DOS_NAMESPACE)
-            .take(1)
-            .next()
-            .unwrap_or_else(|| panic!("Found a file record without name: {}", fr_number));
+            .map(|n| FileName::new(n.name, FileId::directory(n.parent_id as u32)))
+            .collect::<Vec<FileName>>();
+        if names.is_empty() {
+            panic!("Found a file record without name: {}", fr_number);
+        }
 
         let id = if file.header.flags & 0x02 != 0 {
             FileId::directory(file.header.fr_number)
@@ -77,35 +150,44 @@ impl From<FileRecord> for FileEntity {
             FileId::file(file.header.fr_number)
         };
         FileEntity {
-            name: name.name,
-            parent_id: FileId::directory(name.parent_id as u32),
+            names,
             size: file.data_attr.size,
             id,
             _id: u32::MAX,
             flags: file.header.flags,
+            times: FileTimes::from(&file.standard_attr),
+            attributes: file.standard_attr.attributes,
         }
     }
 }
 
 impl FileEntity {
+    /// Reads a row of `sql::SELECT_FILES`, one name of the record.
     pub fn from_file_row(row: &Row) -> Result<FileEntity> {
-        let _id = row.get::<usize, u32>(0)?;
-        let parent_id = FileId::directory(row.get::<usize, i64>(2)? as u32);
+        let parent_id = FileId::directory(row.get::<usize, i64>(1)? as u32);
+        let name = row.get::<usize, String>(2)?;
+        let flags = row.get::<usize, u16>(3)?;
         let size = row.get::<usize, i64>(4)?;
-        let name = row.get::<usize, String>(5)?;
-        let flags = row.get::<usize, u16>(8)?;
+        let times = FileTimes {
+            modified: row.get::<usize, i64>(5)?,
+            created: row.get::<usize, i64>(6)?,
+            mft_modified: row.get::<usize, i64>(7)?,
+            accessed: row.get::<usize, i64>(8)?,
+        };
+        let attributes = FileAttributes::from_bits_truncate(row.get::<usize, u32>(9)?);
         let id = if flags & 0x02 != 0 {
-            FileId::directory(row.get::<usize, u32>(1)?)
+            FileId::directory(row.get::<usize, u32>(0)?)
         } else {
-            FileId::file(row.get::<usize, u32>(1)?)
+            FileId::file(row.get::<usize, u32>(0)?)
         };
         Ok(FileEntity {
-            name,
-            parent_id,
+            names: vec![FileName::new(name, parent_id)],
             size,
             id,
-            _id,
+            _id: u32::MAX,
             flags,
+            times,
+            attributes,
         })
     }
 
@@ -113,12 +195,8 @@ impl FileEntity {
         self.id
     }
 
-    pub fn parent_id(&self) -> FileId {
-        self.parent_id
-    }
-
-    pub fn name(&self) -> &str {
-        &self.name
+    pub fn names(&self) -> &[FileName] {
+        &self.names
     }
 
     pub fn size(&self) -> i64 {
@@ -128,11 +206,71 @@ impl FileEntity {
     pub fn flags(&self) -> u16 {
         self.flags
     }
+
+    pub fn times(&self) -> FileTimes {
+        self.times
+    }
+
+    pub fn attributes(&self) -> FileAttributes {
+        self.attributes
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ntfs::attributes::FilenameAttr;
+
+    fn name_attr(name: &str, parent_id: i64, namespace: u8) -> FilenameAttr {
+        let mut attr = FilenameAttr::default();
+        attr.name = name.to_string();
+        attr.parent_id = parent_id;
+        attr.namespace = namespace;
+        attr
+    }
+
+    #[test]
+    fn file_record_keeps_every_hardlink() {
+        let mut record = FileRecord::default();
+        record.name_attrs = vec![
+            name_attr("LONGNA~1.TXT", 5, DOS_NAMESPACE),
+            name_attr("long name.txt", 5, 1),
+            name_attr("link.txt", 7, 0),
+        ];
+        let entity = FileEntity::from(record);
+        assert_eq!(
+            &[
+                FileName::new("long name.txt", FileId::directory(5)),
+                FileName::new("link.txt", FileId::directory(7)),
+            ],
+            entity.names()
+        );
+    }
+
+    #[test]
+    fn file_record_keeps_standard_info() {
+        let mut record = FileRecord::default();
+        record.name_attrs = vec![name_attr("file.txt", 5, 1)];
+        record.standard_attr = StandardAttr {
+            created: 1,
+            modified: 2,
+            mft_modified: 3,
+            accessed: 4,
+            attributes: FileAttributes::HIDDEN | FileAttributes::ARCHIVE,
+        };
+        let entity = FileEntity::from(record);
+        let times = FileTimes {
+            created: 1,
+            modified: 2,
+            mft_modified: 3,
+            accessed: 4,
+        };
+        assert_eq!(times, entity.times());
+        assert_eq!(
+            FileAttributes::HIDDEN | FileAttributes::ARCHIVE,
+            entity.attributes()
+        );
+    }
 
     #[test]
     fn usn_record_to_file_id_file() {
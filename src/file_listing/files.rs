@@ -1,24 +1,89 @@
 use crate::file_listing::file_entity::FileEntity;
 use crate::file_listing::file_entity::FileId;
+use crate::file_listing::file_entity::FileTimes;
+use crate::file_listing::file_entity::FileType;
+use crate::file_listing::file_entity::LinkId;
+use crate::file_listing::query::FileTree;
+use crate::file_listing::query::Query;
+use crate::file_listing::sort::storage_order;
+use crate::file_listing::sort::Sort;
+use crate::file_listing::sort::SortKeys;
+use crate::file_listing::storage::CompactionStats;
 use crate::file_listing::storage::Storage;
 use crate::file_listing::storage::StorageItem;
+use crate::file_listing::storage::StorageIter;
+use crate::file_listing::trigram_index::IndexStats;
+use crate::ntfs::attributes::FileAttributes;
+use crate::ntfs::change_journal::JournalPosition;
+use rayon::prelude::*;
 use std::borrow::Borrow;
+use std::cmp;
 use std::cmp::Ordering;
-use twoway;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+const SEARCH_CHUNK: usize = 16 * 1024;
+const SEARCH_BATCH: usize = 64;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+const COMPACTION_MIN_TOMBSTONES: usize = 10_000;
+const COMPACTION_TOMBSTONES_PERCENT: usize = 10;
+const CHANGE_LOG_SIZE: usize = 100_000;
 
 #[derive(Debug, Eq)]
 pub struct FileData {
     id: FileId,
-    parent_id: FileId,
-    name_id: NameId,
+    links: Vec<Link>,
     size: i64,
     flags: u16,
     deleted: bool,
+    times: FileTimes,
+    attributes: FileAttributes,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct NameId(pub u32);
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Link {
+    parent_id: FileId,
+    name_id: NameId,
+    // kept in place once the name is gone so the positions of the other links never move
+    removed: bool,
+}
+
+impl Link {
+    pub fn new(parent_id: FileId) -> Link {
+        Link {
+            parent_id,
+            name_id: NameId(0),
+            removed: false,
+        }
+    }
+
+    pub fn set_removed(&mut self, removed: bool) {
+        self.removed = removed;
+    }
+
+    pub fn removed(&self) -> bool {
+        self.removed
+    }
+
+    pub fn set_name_id(&mut self, name_id: NameId) {
+        self.name_id = name_id;
+    }
+
+    pub fn name_id(&self) -> NameId {
+        self.name_id
+    }
+
+    pub fn parent_id(&self) -> FileId {
+        self.parent_id
+    }
+}
+
 impl PartialOrd for FileData {
     fn partial_cmp(&self, other: &FileData) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -41,16 +106,38 @@ impl FileData {
     pub fn new(id: FileId, parent_id: FileId, size: i64, flags: u16, deleted: bool) -> FileData {
         FileData {
             id,
-            parent_id,
+            links: vec![Link::new(parent_id)],
             size,
             flags,
             deleted,
-            name_id: NameId(0),
+            times: FileTimes::default(),
+            attributes: FileAttributes::empty(),
         }
     }
 
-    pub fn set_name_id(&mut self, name_id: NameId) {
-        self.name_id = name_id;
+    pub fn set_times(&mut self, times: FileTimes) {
+        self.times = times;
+    }
+
+    pub fn set_attributes(&mut self, attributes: FileAttributes) {
+        self.attributes = attributes;
+    }
+
+    pub fn add_link(&mut self, parent_id: FileId) {
+        self.links.push(Link::new(parent_id));
+    }
+
+    /// Every link by position, those removed included.
+    pub fn links(&self) -> &[Link] {
+        &self.links
+    }
+
+    pub fn live_links(&self) -> impl Iterator<Item = &Link> {
+        self.links.iter().filter(|l| !l.removed)
+    }
+
+    pub fn links_mut(&mut self) -> &mut Vec<Link> {
+        &mut self.links
     }
 
     pub fn set_deleted(&mut self, deleted: bool) {
@@ -61,16 +148,12 @@ impl FileData {
         self.deleted
     }
 
-    pub fn name_id(&self) -> NameId {
-        self.name_id
-    }
-
     pub fn id(&self) -> FileId {
         self.id
     }
 
     pub fn parent_id(&self) -> FileId {
-        self.parent_id
+        self.live_links().next().unwrap_or(&self.links[0]).parent_id
     }
 
     pub fn size(&self) -> i64 {
@@ -80,8 +163,16 @@ impl FileData {
     pub fn flags(&self) -> u16 {
         self.flags
     }
+
+    pub fn times(&self) -> FileTimes {
+        self.times
+    }
+
+    pub fn attributes(&self) -> FileAttributes {
+        self.attributes
+    }
     pub fn is_root(&self) -> bool {
-        self.parent_id == self.id
+        self.parent_id() == self.id
     }
 
     pub fn is_directory(&self) -> bool {
@@ -92,12 +183,13 @@ impl FileData {
 impl From<FileEntity> for FileData {
     fn from(f: FileEntity) -> FileData {
         FileData {
-            parent_id: f.parent_id(),
+            links: f.names().iter().map(|n| Link::new(n.parent_id())).collect(),
             size: f.size(),
             id: f.id(),
             flags: f.flags(),
             deleted: false,
-            name_id: NameId(0),
+            times: f.times(),
+            attributes: f.attributes(),
         }
     }
 }
@@ -105,45 +197,206 @@ impl From<FileEntity> for FileData {
 pub struct Files {
     separator: String,
     storage: Storage,
+    version: u64,
+    // files changed by each version from `changes_from` on, to bring older results up to date
+    changes: Vec<(u64, FileId)>,
+    changes_from: u64,
+    // changes with new names or folders, the only ones the sort keys depend on
+    names_version: u64,
+    journal: JournalPosition,
+    // built on the first sort after such a change
+    sort_keys: Mutex<Option<(u64, Arc<SortKeys>)>>,
 }
 
 unsafe impl Send for Files {}
 
 impl Files {
-    pub fn new(_count: usize) -> Self {
-        let storage = Storage::new();
+    pub fn new() -> Self {
+        Files::from_storage(Storage::new(), JournalPosition::default())
+    }
+
+    /// Files of a storage holding every change up to `journal`.
+    pub fn from_storage(storage: Storage, journal: JournalPosition) -> Self {
         let separator = "\\".to_owned();
-        Files { storage, separator }
+        Files {
+            storage,
+            separator,
+            version: 0,
+            changes: Vec::new(),
+            changes_from: 0,
+            names_version: 0,
+            journal,
+            sort_keys: Mutex::new(None),
+        }
+    }
+
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    /// Where the change journal was read up to when the files last changed.
+    pub fn journal_position(&self) -> JournalPosition {
+        self.journal
+    }
+
+    pub fn set_journal_position(&mut self, journal: JournalPosition) {
+        self.journal = journal;
     }
 
     pub fn bulk_add(&mut self, files: Vec<FileEntity>) {
         self.storage.bulk_insert(files);
+        self.version += 1;
+        self.changes.clear();
+        self.changes_from = self.version;
+        self.names_version += 1;
+    }
+
+    /// Takes the files of a new scan in place of these, results of an earlier version are
+    /// searched again instead of refined.
+    pub fn replace(&mut self, files: Files) {
+        let version = self.version + 1;
+        let names_version = self.names_version + 1;
+        *self = files;
+        self.version = version;
+        self.changes.clear();
+        self.changes_from = version;
+        self.names_version = names_version;
     }
 
     pub fn add_file(&mut self, f: FileEntity) {
-        self.storage.upsert(f.clone().into(), f.name());
+        self.update_file(f);
     }
 
     pub fn update_file(&mut self, f: FileEntity) {
-        self.storage.upsert(f.clone().into(), f.name());
+        let names = f.names().iter().map(|n| n.name()).collect::<Vec<&str>>();
+        let name_count = self.storage.names().len();
+        self.storage.upsert(f.clone().into(), &names);
+        if self.storage.names().len() != name_count {
+            self.names_version += 1;
+        }
+        self.changed(f.id());
     }
 
     pub fn get_file<T: Borrow<FileId>>(&self, pos: T) -> StorageItem {
         self.storage.get(pos)
     }
 
+    /// The link a search found, `None` once its file is gone from the storage.
+    pub fn get_link(&self, link: LinkId) -> Option<StorageItem> {
+        self.storage.get_link(link)
+    }
+
     pub fn delete_file(&mut self, id: FileId) {
         self.storage.delete(id);
+        self.changed(id);
+    }
+
+    pub fn delete_link(&mut self, id: FileId, parent_id: FileId, name: &str) {
+        self.storage.delete_link(id, parent_id, name);
+        self.changed(id);
     }
 
-    pub fn path_of(&self, file: &FileData) -> String {
+    fn changed(&mut self, id: FileId) {
+        if id.f_type() == FileType::DIRECTORY {
+            self.names_version += 1;
+        }
+        self.version += 1;
+        self.changes.push((self.version, id));
+        if self.changes.len() > CHANGE_LOG_SIZE {
+            let dropped = self.changes.len() - CHANGE_LOG_SIZE / 2;
+            self.changes_from = self.changes[dropped - 1].0;
+            self.changes.drain(..dropped);
+        }
+    }
+
+    /// Files changed after `version`, `None` when it is too old to tell.
+    pub fn changed_since(&self, version: u64) -> Option<HashSet<FileId>> {
+        if version < self.changes_from {
+            return None;
+        }
+        let first = self.changes.iter().position(|(v, _)| *v > version);
+        let changes = first.map_or(&[][..], |first| &self.changes[first..]);
+        Some(changes.iter().map(|(_, id)| *id).collect())
+    }
+
+    /// The results found at `version` with every link of the files changed since in place of
+    /// theirs, ready to be searched again by a query refining the one which found them.
+    pub fn results_since(&self, results: &[LinkId], version: u64) -> Option<Vec<LinkId>> {
+        let changed = self.changed_since(version)?;
+        let mut links = results
+            .iter()
+            .filter(|link| !changed.contains(&link.id()))
+            .cloned()
+            .collect::<Vec<_>>();
+        for id in changed {
+            links.extend(self.storage.items_of(id).map(|item| item.link));
+        }
+        Some(links)
+    }
+
+    /// True once enough of the records are tombstones to be worth a `compact`.
+    pub fn needs_compaction(&self) -> bool {
+        let tombstones = self.storage.tombstones();
+        tombstones >= COMPACTION_MIN_TOMBSTONES
+            && tombstones * 100 >= self.storage.len() * COMPACTION_TOMBSTONES_PERCENT
+    }
+
+    /// Drops deleted files and unused names, keeping the files in `keep` which may still be
+    /// looked up by results being displayed.
+    pub fn compact(&mut self, keep: &HashSet<FileId>) -> CompactionStats {
+        self.version += 1;
+        self.names_version += 1;
+        self.storage.compact(keep)
+    }
+
+    pub fn name_index_stats(&self) -> Option<IndexStats> {
+        self.storage.name_index_stats()
+    }
+
+    /// Changes on every update, results of an older version may be stale.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Sorts results by `sort` comparing ranks of their names and folders, computed again only
+    /// once a change brings new names or touches a folder.
+    pub fn sort(&self, links: &mut [LinkId], sort: Sort) {
+        self.sort_keys().sort(&self.storage, links, sort);
+    }
+
+    fn sort_keys(&self) -> Arc<SortKeys> {
+        let mut cached = self.sort_keys.lock().unwrap();
+        if let Some((version, ref keys)) = *cached {
+            if version == self.names_version {
+                return keys.clone();
+            }
+        }
+        let keys = Arc::new(SortKeys::build(&self.storage));
+        *cached = Some((self.names_version, keys.clone()));
+        keys
+    }
+
+    pub fn paths_of(&self, file: &FileData) -> Vec<String> {
+        self.storage
+            .items_of(file.id())
+            .map(|item| self.path_of(&item))
+            .collect()
+    }
+
+    pub fn path_of(&self, item: &StorageItem) -> String {
         let mut result = String::new();
         let mut parents: Vec<&str> = Vec::new();
-        let mut current = file;
-        while !current.is_root() {
-            let item = self.storage.get(current.parent_id());
-            parents.push(item.name);
-            current = item.data;
+        let mut current = item.data;
+        let mut parent_id = item.parent_id;
+        while current.id() != parent_id {
+            // a folder missing from the storage ends the path
+            let parent = match self.storage.find_item(parent_id) {
+                Some(parent) => parent,
+                None => break,
+            };
+            parents.push(parent.name);
+            current = parent.data;
+            parent_id = parent.parent_id;
         }
         for p in parents.into_iter().rev() {
             result.push_str(p);
@@ -152,53 +405,151 @@ impl Files {
         result
     }
 
-    //    fn new_search_by_name<'a>(&self, name: &'a str) -> Vec<ItemId> {
-    //        println!("2");
-    //        println!("total {}", self.storage.iter().count());
-    //        let now = Instant::now();
-    //        let total = self.storage.iter().filter(|item| twoway::find_str(item.name, name).is_some()).count();
-    //        println!("new search found {} in {:?}ms", total, Instant::now().duration_since(now));
-    //        self.names.par_iter().enumerate()
-    //            .filter(|(_, file_name)| twoway::find_str(file_name, name).is_some())
-    //            .map(|(pos, _)| FileId::new(pos as u32))
-    //            .collect()
-    //    }
-
-    //    fn continue_search_by_name<'a>(&self, name: &'a str, prev_search: &[ItemId]) -> Vec<ItemId> {
-    //        prev_search.par_iter().cloned()
-    //            .filter(|pos| twoway::find_str(self.get_name_of(*pos), name).is_some())
-    //            .collect()
-    //    }
-
-    pub fn search_by_name<'a>(
+    pub fn search_by_name(&self, name: &str) -> Vec<LinkId> {
+        self.search(&Query::from_name(name))
+    }
+
+    pub fn search(&self, query: &Query) -> Vec<LinkId> {
+        self.search_cancellable(query, None, &|| false, &mut |_| {})
+            .unwrap()
+    }
+
+    /// Runs a query refining the one that found `previous` over those results only. They must
+    /// be up to date with the current `version`, see `results_since`; whatever their order the
+    /// results come in the same order as a full search.
+    pub fn search_within(&self, query: &Query, previous: &[LinkId]) -> Vec<LinkId> {
+        self.search_cancellable(query, Some(previous), &|| false, &mut |_| {})
+            .unwrap()
+    }
+
+    /// Searches all the files, or only the `previous` results, in parallel chunks. Results come
+    /// in storage order, directories first, unless the query is ranked. Gives up with `None`
+    /// once `cancelled` returns true; a long search reports the results found so far through
+    /// `progress` every `PROGRESS_INTERVAL`.
+    pub fn search_cancellable(
         &self,
-        name: &'a str,
-        _prev_search: Option<&[FileId]>,
-    ) -> Vec<FileId> {
-        self.storage
-            .iter()
-            .filter(|item| twoway::find_str(item.name, name).is_some())
-            .map(|i| i.data.id())
-            .collect()
+        query: &Query,
+        previous: Option<&[LinkId]>,
+        cancelled: &(dyn Fn() -> bool + Sync),
+        progress: &mut dyn FnMut(Vec<LinkId>),
+    ) -> Option<Vec<LinkId>> {
+        let mut query = query.clone();
+        query.resolve(self);
+        // names without the trigrams of the needles are skipped before matching the query,
+        // without previous results only the files using the other names are visited
+        let candidates = self.storage.name_candidates(&query.name_needles());
+        let names = candidates.as_ref().map(|c| c.names.as_slice());
+        match (previous, &candidates) {
+            (Some(previous), _) => {
+                let chunks = previous
+                    .chunks(SEARCH_CHUNK)
+                    .map(|links| {
+                        links
+                            .iter()
+                            .filter_map(move |link| self.storage.get_link(*link))
+                    })
+                    .collect();
+                let mut found = self.search_chunks(&query, chunks, names, cancelled, progress)?;
+                if !query.is_ranked() {
+                    // previous results may have been sorted
+                    found.par_sort_unstable_by(|a, b| storage_order(*a, *b));
+                }
+                Some(found)
+            }
+            (None, Some(candidates)) => {
+                let chunks = candidates
+                    .files
+                    .chunks(SEARCH_CHUNK)
+                    .map(|ids| ids.iter().flat_map(move |id| self.storage.items_of(*id)))
+                    .collect();
+                self.search_chunks(&query, chunks, names, cancelled, progress)
+            }
+            (None, None) => {
+                let chunks = self.storage.chunks(SEARCH_CHUNK);
+                self.search_chunks(&query, chunks, names, cancelled, progress)
+            }
+        }
     }
 
-    //    pub fn search_by_name<'a>(&self, name: &'a str, prev_search: Option<&[FileId]>) -> Vec<FileId> {
-    //        if name.is_empty() {
-    //            println!("1");
-    //            self.sorted_idx.clone()
-    //        } else {
-    //            match prev_search {
-    //                None => self.new_search_by_name(name),
-    //                Some(prev) => self.continue_search_by_name(name, prev)
-    //            }
-    //        }
-    //    }
+    fn search_chunks<'a, I>(
+        &'a self,
+        query: &Query,
+        mut chunks: Vec<I>,
+        candidates: Option<&[bool]>,
+        cancelled: &(dyn Fn() -> bool + Sync),
+        progress: &mut dyn FnMut(Vec<LinkId>),
+    ) -> Option<Vec<LinkId>>
+    where
+        I: Iterator<Item = StorageItem<'a>> + Send,
+    {
+        let mut found = Vec::new();
+        let mut reported = Instant::now();
+        while !chunks.is_empty() {
+            let rest = chunks.split_off(cmp::min(SEARCH_BATCH, chunks.len()));
+            let batch: Option<Vec<Vec<(i64, LinkId)>>> = chunks
+                .into_par_iter()
+                .map(|items| self.search_chunk(query, items, candidates, cancelled))
+                .collect();
+            found.extend(batch?.into_iter().flatten());
+            chunks = rest;
+            if !chunks.is_empty() && reported.elapsed() >= PROGRESS_INTERVAL {
+                progress(in_order(query, found.clone()));
+                reported = Instant::now();
+            }
+        }
+        Some(in_order(query, found))
+    }
+
+    fn search_chunk<'a, I>(
+        &'a self,
+        query: &Query,
+        items: I,
+        candidates: Option<&[bool]>,
+        cancelled: &(dyn Fn() -> bool + Sync),
+    ) -> Option<Vec<(i64, LinkId)>>
+    where
+        I: Iterator<Item = StorageItem<'a>>,
+    {
+        if cancelled() {
+            return None;
+        }
+        let found = items
+            .filter(|item| !item.data.deleted() && !item.removed)
+            .filter(|item| candidates.map_or(true, |c| c[item.name_id.0 as usize]))
+            .filter_map(|item| query.score(&item, self).map(|score| (score, item.link)))
+            .collect();
+        Some(found)
+    }
+}
+
+fn in_order(query: &Query, mut found: Vec<(i64, LinkId)>) -> Vec<LinkId> {
+    if query.is_ranked() {
+        // ties keep the storage order whatever order the items came in
+        found
+            .sort_by_key(|(score, link)| (-score, link.id().f_type(), link.id().id(), link.link()));
+    }
+    found.into_iter().map(|(_, link)| link).collect()
+}
+
+impl FileTree for Files {
+    fn items(&self) -> StorageIter {
+        self.storage.iter()
+    }
+
+    fn get(&self, id: FileId) -> Option<StorageItem> {
+        self.storage.find_item(id)
+    }
+
+    fn path_of(&self, item: &StorageItem) -> String {
+        Files::path_of(self, item)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::file_listing::file_entity::FileId;
+    use crate::file_listing::sort::SortColumn;
     use crate::ntfs::attributes::FilenameAttr;
     use crate::ntfs::file_record::FileRecord;
 
@@ -206,7 +557,7 @@ mod tests {
     const DIR: u16 = 2;
 
     fn test_data() -> Files {
-        let mut files = Files::new(1);
+        let mut files = Files::new();
         let file0 = FileData::new(FileId::file(0), FileId::directory(1), 0, FILE, false);
         let dir0 = FileData::new(FileId::directory(0), FileId::directory(1), 0, DIR, false);
         let file1 = FileData::new(FileId::file(1), FileId::directory(1), 0, FILE, false);
@@ -215,13 +566,13 @@ mod tests {
         let dir2 = FileData::new(FileId::directory(2), FileId::directory(1), 0, DIR, false);
         let dir3 = FileData::new(FileId::directory(3), FileId::directory(2), 0, DIR, false);
 
-        files.storage.upsert(file2, "file2");
-        files.storage.upsert(file1, "file1");
-        files.storage.upsert(file0, "file0");
-        files.storage.upsert(dir3, "dir3");
-        files.storage.upsert(dir2, "dir2");
-        files.storage.upsert(dir1, "dir1");
-        files.storage.upsert(dir0, "dir0");
+        files.storage.upsert(file2, &["file2"]);
+        files.storage.upsert(file1, &["file1"]);
+        files.storage.upsert(file0, &["file0"]);
+        files.storage.upsert(dir3, &["dir3"]);
+        files.storage.upsert(dir2, &["dir2"]);
+        files.storage.upsert(dir1, &["dir1"]);
+        files.storage.upsert(dir0, &["dir0"]);
 
         files
     }
@@ -252,22 +603,34 @@ mod tests {
         FileEntity::from(entry)
     }
 
+    fn new_hardlinked_file(id: u32, links: &[(&str, u32)]) -> FileEntity {
+        let mut entry = FileRecord::default();
+        entry.header.fr_number = id;
+        for (name, parent) in links {
+            let mut entry_name = FilenameAttr::default();
+            entry_name.name = name.to_string();
+            entry_name.parent_id = i64::from(*parent);
+            entry.name_attrs.push(entry_name);
+        }
+        FileEntity::from(entry)
+    }
+
     #[test]
     fn empty_files() {
-        let files = Files::new(5);
-        assert!(files.search_by_name("", None).is_empty())
+        let files = Files::new();
+        assert!(files.search_by_name("").is_empty())
     }
 
     #[test]
     fn search_by_name() {
         let files = test_data();
 
-        let search = files.search_by_name("0", None);
+        let search = files.search_by_name("0");
         assert_eq!(2, search.len());
-        assert_eq!(&"dir0", &files.get_file(search.get(0).unwrap()).name);
-        assert_eq!(&"file0", &files.get_file(search.get(1).unwrap()).name);
+        assert_eq!(&"dir0", &files.get_link(search[0]).unwrap().name);
+        assert_eq!(&"file0", &files.get_link(search[1]).unwrap().name);
 
-        let search = files.search_by_name("4", None);
+        let search = files.search_by_name("4");
         assert!(search.is_empty());
     }
 
@@ -275,49 +638,438 @@ mod tests {
     fn get_paths() {
         let files = test_data();
 
-        let f = files.get_file(FileId::file(0)).data;
-        assert_eq!("dir1\\", files.path_of(f));
-        let f = files.get_file(FileId::file(1)).data;
-        assert_eq!("dir1\\", files.path_of(f));
-        let f = files.get_file(FileId::directory(3)).data;
-        assert_eq!("dir1\\dir2\\", files.path_of(f));
+        let f = files.get_file(FileId::file(0));
+        assert_eq!("dir1\\", files.path_of(&f));
+        let f = files.get_file(FileId::file(1));
+        assert_eq!("dir1\\", files.path_of(&f));
+        let f = files.get_file(FileId::directory(3));
+        assert_eq!("dir1\\dir2\\", files.path_of(&f));
     }
 
     #[test]
     fn after_adding_file_sorted_new_file_is_present() {
         let mut files = test_data();
-        let prev_search = files.search_by_name("file0", None).len();
+        let prev_search = files.search_by_name("file0").len();
         let new_file = FileData::new(FileId::file(3), FileId::directory(1), 42, FILE, false);
 
-        files.storage.upsert(new_file, "a_file0");
-        let search = files.search_by_name("file0", None);
+        files.storage.upsert(new_file, &["a_file0"]);
+        let search = files.search_by_name("file0");
 
         assert_eq!(prev_search + 1, search.len());
-        assert_eq!(&"a_file0", &files.get_file(search.get(1).unwrap()).name);
+        assert_eq!(&"a_file0", &files.get_link(search[1]).unwrap().name);
     }
 
     #[test]
     fn adding_file_doesnt_invalidate_existing_item_id() {
         let mut files = test_data();
 
-        let search = files.search_by_name("file0", None);
+        let search = files.search_by_name("file0");
         let new_file = FileData::new(FileId::file(3), FileId::directory(1), 42, FILE, false);
-        files.storage.upsert(new_file, "a_file0");
+        files.storage.upsert(new_file, &["a_file0"]);
 
         assert_eq!(1, search.len());
-        assert_eq!(&"file0", &files.get_file(search.get(0).unwrap()).name);
+        assert_eq!(&"file0", &files.get_link(search[0]).unwrap().name);
     }
 
     #[test]
     fn update_existing_file() {
         let mut files = test_data();
         let update_file = FileData::new(FileId::file(0), FileId::directory(1), 42, FILE, false);
-        files.storage.upsert(update_file, "new_name");
+        files.storage.upsert(update_file, &["new_name"]);
 
-        assert!(files.search_by_name(&"file0", None).is_empty());
-        let search = files.search_by_name(&"new_name", None);
+        assert!(files.search_by_name(&"file0").is_empty());
+        let search = files.search_by_name(&"new_name");
         assert_eq!(1, search.len());
-        assert_eq!(FileId::file(0), files.get_file(search[0]).data.id());
-        assert_eq!("new_name", files.get_file(search[0]).name);
+        assert_eq!(
+            FileId::file(0),
+            files.get_link(search[0]).unwrap().data.id()
+        );
+        assert_eq!("new_name", files.get_link(search[0]).unwrap().name);
+    }
+
+    #[test]
+    fn hardlinked_file_is_found_under_every_path() {
+        let mut files = test_data();
+        files.add_file(new_hardlinked_file(7, &[("report", 1), ("report_link", 3)]));
+
+        let search = files.search_by_name("report");
+        assert_eq!(2, search.len());
+        assert_eq!("dir1\\", files.path_of(&files.get_link(search[0]).unwrap()));
+        assert_eq!(
+            "dir1\\dir2\\dir3\\",
+            files.path_of(&files.get_link(search[1]).unwrap())
+        );
+
+        let data = files.get_file(FileId::file(7)).data;
+        assert_eq!(
+            vec!["dir1\\".to_string(), "dir1\\dir2\\dir3\\".to_string()],
+            files.paths_of(data)
+        );
+    }
+
+    #[test]
+    fn bulk_add_merges_rows_of_the_same_file() {
+        let mut files = Files::new();
+        files.bulk_add(vec![
+            new_dir("root", 5),
+            new_hardlinked_file(7, &[("b", 5)]),
+            new_hardlinked_file(7, &[("a", 5)]),
+        ]);
+
+        assert_eq!(2, files.get_file(FileId::file(7)).data.links().len());
+        assert_eq!(1, files.search_by_name("a").len());
+        assert_eq!(1, files.search_by_name("b").len());
+    }
+
+    #[test]
+    fn deleting_a_link_keeps_the_other_paths() {
+        let mut files = test_data();
+        files.add_file(new_hardlinked_file(7, &[("report", 1), ("report_link", 3)]));
+
+        files.delete_link(FileId::file(7), FileId::directory(1), "report");
+
+        let search = files.search_by_name("report");
+        assert_eq!(1, search.len());
+        assert_eq!("report_link", files.get_link(search[0]).unwrap().name);
+    }
+
+    #[test]
+    fn search_with_date_filter() {
+        let mut files = test_data();
+        let mut old = FileData::new(FileId::file(5), FileId::directory(1), 0, FILE, false);
+        old.set_times(FileTimes {
+            modified: 131_000_000_000_000_000,
+            ..FileTimes::default()
+        });
+        files.storage.upsert(old, &["old_file"]);
+        let mut new = FileData::new(FileId::file(6), FileId::directory(1), 0, FILE, false);
+        new.set_times(FileTimes {
+            modified: 133_000_000_000_000_000,
+            ..FileTimes::default()
+        });
+        files.storage.upsert(new, &["new_file"]);
+
+        let search = files.search(&Query::parse("_file dm:>2020").unwrap());
+        assert_eq!(1, search.len());
+        assert_eq!("new_file", files.get_link(search[0]).unwrap().name);
+    }
+
+    #[test]
+    fn search_with_boolean_query() {
+        let mut files = test_data();
+        files.add_file(new_hardlinked_file(7, &[("report.doc", 1)]));
+        files.add_file(new_hardlinked_file(8, &[("report.tmp", 1)]));
+        files.add_file(new_hardlinked_file(9, &[("notes.doc", 1)]));
+
+        let search = files.search(&Query::parse("(report | notes) -tmp").unwrap());
+        let mut names = search
+            .iter()
+            .map(|link| files.get_link(*link).unwrap().name)
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(vec!["notes.doc", "report.doc"], names);
+    }
+
+    #[test]
+    fn search_with_globs() {
+        let mut files = test_data();
+        files.add_file(new_hardlinked_file(7, &[("report_01.xlsx", 2)]));
+        files.add_file(new_hardlinked_file(8, &[("report_1.xlsx", 3)]));
+
+        let search = files.search(&Query::parse("report_??.xlsx").unwrap());
+        assert_eq!(1, search.len());
+        assert_eq!("report_01.xlsx", files.get_link(search[0]).unwrap().name);
+
+        let search = files.search(&Query::parse("dir2\\**\\*.xlsx").unwrap());
+        let mut names = search
+            .iter()
+            .map(|link| files.get_link(*link).unwrap().name)
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(vec!["report_01.xlsx", "report_1.xlsx"], names);
+    }
+
+    #[test]
+    fn fuzzy_search_is_ranked() {
+        let mut files = test_data();
+        files.add_file(new_hardlinked_file(7, &[("restore.rs", 2)]));
+        files.add_file(new_hardlinked_file(8, &[("storage.rs", 3)]));
+        files.add_file(new_hardlinked_file(9, &[("storage.rs", 1)]));
+
+        let search = files.search(&Query::parse("fuzzy:stor").unwrap());
+        let ids = search.iter().map(|link| link.id()).collect::<Vec<_>>();
+        assert_eq!(vec![FileId::file(9), FileId::file(8), FileId::file(7)], ids);
+    }
+
+    #[test]
+    fn search_with_path_terms() {
+        let mut files = test_data();
+        files.add_file(new_hardlinked_file(7, &[("report.doc", 2)]));
+        files.add_file(new_hardlinked_file(8, &[("report.doc", 3)]));
+        files.add_file(new_hardlinked_file(9, &[("report.doc", 1)]));
+
+        let names = |query: &str| {
+            let mut found = files
+                .search(&Query::parse(query).unwrap())
+                .iter()
+                .map(|link| {
+                    files.path_of(&files.get_link(*link).unwrap())
+                        + files.get_link(*link).unwrap().name
+                })
+                .collect::<Vec<_>>();
+            found.sort();
+            found
+        };
+        assert_eq!(
+            vec!["dir1\\dir2\\dir3\\report.doc", "dir1\\dir2\\report.doc"],
+            names("ir2\\ report")
+        );
+        assert_eq!(vec!["dir1\\dir2\\report.doc"], names("dir2\\rep"));
+        assert_eq!(
+            vec!["dir1\\dir2\\dir3\\report.doc"],
+            names("DIR2/dir3/report.doc")
+        );
+        assert!(names("dir3\\dir2").is_empty());
+    }
+
+    #[test]
+    fn search_in_folder() {
+        let mut files = test_data();
+        files.add_file(new_hardlinked_file(7, &[("report.doc", 2)]));
+        files.add_file(new_hardlinked_file(8, &[("report.doc", 3)]));
+        files.add_file(new_hardlinked_file(9, &[("report.doc", 1)]));
+
+        let ids = |query: &str| {
+            let mut ids = files
+                .search(&Query::parse(query).unwrap())
+                .iter()
+                .map(|link| link.id())
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+        assert_eq!(vec![FileId::file(7)], ids("report parent:dir2"));
+        assert_eq!(
+            vec![FileId::file(7), FileId::file(8)],
+            ids("report infolder:dir1\\dir2\\")
+        );
+        assert!(ids("report infolder:ir2").is_empty());
+    }
+
+    #[test]
+    fn missing_folders_end_the_path() {
+        let mut files = test_data();
+        files.add_file(new_hardlinked_file(7, &[("report.doc", 2)]));
+        files.add_file(new_hardlinked_file(8, &[("report.doc", 40)]));
+
+        let ids = |query: &str| {
+            files
+                .search(&Query::parse(query).unwrap())
+                .iter()
+                .map(|link| link.id())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![FileId::file(7)], ids("report infolder:dir1"));
+        assert_eq!(vec![FileId::file(7)], ids("dir1\\dir2\\rep"));
+        assert_eq!("", files.path_of(&files.get_file(FileId::file(8))));
+    }
+
+    #[test]
+    fn refined_search_filters_previous_results() {
+        let mut files = test_data();
+        files.add_file(new_hardlinked_file(7, &[("report.doc", 1)]));
+        files.add_file(new_hardlinked_file(8, &[("report.tmp", 1)]));
+        files.add_file(new_hardlinked_file(9, &[("repair.doc", 2)]));
+
+        let previous = files.search(&Query::parse("rep").unwrap());
+        assert_eq!(3, previous.len());
+        let query = Query::parse("report doc").unwrap();
+        let refined = files.search_within(&query, &previous);
+        assert_eq!(files.search(&query), refined);
+        assert_eq!(vec![LinkId::from(FileId::file(7))], refined);
+    }
+
+    #[test]
+    fn refined_ranked_search_keeps_full_search_order() {
+        let mut files = test_data();
+        files.add_file(new_hardlinked_file(7, &[("restore.rs", 2)]));
+        files.add_file(new_hardlinked_file(8, &[("storage.rs", 1)]));
+        files.add_file(new_hardlinked_file(9, &[("storage.rs", 1)]));
+
+        let previous = files.search(&Query::parse("fuzzy:st").unwrap());
+        let query = Query::parse("fuzzy:sto").unwrap();
+        assert_eq!(files.search(&query), files.search_within(&query, &previous));
+    }
+
+    #[test]
+    fn sorted_results_refine_in_storage_order() {
+        let mut files = test_data();
+        files.add_file(new_hardlinked_file(7, &[("report_b.doc", 1)]));
+        files.add_file(new_hardlinked_file(8, &[("report_a.doc", 1)]));
+        files.add_file(new_hardlinked_file(9, &[("report_c.doc", 2)]));
+
+        let mut previous = files.search(&Query::parse("report").unwrap());
+        files.sort(&mut previous, Sort::new(SortColumn::Name, true));
+        let ids = |links: &[LinkId]| links.iter().map(|l| l.id().id()).collect::<Vec<_>>();
+        assert_eq!(vec![9, 7, 8], ids(&previous));
+
+        let refined = files.search_within(&Query::parse("report doc").unwrap(), &previous);
+        assert_eq!(vec![7, 8, 9], ids(&refined));
+    }
+
+    #[test]
+    fn older_results_are_refined_with_the_changed_files() {
+        let mut files = test_data();
+        files.add_file(new_hardlinked_file(7, &[("report.doc", 1)]));
+        files.add_file(new_hardlinked_file(8, &[("report.tmp", 1)]));
+        let previous = files.search(&Query::parse("rep").unwrap());
+        let version = files.version();
+
+        files.add_file(new_hardlinked_file(9, &[("report_new.doc", 2)]));
+        files.delete_file(FileId::file(7));
+        files.add_file(new_hardlinked_file(8, &[("report.doc", 1)]));
+        assert_eq!(
+            Some(vec![FileId::file(7), FileId::file(8), FileId::file(9)]),
+            files.changed_since(version).map(|changed| {
+                let mut changed = changed.into_iter().collect::<Vec<_>>();
+                changed.sort();
+                changed
+            })
+        );
+
+        let query = Query::parse("report doc").unwrap();
+        let results = files.results_since(&previous, version).unwrap();
+        assert_eq!(files.search(&query), files.search_within(&query, &results));
+        assert_eq!(
+            vec![FileId::file(8), FileId::file(9)],
+            files
+                .search_within(&query, &results)
+                .iter()
+                .map(|link| link.id())
+                .collect::<Vec<_>>()
+        );
+        files.bulk_add(Vec::new());
+        assert_eq!(None, files.changed_since(version));
+    }
+
+    #[test]
+    fn replaced_files_are_searched_again() {
+        let mut files = test_data();
+        files.add_file(new_hardlinked_file(7, &[("report.doc", 1)]));
+        let version = files.version();
+
+        files.replace(test_data());
+        assert!(files.version() > version);
+        assert_eq!(None, files.changed_since(version));
+        assert!(files.search(&Query::parse("report").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn sort_keys_follow_new_names_and_folders() {
+        let mut files = test_data();
+        let keys = files.sort_keys();
+        files.add_file(new_hardlinked_file(7, &[("file1", 1)]));
+        files.delete_file(FileId::file(7));
+        assert!(Arc::ptr_eq(&keys, &files.sort_keys()));
+
+        files.add_file(new_hardlinked_file(8, &[("a_file", 1)]));
+        assert!(!Arc::ptr_eq(&keys, &files.sort_keys()));
+        let keys = files.sort_keys();
+        files.add_file(new_dir("dir3", 3));
+        assert!(!Arc::ptr_eq(&keys, &files.sort_keys()));
+
+        let mut links = files.search(&Query::parse("file").unwrap());
+        files.sort(&mut links, Sort::new(SortColumn::Name, false));
+        let names = links
+            .iter()
+            .map(|link| files.get_link(*link).unwrap().name)
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["a_file", "file0", "file1", "file2"], names);
+    }
+
+    #[test]
+    fn updates_change_the_version() {
+        let mut files = test_data();
+        let version = files.version();
+        files.add_file(new_hardlinked_file(7, &[("report", 1)]));
+        assert_ne!(version, files.version());
+        let version = files.version();
+        files.delete_link(FileId::file(7), FileId::directory(1), "report");
+        assert_ne!(version, files.version());
+    }
+
+    #[test]
+    fn parallel_search_keeps_storage_order() {
+        let mut files = test_data();
+        for id in 10..SEARCH_CHUNK as u32 * 3 {
+            let file = FileData::new(FileId::file(id), FileId::directory(1), 0, FILE, false);
+            files.storage.upsert(file, &[format!("file{}", id % 7)]);
+        }
+
+        let search = files.search(&Query::parse("file3").unwrap());
+        let sequential = files
+            .storage
+            .iter()
+            .filter(|item| item.name == "file3")
+            .map(|item| item.link)
+            .collect::<Vec<_>>();
+        assert!(sequential.len() > SEARCH_CHUNK / 7);
+        assert_eq!(sequential, search);
+    }
+
+    #[test]
+    fn cancelled_search_gives_up() {
+        let files = test_data();
+        let query = Query::parse("file").unwrap();
+
+        let mut progress = |_| panic!("no progress on small searches");
+        assert_eq!(
+            None,
+            files.search_cancellable(&query, None, &|| true, &mut progress)
+        );
+        assert_eq!(
+            Some(files.search(&query)),
+            files.search_cancellable(&query, None, &|| false, &mut progress)
+        );
+    }
+
+    #[test]
+    fn indexed_search_finds_the_same_files() {
+        let mut files = Files::new();
+        files.bulk_add(vec![
+            new_dir("root", 5),
+            new_hardlinked_file(7, &[("Część.doc", 5)]),
+            new_hardlinked_file(8, &[("report.doc", 5), ("raport.doc", 5)]),
+            new_hardlinked_file(9, &[("notes.txt", 5)]),
+        ]);
+        assert!(files.name_index_stats().is_some());
+        files.add_file(new_hardlinked_file(10, &[("report_old.doc", 5)]));
+
+        let ids = |query: &str| {
+            files
+                .search(&Query::parse(query).unwrap())
+                .iter()
+                .map(|link| link.id().id())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![8, 10], ids("report"));
+        assert_eq!(vec![8, 8], ids("rt.doc"));
+        assert_eq!(vec![7], ids("nodiacritics:czesc"));
+        assert_eq!(vec![7], ids("case:Część"));
+        assert!(ids("case:REPORT").is_empty());
+        assert_eq!(vec![8, 10], ids("repo | nothing"));
+    }
+
+    #[test]
+    fn deleted_files_are_not_found() {
+        let mut files = test_data();
+        files.delete_file(FileId::file(1));
+
+        let search = files.search(&Query::parse("file").unwrap());
+        let ids = search.iter().map(|link| link.id()).collect::<Vec<_>>();
+        assert_eq!(vec![FileId::file(0), FileId::file(2)], ids);
+        let compacted = files.compact(&HashSet::new());
+        assert_eq!(1, compacted.records);
+        assert_eq!(search, files.search(&Query::parse("file").unwrap()));
     }
 }
@@ -1,19 +1,34 @@
 use crate::file_listing::files::FileData;
+use crate::file_listing::query::Highlight;
 use crate::windows::utils::ToWide;
-use twoway;
+use time;
+use time::Timespec;
+
+pub const KB: i64 = 1024;
+pub const MB: i64 = 1024 * KB;
+pub const GB: i64 = 1024 * MB;
+const SIZE_UNITS: [(&str, i64); 4] = [("gb", GB), ("mb", MB), ("kb", KB), ("b", 1)];
+const SEC_TO_UNIX_EPOCH: i64 = 11_644_473_600;
+const WINDOWS_TICK: i64 = 10_000_000;
 
 #[derive(Debug)]
 pub struct DisplayItem {
     pub name: String,
     pub path: Vec<u16>,
     pub size: Vec<u16>,
+    pub modified: Vec<u16>,
     pub matches: Vec<Match>,
     pub flags: u16,
 }
 
 impl DisplayItem {
-    pub fn new(file: &FileData, name: String, path: String, query: &str) -> DisplayItem {
-        let matches = matches(query, &name);
+    pub fn new(
+        file: &FileData,
+        name: String,
+        path: String,
+        highlights: &[Highlight],
+    ) -> DisplayItem {
+        let matches = matches(highlights, &path, &name);
         let size = if file.is_directory() {
             "".to_wide_null()
         } else {
@@ -23,6 +38,7 @@ impl DisplayItem {
             name,
             path: path.to_wide_null(),
             size,
+            modified: pretty_date(file.times().modified).to_wide_null(),
             matches,
             flags: file.flags(),
         }
@@ -33,10 +49,10 @@ impl DisplayItem {
 }
 
 fn pretty_size(bytes_size: i64) -> String {
-    let kb = if bytes_size % 1024 != 0 {
-        (bytes_size / 1024) + 1
+    let kb = if bytes_size % KB != 0 {
+        (bytes_size / KB) + 1
     } else {
-        bytes_size / 1024
+        bytes_size / KB
     };
     let mut result = kb.to_string();
     let len = result.len();
@@ -50,6 +66,40 @@ fn pretty_size(bytes_size: i64) -> String {
     result
 }
 
+pub fn parse_size(text: &str) -> Option<i64> {
+    let text = text.trim().to_lowercase();
+    let (number, unit) = SIZE_UNITS
+        .iter()
+        .find(|(suffix, _)| text.ends_with(suffix))
+        .map(|(suffix, unit)| (&text[..text.len() - suffix.len()], *unit))
+        .unwrap_or((&text, 1));
+    let bytes = match number.trim().parse::<f64>() {
+        Ok(value) if value >= 0.0 => (value * unit as f64).round(),
+        _ => return None,
+    };
+    // `as` would saturate infinities and sizes past i64 instead of failing
+    if bytes.is_finite() && bytes < i64::max_value() as f64 {
+        Some(bytes as i64)
+    } else {
+        None
+    }
+}
+
+fn filetime_to_timespec(filetime: i64) -> Timespec {
+    let nanos = (filetime % WINDOWS_TICK) * 100;
+    Timespec::new(filetime / WINDOWS_TICK - SEC_TO_UNIX_EPOCH, nanos as i32)
+}
+
+fn pretty_date(filetime: i64) -> String {
+    if filetime == 0 {
+        return String::new();
+    }
+    time::at(filetime_to_timespec(filetime))
+        .strftime("%Y-%m-%d %H:%M")
+        .map(|date| date.to_string())
+        .unwrap_or_default()
+}
+
 #[derive(Debug)]
 pub struct Match {
     pub matched: bool,
@@ -71,19 +121,30 @@ impl Match {
     }
 }
 
-pub fn matches(needle: &str, haystack: &str) -> Vec<Match> {
-    let mut result = Vec::new();
+pub fn matches(highlights: &[Highlight], path: &str, haystack: &str) -> Vec<Match> {
+    let mut ranges = highlights
+        .iter()
+        .flat_map(|h| h.ranges(path, haystack))
+        .filter(|(start, end)| start < end)
+        .collect::<Vec<_>>();
+    ranges.sort();
 
+    let mut result = Vec::new();
     let mut curr_pos = 0;
-    if needle.len() > 0 {
-        while let Some(mut next_pos) = twoway::find_str(&haystack[curr_pos..], &needle) {
-            next_pos += curr_pos;
-            if next_pos > curr_pos {
-                result.push(Match::unmatched(&haystack[curr_pos..next_pos]));
+    let mut iter = ranges.into_iter().peekable();
+    while let Some((start, mut end)) = iter.next() {
+        while let Some(&(next_start, next_end)) = iter.peek() {
+            if next_start > end {
+                break;
             }
-            curr_pos = next_pos + needle.len();
-            result.push(Match::matched(&haystack[next_pos..curr_pos]));
+            end = end.max(next_end);
+            iter.next();
         }
+        if start > curr_pos {
+            result.push(Match::unmatched(&haystack[curr_pos..start]));
+        }
+        result.push(Match::matched(&haystack[start..end]));
+        curr_pos = end;
     }
     if curr_pos != haystack.len() {
         result.push(Match::unmatched(&haystack[curr_pos..haystack.len()]));
@@ -94,12 +155,16 @@ pub fn matches(needle: &str, haystack: &str) -> Vec<Match> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::file_listing::query::Query;
+
+    fn highlights(query: &str) -> Vec<Highlight> {
+        Query::parse(query).unwrap().highlights()
+    }
 
     #[test]
     fn test() {
         let haystack = "Część 1 -Część 2";
-        let needle = "Czę";
-        let matches = matches(needle, haystack);
+        let matches = matches(&highlights("Czę"), "", haystack);
         let expected = ["Czę", "ść 1 -", "Czę", "ść 2"];
         for x in 0..matches.len() {
             let m = &matches[x];
@@ -108,6 +173,51 @@ mod tests {
         assert_eq!(matches.len(), expected.len());
     }
 
+    #[test]
+    fn overlapping_terms_are_merged() {
+        let matches = matches(
+            &highlights("report port_2 final"),
+            "",
+            "report_2024_final.doc",
+        );
+        let expected = [
+            (true, "report_2"),
+            (false, "024_"),
+            (true, "final"),
+            (false, ".doc"),
+        ];
+        assert_eq!(matches.len(), expected.len());
+        for (m, (matched, text)) in matches.iter().zip(expected.iter()) {
+            assert_eq!(*matched, m.matched);
+            assert_eq!(text.encode_utf16().collect::<Vec<_>>(), m.text);
+        }
+    }
+
+    #[test]
+    fn folded_matches_keep_original_text() {
+        let matches = matches(&highlights("nodiacritics:CZESC"), "", "Część 1 -część 2");
+        let expected = ["Część", " 1 -", "część", " 2"];
+        assert_eq!(matches.len(), expected.len());
+        for (m, text) in matches.iter().zip(expected.iter()) {
+            assert_eq!(text.encode_utf16().collect::<Vec<_>>(), m.text);
+        }
+    }
+
+    #[test]
+    fn fuzzy_matches_single_characters() {
+        let matches = matches(
+            &highlights("fuzzy:flsto"),
+            "src\\file_listing\\",
+            "store.rs",
+        );
+        let expected = [(true, "sto"), (false, "re.rs")];
+        assert_eq!(matches.len(), expected.len());
+        for (m, (matched, text)) in matches.iter().zip(expected.iter()) {
+            assert_eq!(*matched, m.matched);
+            assert_eq!(text.encode_utf16().collect::<Vec<_>>(), m.text);
+        }
+    }
+
     #[test]
     fn pretty_size_test() {
         assert_eq!(&"1 KB", &pretty_size(1));
@@ -118,4 +228,30 @@ mod tests {
         assert_eq!(&"123.456 KB", &pretty_size(126418944));
         assert_eq!(&"123.456.789 KB", &pretty_size(126419751936));
     }
+
+    #[test]
+    fn parse_size_test() {
+        assert_eq!(Some(42), parse_size("42"));
+        assert_eq!(Some(42), parse_size("42b"));
+        assert_eq!(Some(10 * KB), parse_size("10kb"));
+        assert_eq!(Some(3 * MB / 2), parse_size("1.5MB"));
+        assert_eq!(Some(2 * GB), parse_size("2 gb"));
+        assert_eq!(None, parse_size("mb"));
+        assert_eq!(None, parse_size("-1kb"));
+        assert_eq!(None, parse_size("10tb"));
+        assert_eq!(None, parse_size("inf"));
+        assert_eq!(None, parse_size("1e30kb"));
+    }
+
+    #[test]
+    fn filetime_keeps_sub_second_precision() {
+        let spec = filetime_to_timespec(130903099841610578);
+        assert_eq!(1445836384, spec.sec);
+        assert_eq!(161057800, spec.nsec);
+    }
+
+    #[test]
+    fn missing_date_is_empty() {
+        assert_eq!("", pretty_date(0));
+    }
 }
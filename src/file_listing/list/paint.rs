@@ -43,11 +43,15 @@ impl ItemPaint {
 
     pub fn draw_item(&self, event: Event, items: &HashMap<u32, DisplayItem>) -> DrawResult {
         let draw = event.as_display_info().item;
-        let item = items.get(&(draw.iItem as u32)).unwrap();
+        let item = match items.get(&(draw.iItem as u32)) {
+            Some(item) => item,
+            None => return DrawResult::IGNORE,
+        };
         match draw.iSubItem {
             0 => DrawResult::IGNORE,
             1 => DrawResult::SIMPLE(item.path.as_ptr() as LPWSTR),
             2 => DrawResult::SIMPLE(item.size.as_ptr() as LPWSTR),
+            3 => DrawResult::SIMPLE(item.modified.as_ptr() as LPWSTR),
             _ => unreachable!(),
         }
     }
@@ -58,12 +62,12 @@ impl ItemPaint {
         items: &HashMap<u32, DisplayItem>,
     ) -> CustomDrawResult {
         let custom_draw = event.as_custom_draw();
-        if custom_draw.iSubItem == 0 {
-            let item = items.get(&(custom_draw.nmcd.dwItemSpec as u32)).unwrap();
-            self.draw_name(custom_draw, &item);
-            CustomDrawResult::HANDLED
-        } else {
-            CustomDrawResult::IGNORED
+        match items.get(&(custom_draw.nmcd.dwItemSpec as u32)) {
+            Some(item) if custom_draw.iSubItem == 0 => {
+                self.draw_name(custom_draw, &item);
+                CustomDrawResult::HANDLED
+            }
+            _ => CustomDrawResult::IGNORED,
         }
     }
 
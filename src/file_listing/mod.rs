@@ -1,29 +1,52 @@
 use crate::dispatcher::UiAsyncMessage;
+use crate::errors::failure_to_line;
+use crate::errors::failure_to_string;
+use crate::file_listing::file_entity::FileId;
+use crate::file_listing::file_entity::LinkId;
 use crate::file_listing::files::Files;
 use crate::file_listing::list::item::DisplayItem;
 use crate::file_listing::list::paint::ItemPaint;
+use crate::file_listing::query::Query;
+use crate::file_listing::sort::Sort;
 use crate::file_listing::state::FilesState;
 use crate::file_listing::FilesMsg::ChangeJournal;
+use crate::file_listing::FilesMsg::Compact;
+use crate::file_listing::FilesMsg::JournalError;
+use crate::file_listing::FilesMsg::SaveSnapshot;
 use crate::gui::event::Event;
 use crate::ntfs::change_journal;
+use crate::ntfs::change_journal::JournalPosition;
 use crate::ntfs::change_journal::UsnChange;
+use crate::ntfs::parse_operation;
 use crate::plugin::CustomDrawResult;
 use crate::plugin::DrawResult;
 use crate::plugin::Plugin;
 use crate::plugin::PluginState;
 use crate::plugin::State;
+use crate::store::Store;
 use crossbeam_channel as channel;
 use failure::Error;
 use slog::Logger;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::sync::RwLock;
+use std::sync::Weak;
 use std::thread;
+use std::time::Duration;
 use std::time::Instant;
 
 pub mod file_entity;
 pub mod files;
 mod list;
+mod query;
+pub mod snapshot;
+pub mod sort;
 mod state;
 mod storage;
+mod trigram_index;
+
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(10 * 60);
 
 pub struct FileListing(RwLock<Inner>);
 
@@ -31,23 +54,92 @@ struct Inner {
     logger: Logger,
     files: Files,
     item_paint: ItemPaint,
+    live_results: Mutex<Vec<Weak<Vec<LinkId>>>>,
+    initial_sort: Option<Sort>,
+    store: Store,
+    volume: String,
+    // version of the files in the last snapshot written
+    saved_version: Mutex<u64>,
+    sender: channel::Sender<UiAsyncMessage>,
 }
 
 unsafe impl Sync for Inner {}
 
+impl Inner {
+    /// Shares results handed out in a state, their files survive compactions while any state
+    /// holding them is alive.
+    fn track(&self, items: Vec<LinkId>) -> Arc<Vec<LinkId>> {
+        let items = Arc::new(items);
+        let mut live_results = self.live_results.lock().unwrap();
+        live_results.retain(|results| results.upgrade().is_some());
+        live_results.push(Arc::downgrade(&items));
+        items
+    }
+
+    fn compact(&mut self) {
+        let now = Instant::now();
+        let keep = self.live_files();
+        let stats = self.files.compact(&keep);
+        info!(self.logger, "compaction"; "records" => stats.records, "names" => stats.names,
+            "kept" => keep.len(), "time(ms)" => millis_since(now));
+    }
+
+    fn save_snapshot(&self) {
+        let mut saved_version = self.saved_version.lock().unwrap();
+        if *saved_version == self.files.version() {
+            return;
+        }
+        let now = Instant::now();
+        match self.store.save_snapshot(&self.files) {
+            Ok(()) => {
+                *saved_version = self.files.version();
+                info!(self.logger, "snapshot saved"; "file" => self.store.snapshot_path().to_str(),
+                    "time(ms)" => millis_since(now));
+            }
+            Err(e) => {
+                error!(self.logger, "snapshot not saved"; "file" => self.store.snapshot_path().to_str(),
+                    "error" => failure_to_string(e));
+            }
+        }
+    }
+
+    fn live_files(&self) -> HashSet<FileId> {
+        let live_results = self.live_results.lock().unwrap();
+        live_results
+            .iter()
+            .filter_map(|results| results.upgrade())
+            .flat_map(|results| results.iter().map(|link| link.id()).collect::<Vec<_>>())
+            .collect()
+    }
+}
+
 impl FileListing {
     pub fn create(
         files: Files,
         sender: channel::Sender<UiAsyncMessage>,
+        initial_sort: Option<Sort>,
+        store: Store,
+        volume: String,
         parent_logger: &Logger,
     ) -> Self {
         let logger = parent_logger.new(o!("type" =>"files"));
+        if let Some(stats) = files.name_index_stats() {
+            info!(logger, "name index"; "trigrams" => stats.trigrams, "postings" => stats.postings,
+                "memory(kb)" => stats.memory / 1024, "time(ms)" => millis(stats.build_time));
+        }
         let item_paint = ItemPaint::create();
-        run_change_journal(sender).unwrap();
+        run_change_journal(sender.clone(), volume.clone(), files.journal_position()).unwrap();
+        run_snapshot_timer(sender.clone()).unwrap();
         let inner = Inner {
+            saved_version: Mutex::new(files.version()),
+            sender,
             files,
             logger,
             item_paint,
+            live_results: Mutex::new(Vec::new()),
+            initial_sort,
+            store,
+            volume,
         };
         let res = RwLock::new(inner);
         FileListing(res)
@@ -55,25 +147,81 @@ impl FileListing {
 
     pub fn on_message(&self, msg: FilesMsg) {
         match msg {
-            ChangeJournal(changes) => self.update_files(changes),
+            ChangeJournal(changes, position) => self.update_files(changes, position),
+            Compact => self.compact(),
+            SaveSnapshot => self.save_snapshot(),
+            JournalError(e) => self.rescan(e),
+        }
+    }
+
+    /// The journal stopped at `error`, the files are replaced by a new scan and the journal is
+    /// read again from where that scan started.
+    fn rescan(&self, error: Error) {
+        let now = Instant::now();
+        let (logger, files) = {
+            let inner = self.0.read().unwrap();
+            error!(inner.logger, "change journal not read, rescanning"; "error" => failure_to_string(error));
+            let files = parse_operation::rescan(inner.logger.clone(), &inner.store, &inner.volume);
+            (inner.logger.clone(), files)
+        };
+        let files = match files {
+            Ok(files) => files,
+            Err(e) => {
+                error!(logger, "rescan failed, files are not updated anymore"; "error" => failure_to_string(e));
+                return;
+            }
+        };
+        let inner: &mut Inner = &mut *self.0.write().unwrap();
+        let position = files.journal_position();
+        inner.files.replace(files);
+        *inner.saved_version.lock().unwrap() = inner.files.version();
+        if let Err(e) = run_change_journal(inner.sender.clone(), inner.volume.clone(), position) {
+            error!(logger, "change journal not read"; "error" => failure_to_string(e));
         }
+        info!(logger, "rescan"; "time(ms)" => millis_since(now));
+    }
+
+    fn save_snapshot(&self) {
+        let inner = self.0.read().unwrap();
+        inner.save_snapshot();
+    }
+
+    fn compact(&self) {
+        let inner: &mut Inner = &mut *self.0.write().unwrap();
+        inner.compact();
     }
 
-    fn update_files(&self, changes: Vec<UsnChange>) {
+    fn update_files(&self, changes: Vec<UsnChange>, position: JournalPosition) {
         let inner: &mut Inner = &mut *self.0.write().unwrap();
         for change in changes {
             match change {
-                UsnChange::DELETE(id) => inner.files.delete_file(id.into()),
+                UsnChange::DELETE(record) => {
+                    let parent_id = FileId::directory(record.parent_fr_number as u32);
+                    let name = record.name.clone();
+                    inner.files.delete_link(record.into(), parent_id, &name)
+                }
                 UsnChange::UPDATE(file) => inner.files.update_file(file.into()),
                 UsnChange::NEW(file) => inner.files.add_file(file.into()),
                 UsnChange::IGNORE => {}
             }
         }
+        inner.files.set_journal_position(position);
+        if inner.files.needs_compaction() {
+            inner.compact();
+        }
     }
 }
 
 pub enum FilesMsg {
-    ChangeJournal(Vec<UsnChange>),
+    /// Changes read from the journal and the position after them.
+    ChangeJournal(Vec<UsnChange>, JournalPosition),
+    /// Drops deleted files and unused names, done anyway once tombstones pile up.
+    Compact,
+    /// Writes the files to the snapshot if they changed since the last one, done on exit and
+    /// every `SNAPSHOT_INTERVAL`.
+    SaveSnapshot,
+    /// The journal can't be read anymore, the volume is scanned again.
+    JournalError(Error),
 }
 
 impl Plugin for FileListing {
@@ -90,56 +238,154 @@ impl Plugin for FileListing {
     }
 
     fn prepare_item(&self, item_id: usize, state: &mut State) {
-        let query = state.query().to_string();
         let inner = self.0.read().unwrap();
         let plugin_state = state.plugin_state_mut::<FilesState>().unwrap();
-        let file = plugin_state
+        let highlights = plugin_state.highlights().to_vec();
+        // results older than a rescan can point to files it didn't find, they are searched again
+        let file = match plugin_state
             .file_in_current_search(item_id)
-            .map(|file_id| inner.files.get_file(file_id))
-            .unwrap();
-        let path = inner.files.path_of(file.data);
+            .and_then(|link| inner.files.get_link(*link))
+        {
+            Some(file) => file,
+            None => return,
+        };
+        let path = inner.files.path_of(&file);
         plugin_state.item_cache_mut().insert(
             item_id as u32,
-            DisplayItem::new(file.data, file.name.to_string(), path, &query),
+            DisplayItem::new(file.data, file.name.to_string(), path, &highlights),
         );
     }
 
-    fn handle_message(&self, msg: &str, _prev_state: &State) -> State {
+    fn handle_message(
+        &self,
+        msg: &str,
+        prev_state: &State,
+        is_stale: &(dyn Fn() -> bool + Sync),
+        progress: &mut dyn FnMut(State),
+    ) -> Option<State> {
         let now = Instant::now();
         let inner = self.0.read().unwrap();
-        let items = {
-            //            if !inner.last_search.is_empty() && msg.starts_with(&inner.last_search) {
-            //                inner.files.search_by_name(&msg, Some(&inner.items_current_search))
-            //            } else {
-            inner.files.search_by_name(msg, None)
-            //            }
+        let query = match Query::parse(msg) {
+            Ok(query) => query,
+            Err(e) => {
+                let error = failure_to_line(&e);
+                warn!(inner.logger, "invalid query"; "query" => msg, "error" => failure_to_string(e));
+                let files_state = FilesState::empty(sort_of(prev_state));
+                return Some(State::with_error(msg, error, Box::new(files_state)));
+            }
+        };
+        let sort = sort_of(prev_state);
+        let version = inner.files.version();
+        let previous = prev_state
+            .plugin_state::<FilesState>()
+            .and_then(|state| state.results_to_refine(&query))
+            .and_then(|(results, since)| inner.files.results_since(results, since));
+        let refined = previous.is_some();
+        let mut partial = |mut items: Vec<LinkId>| {
+            if let Some(sort) = sort {
+                inner.files.sort(&mut items, sort);
+            }
+            let count = items.len();
+            let files_state = FilesState::new(inner.track(items), query.clone(), version, sort);
+            progress(State::searching(msg, count, Box::new(files_state)));
         };
+        let mut items = match inner.files.search_cancellable(
+            &query,
+            previous.as_ref().map(|p| &p[..]),
+            is_stale,
+            &mut partial,
+        ) {
+            Some(items) => items,
+            None => {
+                info!(inner.logger, "handle_message cancelled"; "query" => msg, "time(ms)" => millis_since(now));
+                return None;
+            }
+        };
+        if let Some(sort) = sort {
+            inner.files.sort(&mut items, sort);
+        }
         let count = items.len();
-        let files_state = Box::new(FilesState::new(items));
-        info!(inner.logger, "handle_message"; "query" => msg, "time(ms)" => millis_since(now));
-        State::new(msg, count, files_state)
+        let files_state = Box::new(FilesState::new(inner.track(items), query, version, sort));
+        info!(inner.logger, "handle_message"; "query" => msg, "refined" => refined, "time(ms)" => millis_since(now));
+        Some(State::new(msg, count, files_state))
+    }
+
+    fn sort(&self, sort: Option<Sort>, state: &State) -> State {
+        let now = Instant::now();
+        let inner = self.0.read().unwrap();
+        let files_state = state.plugin_state::<FilesState>().unwrap();
+        let mut items = files_state.results().to_vec();
+        match (sort, files_state.query()) {
+            (Some(sort), _) => inner.files.sort(&mut items, sort),
+            // searching the results again brings back the storage or rank order
+            (None, Some(query)) => items = inner.files.search_within(query, &items),
+            (None, None) => {}
+        }
+        let count = items.len();
+        let files_state = Box::new(files_state.sorted(inner.track(items), sort));
+        info!(inner.logger, "sort"; "sort" => ?sort, "count" => count, "time(ms)" => millis_since(now));
+        State::new(state.query(), count, files_state)
     }
 
     fn default_plugin_state(&self) -> Box<dyn PluginState> {
-        Box::new(FilesState::default())
+        let inner = self.0.read().unwrap();
+        Box::new(FilesState::empty(inner.initial_sort))
     }
 }
 
+fn sort_of(state: &State) -> Option<Sort> {
+    state
+        .plugin_state::<FilesState>()
+        .and_then(|state| state.sort())
+}
+
 fn millis_since(before: Instant) -> u32 {
-    let now = Instant::now().duration_since(before);
-    now.as_secs() as u32 * 1000 + now.subsec_millis()
+    millis(Instant::now().duration_since(before))
 }
 
-pub fn run_change_journal(sender: channel::Sender<UiAsyncMessage>) -> Result<(), Error> {
+fn millis(duration: Duration) -> u32 {
+    duration.as_secs() as u32 * 1000 + duration.subsec_millis()
+}
+
+/// Reads the journal of `volume` from `position` on, the files were loaded up to there. The
+/// thread ends on the first error, sending it instead.
+pub fn run_change_journal(
+    sender: channel::Sender<UiAsyncMessage>,
+    volume: String,
+    position: JournalPosition,
+) -> Result<(), Error> {
     thread::Builder::new()
         .name("read journal".to_string())
         .spawn(move || {
-            let volume_path = "\\\\.\\C:";
-            let mut journal = change_journal::UsnJournal::new(volume_path).unwrap();
-            loop {
-                let changes = journal.get_new_changes().unwrap();
-                sender.send(UiAsyncMessage::Files(FilesMsg::ChangeJournal(changes)));
+            if let Err(e) = read_journal(&sender, &volume, position) {
+                sender.send(UiAsyncMessage::Files(FilesMsg::JournalError(e)));
             }
         })?;
     Ok(())
 }
+
+fn read_journal(
+    sender: &channel::Sender<UiAsyncMessage>,
+    volume: &str,
+    position: JournalPosition,
+) -> Result<(), Error> {
+    let mut journal = change_journal::UsnJournal::new(volume)?;
+    journal.resume_from(position)?;
+    loop {
+        let changes = journal.get_new_changes()?;
+        let position = journal.position();
+        sender.send(UiAsyncMessage::Files(FilesMsg::ChangeJournal(
+            changes, position,
+        )));
+    }
+}
+
+fn run_snapshot_timer(sender: channel::Sender<UiAsyncMessage>) -> Result<(), Error> {
+    thread::Builder::new()
+        .name("snapshot timer".to_string())
+        .spawn(move || loop {
+            thread::sleep(SNAPSHOT_INTERVAL);
+            sender.send(UiAsyncMessage::Files(FilesMsg::SaveSnapshot));
+        })?;
+    Ok(())
+}
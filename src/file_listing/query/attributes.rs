@@ -0,0 +1,61 @@
+use crate::errors::MyErrorKind::InvalidQuery;
+use crate::ntfs::attributes::FileAttributes;
+use failure::Error;
+
+/// Files having every attribute of `attrib:`, given by their letters as Explorer shows them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AttributeFilter {
+    required: FileAttributes,
+}
+
+impl AttributeFilter {
+    pub fn parse(value: &str) -> Result<AttributeFilter, Error> {
+        if value.is_empty() {
+            Err(InvalidQuery("missing attributes"))?
+        }
+        let mut required = FileAttributes::empty();
+        for c in value.chars() {
+            required |= match c.to_ascii_lowercase() {
+                'r' => FileAttributes::READONLY,
+                'h' => FileAttributes::HIDDEN,
+                's' => FileAttributes::SYSTEM,
+                'a' => FileAttributes::ARCHIVE,
+                'p' => FileAttributes::SPARSE_FILE,
+                'l' => FileAttributes::REPARSE_POINT,
+                'c' => FileAttributes::COMPRESSED,
+                'e' => FileAttributes::ENCRYPTED,
+                _ => Err(InvalidQuery("invalid attribute"))?,
+            };
+        }
+        Ok(AttributeFilter { required })
+    }
+
+    pub fn matches(&self, attributes: FileAttributes) -> bool {
+        attributes.contains(self.required)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(value: &str) -> AttributeFilter {
+        AttributeFilter::parse(value).unwrap()
+    }
+
+    #[test]
+    fn every_attribute_is_required() {
+        let hidden_system = FileAttributes::HIDDEN | FileAttributes::SYSTEM;
+        assert!(filter("h").matches(hidden_system));
+        assert!(filter("HS").matches(hidden_system));
+        assert!(!filter("hr").matches(hidden_system));
+        assert!(!filter("a").matches(FileAttributes::empty()));
+    }
+
+    #[test]
+    fn invalid_attributes() {
+        for value in &["", "x", "h1"] {
+            assert!(AttributeFilter::parse(value).is_err());
+        }
+    }
+}
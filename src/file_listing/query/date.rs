@@ -0,0 +1,262 @@
+use crate::errors::MyErrorKind::InvalidQuery;
+use crate::file_listing::file_entity::FileTimes;
+use failure::Error;
+use failure::ResultExt;
+use time;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+const SEC_TO_UNIX_EPOCH: i64 = 11_644_473_600;
+const WINDOWS_TICK: i64 = 10_000_000;
+// years a FILETIME can hold, later ones overflow it
+const MIN_YEAR: i64 = 1601;
+const MAX_YEAR: i64 = 30_827;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DateField {
+    Modified,
+    Created,
+    Accessed,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Clock {
+    today: i64,
+    utc_offset: i64,
+}
+
+impl Clock {
+    pub fn now() -> Clock {
+        let now = time::now();
+        let utc_offset = i64::from(now.tm_utcoff);
+        let local = now.to_timespec().sec + utc_offset;
+        Clock::new(local / SECONDS_PER_DAY, utc_offset)
+    }
+
+    pub fn new(today: i64, utc_offset: i64) -> Clock {
+        Clock { today, utc_offset }
+    }
+
+    fn filetime(self, day: i64) -> i64 {
+        (day * SECONDS_PER_DAY - self.utc_offset + SEC_TO_UNIX_EPOCH) * WINDOWS_TICK
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DateFilter {
+    field: DateField,
+    from: Option<i64>,
+    to: Option<i64>,
+}
+
+impl DateFilter {
+    pub fn parse(field: DateField, value: &str, clock: Clock) -> Result<DateFilter, Error> {
+        let (from, to) = if value.starts_with(">=") {
+            (Some(period(&value[2..], clock)?.0), None)
+        } else if value.starts_with('>') {
+            (Some(period(&value[1..], clock)?.1), None)
+        } else if value.starts_with("<=") {
+            (None, Some(period(&value[2..], clock)?.1))
+        } else if value.starts_with('<') {
+            (None, Some(period(&value[1..], clock)?.0))
+        } else if let Some(pos) = value.find("..") {
+            let from = period(&value[..pos], clock)?.0;
+            let to = period(&value[pos + 2..], clock)?.1;
+            (Some(from), Some(to))
+        } else {
+            let (from, to) = period(value, clock)?;
+            (Some(from), Some(to))
+        };
+        Ok(DateFilter {
+            field,
+            from: from.map(|day| clock.filetime(day)),
+            to: to.map(|day| clock.filetime(day)),
+        })
+    }
+
+    pub fn matches(&self, times: &FileTimes) -> bool {
+        let time = match self.field {
+            DateField::Modified => times.modified,
+            DateField::Created => times.created,
+            DateField::Accessed => times.accessed,
+        };
+        self.from.map_or(true, |from| time >= from) && self.to.map_or(true, |to| time < to)
+    }
+}
+
+fn period(value: &str, clock: Clock) -> Result<(i64, i64), Error> {
+    let today = clock.today;
+    let week = today - (today + 3) % 7;
+    let (year, month, _) = civil_from_days(today);
+    let period = match value {
+        "today" => (today, today + 1),
+        "yesterday" => (today - 1, today),
+        "thisweek" => (week, week + 7),
+        "lastweek" => (week - 7, week),
+        "thismonth" => month_period(year, month),
+        "lastmonth" if month == 1 => month_period(year - 1, 12),
+        "lastmonth" => month_period(year, month - 1),
+        "thisyear" => year_period(year),
+        "lastyear" => year_period(year - 1),
+        _ => date_period(value)?,
+    };
+    Ok(period)
+}
+
+fn date_period(value: &str) -> Result<(i64, i64), Error> {
+    let parts = value
+        .split('-')
+        .map(|part| part.parse::<i64>())
+        .collect::<Result<Vec<i64>, _>>()
+        .context(InvalidQuery("invalid date"))?;
+    match parts.first() {
+        Some(year) if *year < MIN_YEAR || *year > MAX_YEAR => Err(InvalidQuery("invalid year"))?,
+        _ => {}
+    }
+    match parts.as_slice() {
+        [year] => Ok(year_period(*year)),
+        [year, month] if *month >= 1 && *month <= 12 => Ok(month_period(*year, *month)),
+        [year, month, day] if *month >= 1 && *month <= 12 => {
+            let (first, last) = month_period(*year, *month);
+            let start = first + day - 1;
+            if *day < 1 || start >= last {
+                Err(InvalidQuery("invalid day of month"))?
+            }
+            Ok((start, start + 1))
+        }
+        _ => Err(InvalidQuery("invalid date"))?,
+    }
+}
+
+fn year_period(year: i64) -> (i64, i64) {
+    (days_from_civil(year, 1, 1), days_from_civil(year + 1, 1, 1))
+}
+
+fn month_period(year: i64, month: i64) -> (i64, i64) {
+    let next = if month == 12 {
+        days_from_civil(year + 1, 1, 1)
+    } else {
+        days_from_civil(year, month + 1, 1)
+    };
+    (days_from_civil(year, month, 1), next)
+}
+
+// http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = if days >= 0 { days } else { days - 146_096 } / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-03-14, a thursday
+    const TODAY: i64 = 19796;
+
+    fn clock() -> Clock {
+        Clock::new(TODAY, 0)
+    }
+
+    fn modified(year: i64, month: i64, day: i64) -> FileTimes {
+        let mut times = FileTimes::default();
+        times.modified = clock().filetime(days_from_civil(year, month, day));
+        times
+    }
+
+    fn filter(value: &str) -> DateFilter {
+        DateFilter::parse(DateField::Modified, value, clock()).unwrap()
+    }
+
+    #[test]
+    fn civil_dates_round_trip() {
+        assert_eq!(0, days_from_civil(1970, 1, 1));
+        assert_eq!(TODAY, days_from_civil(2024, 3, 14));
+        assert_eq!((2024, 2, 29), civil_from_days(days_from_civil(2024, 2, 29)));
+        assert_eq!((1601, 1, 1), civil_from_days(days_from_civil(1601, 1, 1)));
+    }
+
+    #[test]
+    fn filetime_of_unix_epoch() {
+        assert_eq!(SEC_TO_UNIX_EPOCH * WINDOWS_TICK, clock().filetime(0));
+        assert_eq!(
+            (SEC_TO_UNIX_EPOCH - 3600) * WINDOWS_TICK,
+            Clock::new(0, 3600).filetime(0)
+        );
+    }
+
+    #[test]
+    fn date_range() {
+        let filter = filter("2024-01-01..2024-03-31");
+        assert!(!filter.matches(&modified(2023, 12, 31)));
+        assert!(filter.matches(&modified(2024, 1, 1)));
+        assert!(filter.matches(&modified(2024, 3, 31)));
+        assert!(!filter.matches(&modified(2024, 4, 1)));
+    }
+
+    #[test]
+    fn comparisons() {
+        assert!(filter(">2023").matches(&modified(2024, 1, 1)));
+        assert!(!filter(">2023").matches(&modified(2023, 12, 31)));
+        assert!(filter(">=2023-06").matches(&modified(2023, 6, 1)));
+        assert!(filter("<2023").matches(&modified(2022, 12, 31)));
+        assert!(!filter("<2023").matches(&modified(2023, 1, 1)));
+        assert!(filter("<=2023").matches(&modified(2023, 12, 31)));
+    }
+
+    #[test]
+    fn relative_periods() {
+        assert!(filter("today").matches(&modified(2024, 3, 14)));
+        assert!(!filter("today").matches(&modified(2024, 3, 13)));
+        assert!(filter("yesterday").matches(&modified(2024, 3, 13)));
+        assert!(filter("thisweek").matches(&modified(2024, 3, 11)));
+        assert!(!filter("thisweek").matches(&modified(2024, 3, 10)));
+        assert!(filter("lastweek").matches(&modified(2024, 3, 4)));
+        assert!(filter("lastweek").matches(&modified(2024, 3, 10)));
+        assert!(filter("thismonth").matches(&modified(2024, 3, 1)));
+        assert!(filter("lastmonth").matches(&modified(2024, 2, 29)));
+        assert!(filter("lastyear").matches(&modified(2023, 7, 1)));
+    }
+
+    #[test]
+    fn years_a_filetime_can_hold() {
+        assert!(filter("1601").matches(&modified(1601, 1, 1)));
+        assert!(filter("30827-12-31").matches(&modified(30827, 12, 31)));
+    }
+
+    #[test]
+    fn invalid_dates() {
+        for value in &[
+            "",
+            "2024-13",
+            "2023-02-29",
+            "soon",
+            "2024..",
+            "x2024",
+            "1600",
+            "30828-01",
+            "9999999999999",
+            "<-9999999999999",
+        ] {
+            assert!(DateFilter::parse(DateField::Created, value, clock()).is_err());
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use unicode_normalization::char::decompose_canonical;
+use unicode_normalization::char::is_combining_mark;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MatchOptions {
+    pub case_sensitive: bool,
+    pub ignore_diacritics: bool,
+}
+
+impl MatchOptions {
+    pub fn is_exact(self) -> bool {
+        self.case_sensitive && !self.ignore_diacritics
+    }
+}
+
+/// Text folded for comparison, remembering which original bytes every folded byte came from
+/// so that match ranges can be reported against the original text.
+pub struct Folded {
+    pub text: String,
+    origins: Vec<(usize, usize)>,
+}
+
+impl Folded {
+    pub fn new(text: &str, options: MatchOptions) -> Folded {
+        let mut folded = String::with_capacity(text.len());
+        let mut origins = Vec::with_capacity(text.len());
+        for (start, c) in text.char_indices() {
+            let origin = (start, start + c.len_utf8());
+            fold_char(c, options, &mut |folded_char| {
+                folded.push(folded_char);
+                for _ in 0..folded_char.len_utf8() {
+                    origins.push(origin);
+                }
+            });
+        }
+        Folded {
+            text: folded,
+            origins,
+        }
+    }
+
+    pub fn original(&self, start: usize, end: usize) -> (usize, usize) {
+        (self.origins[start].0, self.origins[end - 1].1)
+    }
+}
+
+pub fn fold(text: &str, options: MatchOptions) -> String {
+    let mut folded = String::with_capacity(text.len());
+    text.chars()
+        .for_each(|c| fold_char(c, options, &mut |f| folded.push(f)));
+    folded
+}
+
+/// Folds away both case and diacritics. Folding works char by char, so a name matching a
+/// needle under any options contains the needle once both are folded loosely.
+pub fn fold_loosely(text: &str) -> String {
+    fold(
+        text,
+        MatchOptions {
+            case_sensitive: false,
+            ignore_diacritics: true,
+        },
+    )
+}
+
+pub fn fold_char<F: FnMut(char)>(c: char, options: MatchOptions, output: &mut F) {
+    if options.case_sensitive {
+        strip_diacritics(c, options, output);
+    } else {
+        c.to_lowercase()
+            .for_each(|lower| strip_diacritics(lower, options, output));
+    }
+}
+
+fn strip_diacritics<F: FnMut(char)>(c: char, options: MatchOptions, output: &mut F) {
+    if !options.ignore_diacritics || c.is_ascii() {
+        output(c);
+        return;
+    }
+    match c {
+        'ł' => output('l'),
+        'Ł' => output('L'),
+        'đ' => output('d'),
+        'Đ' => output('D'),
+        'ø' => output('o'),
+        'Ø' => output('O'),
+        _ => decompose_canonical(c, |d| {
+            if !is_combining_mark(d) {
+                output(d)
+            }
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INSENSITIVE: MatchOptions = MatchOptions {
+        case_sensitive: false,
+        ignore_diacritics: true,
+    };
+
+    #[test]
+    fn fold_case_and_diacritics() {
+        assert_eq!("readme.md", fold("README.md", MatchOptions::default()));
+        assert_eq!("część", fold("Część", MatchOptions::default()));
+        assert_eq!("czesc", fold("Część", INSENSITIVE));
+        assert_eq!(
+            "Zolw",
+            fold(
+                "Żółw",
+                MatchOptions {
+                    case_sensitive: true,
+                    ignore_diacritics: true,
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn folded_ranges_map_to_original_text() {
+        let folded = Folded::new("Część 1", INSENSITIVE);
+        assert_eq!("czesc 1", folded.text);
+        assert_eq!((0, 4), folded.original(0, 3));
+        assert_eq!((2, 8), folded.original(2, 5));
+        assert_eq!((8, 10), folded.original(5, 7));
+    }
+
+    #[test]
+    fn expanded_lowercase_keeps_whole_char() {
+        let folded = Folded::new("İx", MatchOptions::default());
+        assert_eq!("i\u{307}x", folded.text);
+        assert_eq!((0, 2), folded.original(0, 1));
+        assert_eq!((2, 3), folded.original(3, 4));
+    }
+}
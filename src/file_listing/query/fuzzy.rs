@@ -0,0 +1,255 @@
+use crate::file_listing::query::fold::fold;
+use crate::file_listing::query::fold::Folded;
+use crate::file_listing::query::fold::MatchOptions;
+
+const SCORE_MATCH: i64 = 16;
+const BONUS_CONSECUTIVE: i64 = 8;
+const BONUS_SEPARATOR: i64 = 10;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CAMEL_CASE: i64 = 6;
+const PENALTY_GAP: i64 = 1;
+const PENALTY_DEPTH: i64 = 2;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FuzzyFilter {
+    pattern: Vec<char>,
+    options: MatchOptions,
+}
+
+struct Candidate {
+    c: char,
+    start: usize,
+    end: usize,
+    bonus: i64,
+}
+
+impl FuzzyFilter {
+    pub fn new(pattern: &str, options: MatchOptions) -> FuzzyFilter {
+        FuzzyFilter {
+            pattern: fold(pattern, options).chars().collect(),
+            options,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pattern.is_empty()
+    }
+
+    /// True when every text matched by this filter is also matched by `other`, that is when
+    /// the pattern of `other` is a subsequence of this one.
+    pub fn narrows(&self, other: &FuzzyFilter) -> bool {
+        let mut pattern = self.pattern.iter();
+        self.options == other.options
+            && other
+                .pattern
+                .iter()
+                .all(|o| pattern.any(|p| same_char(*o, *p)))
+    }
+
+    /// Scores `path` followed by `name`, favouring contiguous runs and word boundaries.
+    pub fn score(&self, path: &str, name: &str) -> Option<i64> {
+        let text = path.to_string() + name;
+        let depth = path.chars().filter(|c| is_separator(*c)).count() as i64;
+        self.align(&text)
+            .map(|(score, _)| score - depth * PENALTY_DEPTH)
+    }
+
+    /// Byte ranges of `name` holding the characters matched when scoring `path` and `name`.
+    pub fn find(&self, path: &str, name: &str) -> Vec<(usize, usize)> {
+        let text = path.to_string() + name;
+        match self.align(&text) {
+            Some((_, positions)) => positions
+                .into_iter()
+                .filter(|(start, _)| *start >= path.len())
+                .map(|(start, end)| (start - path.len(), end - path.len()))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn align(&self, text: &str) -> Option<(i64, Vec<(usize, usize)>)> {
+        if self.pattern.is_empty() {
+            return Some((0, Vec::new()));
+        }
+        let candidates = candidates(text, self.options);
+        if !self.is_subsequence(&candidates) {
+            return None;
+        }
+        let (rows, cols) = (self.pattern.len(), candidates.len());
+        // scores[i][j]: best alignment of pattern[..=i] ending with pattern[i] on candidates[j]
+        let mut scores = vec![vec![None; cols]; rows];
+        let mut previous = vec![vec![0; cols]; rows];
+        for (i, p) in self.pattern.iter().enumerate() {
+            // best of scores[i - 1][k] + k over k < j - 1, the gap penalty is applied on use
+            let mut best_gap: Option<(i64, usize)> = None;
+            for (j, candidate) in candidates.iter().enumerate() {
+                if j >= 2 && i > 0 {
+                    if let Some(score) = scores[i - 1][j - 2] {
+                        let value = score + (j - 2) as i64 * PENALTY_GAP;
+                        if best_gap.map_or(true, |(best, _)| value > best) {
+                            best_gap = Some((value, j - 2));
+                        }
+                    }
+                }
+                if !same_char(*p, candidate.c) {
+                    continue;
+                }
+                let matched = SCORE_MATCH + candidate.bonus;
+                if i == 0 {
+                    scores[i][j] = Some(matched);
+                    continue;
+                }
+                let consecutive = if j > 0 {
+                    scores[i - 1][j - 1].map(|s| (s + matched + BONUS_CONSECUTIVE, j - 1))
+                } else {
+                    None
+                };
+                let gap =
+                    best_gap.map(|(best, k)| (best - (j as i64 - 1) * PENALTY_GAP + matched, k));
+                let best = match (consecutive, gap) {
+                    (Some(c), Some(g)) => Some(if c.0 >= g.0 { c } else { g }),
+                    (c, g) => c.or(g),
+                };
+                if let Some((score, k)) = best {
+                    scores[i][j] = Some(score);
+                    previous[i][j] = k;
+                }
+            }
+        }
+        let (score, mut j) = scores[rows - 1]
+            .iter()
+            .enumerate()
+            .filter_map(|(j, score)| score.map(|s| (s, j)))
+            .max_by_key(|(score, j)| (*score, -(*j as i64)))?;
+        let mut positions = Vec::with_capacity(rows);
+        for i in (0..rows).rev() {
+            positions.push((candidates[j].start, candidates[j].end));
+            j = previous[i][j];
+        }
+        positions.reverse();
+        positions.dedup();
+        Some((score, positions))
+    }
+
+    fn is_subsequence(&self, candidates: &[Candidate]) -> bool {
+        let mut pattern = self.pattern.iter().peekable();
+        for candidate in candidates {
+            match pattern.peek() {
+                Some(p) if same_char(**p, candidate.c) => {
+                    pattern.next();
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+        pattern.peek().is_none()
+    }
+}
+
+fn candidates(text: &str, options: MatchOptions) -> Vec<Candidate> {
+    let folded = Folded::new(text, options);
+    folded
+        .text
+        .char_indices()
+        .map(|(pos, c)| {
+            let (start, end) = folded.original(pos, pos + c.len_utf8());
+            Candidate {
+                c,
+                start,
+                end,
+                bonus: bonus(text, start),
+            }
+        })
+        .collect()
+}
+
+fn bonus(text: &str, start: usize) -> i64 {
+    let current = text[start..].chars().next();
+    match (text[..start].chars().next_back(), current) {
+        (None, _) => BONUS_SEPARATOR,
+        (Some(prev), _) if is_separator(prev) => BONUS_SEPARATOR,
+        (Some(prev), _) if prev == '_' || prev == '-' || prev == '.' || prev == ' ' => {
+            BONUS_BOUNDARY
+        }
+        (Some(prev), Some(c)) if prev.is_lowercase() && c.is_uppercase() => BONUS_CAMEL_CASE,
+        _ => 0,
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    c == '\\' || c == '/'
+}
+
+fn same_char(pattern: char, text: char) -> bool {
+    pattern == text || (is_separator(pattern) && is_separator(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fuzzy(pattern: &str) -> FuzzyFilter {
+        FuzzyFilter::new(pattern, MatchOptions::default())
+    }
+
+    #[test]
+    fn subsequence_matches() {
+        assert!(fuzzy("flsto")
+            .score("src\\file_listing\\", "storage.rs")
+            .is_some());
+        assert!(fuzzy("fl/sto")
+            .score("src\\file_listing\\", "storage.rs")
+            .is_some());
+        assert!(fuzzy("FLSTO")
+            .score("src\\file_listing\\", "storage.rs")
+            .is_some());
+        assert!(fuzzy("flsto").score("src\\", "storage.rs").is_none());
+    }
+
+    #[test]
+    fn contiguous_runs_and_boundaries_score_higher() {
+        let filter = fuzzy("stor");
+        let contiguous = filter.score("src\\", "storage.rs").unwrap();
+        let scattered = filter.score("src\\", "s_t_o_r.rs").unwrap();
+        let inner = filter.score("src\\", "restore.rs").unwrap();
+        assert!(contiguous > scattered);
+        assert!(contiguous > inner);
+    }
+
+    #[test]
+    fn deeper_paths_score_lower() {
+        let filter = fuzzy("storage");
+        let shallow = filter.score("src\\", "storage.rs").unwrap();
+        let deep = filter.score("src\\a\\b\\c\\", "storage.rs").unwrap();
+        assert!(shallow > deep);
+    }
+
+    #[test]
+    fn extended_patterns_narrow() {
+        assert!(fuzzy("flsto").narrows(&fuzzy("fls")));
+        assert!(fuzzy("flsto").narrows(&fuzzy("fst")));
+        assert!(fuzzy("fl\\sto").narrows(&fuzzy("fl/s")));
+        assert!(!fuzzy("flsto").narrows(&fuzzy("sf")));
+    }
+
+    #[test]
+    fn camel_case_boundaries() {
+        let filter = fuzzy("fr");
+        let camel = filter.score("", "FileRecord.txt").unwrap();
+        let inner = filter.score("", "Fairy.txt").unwrap();
+        assert!(camel > inner);
+    }
+
+    #[test]
+    fn matched_characters_of_the_name() {
+        let filter = fuzzy("flsto");
+        assert_eq!(
+            vec![(0, 1), (1, 2), (2, 3)],
+            filter.find("src\\file_listing\\", "storage.rs")
+        );
+        assert_eq!(
+            vec![(0, 1), (5, 6), (7, 8)],
+            fuzzy("frc").find("", "file_record.rs")
+        );
+    }
+}
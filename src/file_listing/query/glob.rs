@@ -0,0 +1,376 @@
+use crate::file_listing::query::fold::fold;
+use crate::file_listing::query::fold::fold_char;
+use crate::file_listing::query::fold::Folded;
+use crate::file_listing::query::fold::MatchOptions;
+use std::iter::Peekable;
+use std::mem;
+use std::str::Chars;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Literal(String),
+    AnyChar,
+    AnyRun,
+    AnyPath,
+    Dirs,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Glob {
+    tokens: Vec<Token>,
+    is_path: bool,
+    options: MatchOptions,
+}
+
+impl Glob {
+    pub fn is_glob(text: &str) -> bool {
+        text.contains(|c| c == '*' || c == '?')
+    }
+
+    pub fn new(pattern: &str, options: MatchOptions) -> Glob {
+        let mut tokens = Vec::new();
+        let mut literal = String::new();
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek().map_or(false, |c| is_separator(*c)) {
+                        chars.next();
+                        Token::Dirs
+                    } else {
+                        Token::AnyPath
+                    }
+                }
+                '*' => Token::AnyRun,
+                '?' => Token::AnyChar,
+                '[' => match class(&mut chars, options) {
+                    Some(class) => class,
+                    None => {
+                        literal.push(c);
+                        continue;
+                    }
+                },
+                c => {
+                    literal.push(c);
+                    continue;
+                }
+            };
+            if !literal.is_empty() {
+                let literal = mem::replace(&mut literal, String::new());
+                tokens.push(Token::Literal(fold(&literal, options)));
+            }
+            tokens.push(token);
+        }
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(fold(&literal, options)));
+        }
+        Glob {
+            tokens,
+            is_path: pattern.contains(is_separator),
+            options,
+        }
+    }
+
+    pub fn is_path(&self) -> bool {
+        self.is_path
+    }
+
+    pub fn name_glob(&self) -> Glob {
+        let mut tokens = Vec::new();
+        for token in self.tokens.iter().rev() {
+            match token {
+                Token::Dirs => break,
+                Token::Literal(literal) => match literal.rfind(is_separator) {
+                    Some(pos) => {
+                        if pos + 1 < literal.len() {
+                            tokens.push(Token::Literal(literal[pos + 1..].to_string()));
+                        }
+                        break;
+                    }
+                    None => tokens.push(token.clone()),
+                },
+                token => tokens.push(token.clone()),
+            }
+        }
+        tokens.reverse();
+        Glob {
+            tokens,
+            is_path: false,
+            options: self.options,
+        }
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    /// Byte ranges of `text` matched by the literal parts of the pattern.
+    /// Path globs may start at any directory of `text`, name globs must match it whole.
+    pub fn find(&self, text: &str) -> Option<Vec<(usize, usize)>> {
+        if self.options.is_exact() {
+            return self.find_folded(text);
+        }
+        let folded = Folded::new(text, self.options);
+        let spans = self.find_folded(&folded.text)?;
+        Some(
+            spans
+                .into_iter()
+                .map(|(start, end)| folded.original(start, end))
+                .collect(),
+        )
+    }
+
+    fn find_folded(&self, text: &str) -> Option<Vec<(usize, usize)>> {
+        let mut spans = Vec::new();
+        let mut start = 0;
+        loop {
+            spans.clear();
+            if match_tokens(&self.tokens, text, start, &mut spans) {
+                return Some(spans);
+            }
+            if !self.is_path {
+                return None;
+            }
+            start += text[start..].find(is_separator)? + 1;
+        }
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    c == '\\' || c == '/'
+}
+
+fn class(chars: &mut Peekable<Chars>, options: MatchOptions) -> Option<Token> {
+    let mut iter = chars.clone();
+    let negated = match iter.peek() {
+        Some('!') | Some('^') => {
+            iter.next();
+            true
+        }
+        _ => false,
+    };
+    let mut ranges = Vec::new();
+    loop {
+        let c = iter.next()?;
+        if c == ']' && !ranges.is_empty() {
+            break;
+        }
+        if iter.peek() == Some(&'-') {
+            let mut ahead = iter.clone();
+            ahead.next();
+            match ahead.next() {
+                Some(end) if end != ']' => {
+                    iter = ahead;
+                    ranges.push((c, end));
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        ranges.push((c, c));
+    }
+    *chars = iter;
+    let ranges = ranges
+        .into_iter()
+        .map(|(from, to)| (fold_single(from, options), fold_single(to, options)))
+        .collect();
+    Some(Token::Class { negated, ranges })
+}
+
+fn fold_single(c: char, options: MatchOptions) -> char {
+    let mut folded = Vec::new();
+    fold_char(c, options, &mut |f| folded.push(f));
+    match folded.as_slice() {
+        [single] => *single,
+        _ => c,
+    }
+}
+
+/// Matches from `start` on, backtracking only to the last star: when the rest of the pattern
+/// fails the star takes one more character and the rest is matched again, once it cannot the
+/// star before it does. A star which ran out of text from some position is never tried from
+/// there again.
+fn match_tokens(
+    tokens: &[Token],
+    text: &str,
+    start: usize,
+    spans: &mut Vec<(usize, usize)>,
+) -> bool {
+    // token of each star, where it starts and ends and the spans before it
+    let mut stars: Vec<(usize, usize, usize, usize)> = Vec::new();
+    // by token and start, only needed once a star gives up with another one before it
+    let mut failed: Vec<bool> = Vec::new();
+    let width = text.len() + 1;
+    let mut current = 0;
+    let mut pos = start;
+    loop {
+        let rest = &text[pos..];
+        let next = match tokens.get(current) {
+            None if pos == text.len() => return true,
+            None => None,
+            Some(Token::Literal(literal)) if starts_with(rest, literal) => {
+                spans.push((pos, pos + literal.len()));
+                Some(pos + literal.len())
+            }
+            Some(Token::Literal(_)) => None,
+            Some(Token::AnyChar) => match rest.chars().next() {
+                Some(c) if !is_separator(c) => Some(pos + c.len_utf8()),
+                _ => None,
+            },
+            Some(Token::Class { negated, ranges }) => match rest.chars().next() {
+                Some(c) if !is_separator(c) => {
+                    let in_class = ranges.iter().any(|(from, to)| *from <= c && c <= *to);
+                    if in_class != *negated {
+                        Some(pos + c.len_utf8())
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            },
+            Some(_) if failed.get(current * width + pos) == Some(&true) => None,
+            Some(_) => {
+                stars.push((current, pos, pos, spans.len()));
+                current += 1;
+                continue;
+            }
+        };
+        if let Some(next) = next {
+            current += 1;
+            pos = next;
+            continue;
+        }
+        loop {
+            let (token, from, end, matched) = match stars.last_mut() {
+                Some(star) => star,
+                None => return false,
+            };
+            if let Some(next) = extend(&tokens[*token], text, *end) {
+                *end = next;
+                spans.truncate(*matched);
+                current = *token + 1;
+                pos = next;
+                break;
+            }
+            let gave_up = *token * width + *from;
+            stars.pop();
+            if stars.is_empty() {
+                return false;
+            }
+            if failed.is_empty() {
+                failed = vec![false; tokens.len() * width];
+            }
+            failed[gave_up] = true;
+        }
+    }
+}
+
+/// Where the text after a star starts once the star takes one more character, or directory.
+fn extend(star: &Token, text: &str, end: usize) -> Option<usize> {
+    match star {
+        Token::Dirs => text[end..].find(is_separator).map(|next| end + next + 1),
+        _ => match text[end..].chars().next() {
+            Some(c) if *star == Token::AnyPath || !is_separator(c) => Some(end + c.len_utf8()),
+            _ => None,
+        },
+    }
+}
+
+fn starts_with(text: &str, literal: &str) -> bool {
+    text.len() >= literal.len()
+        && text
+            .chars()
+            .zip(literal.chars())
+            .all(|(t, l)| t == l || (is_separator(t) && is_separator(l)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str) -> Glob {
+        Glob::new(pattern, MatchOptions::default())
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(glob("*.rs").matches("mod.rs"));
+        assert!(!glob("*.rs").matches("mod.rs.bak"));
+        assert!(glob("report_??.xlsx").matches("report_03.xlsx"));
+        assert!(!glob("report_??.xlsx").matches("report_3.xlsx"));
+        assert!(glob("*ść*").matches("Część 1"));
+    }
+
+    #[test]
+    fn character_classes() {
+        assert!(glob("file[0-9].txt").matches("file7.txt"));
+        assert!(!glob("file[0-9].txt").matches("filex.txt"));
+        assert!(glob("file[!0-9].txt").matches("filex.txt"));
+        assert!(glob("[]a]*").matches("]b"));
+        assert!(glob("[a-*").matches("[a-bc"));
+    }
+
+    #[test]
+    fn path_globs() {
+        let dirs = glob("src\\**\\mod.rs");
+        assert!(dirs.is_path());
+        assert!(dirs.matches("C:\\crate\\src\\mod.rs"));
+        assert!(dirs.matches("C:\\crate\\src\\file_listing\\query\\mod.rs"));
+        assert!(!dirs.matches("C:\\crate\\src\\main.rs"));
+        assert!(!dirs.matches("C:\\crate\\my_src\\mod.rs"));
+        assert!(glob("src/*/mod.rs").matches("C:\\src\\query\\mod.rs"));
+        assert!(!glob("src/*/mod.rs").matches("C:\\src\\a\\b\\mod.rs"));
+        assert!(glob("src\\**.rs").matches("C:\\src\\a\\b\\mod.rs"));
+    }
+
+    #[test]
+    fn spans_cover_literals() {
+        assert_eq!(
+            Some(vec![(0, 7), (9, 14)]),
+            glob("report_??.xlsx").find("report_03.xlsx")
+        );
+        assert_eq!(Some(vec![(3, 6)]), glob("*.rs").find("mod.rs"));
+        assert_eq!(None, glob("*.rs").find("mod.rc"));
+    }
+
+    #[test]
+    fn many_stars_do_not_backtrack_forever() {
+        let text = "a".repeat(100);
+        assert!(!glob(&"*a".repeat(30)).matches(&(text.clone() + "b")));
+        assert!(glob(&("*a".repeat(30) + "*")).matches(&text));
+        assert!(glob("**\\*a*b").matches("x\\y\\zaqb"));
+        assert!(!glob("*a*b").matches("xa\\b"));
+        assert!(glob("a*\\**b").matches("ax\\y\\zb"));
+    }
+
+    #[test]
+    fn name_glob_of_path() {
+        assert_eq!(glob("mod.rs"), glob("src\\**\\mod.rs").name_glob());
+        assert_eq!(glob("*.rs"), glob("src/*.rs").name_glob());
+        assert_eq!(glob("m*"), glob("a\\b*\\m*").name_glob());
+    }
+
+    #[test]
+    fn folded_matching() {
+        assert!(glob("*.RS").matches("Mod.rs"));
+        assert!(glob("[A-C]*").matches("beta"));
+        let exact = MatchOptions {
+            case_sensitive: true,
+            ignore_diacritics: false,
+        };
+        assert!(!Glob::new("*.RS", exact).matches("mod.rs"));
+        let plain = MatchOptions {
+            case_sensitive: false,
+            ignore_diacritics: true,
+        };
+        assert_eq!(
+            Some(vec![(0, 8)]),
+            Glob::new("czesc*", plain).find("Część 1")
+        );
+    }
+}
@@ -0,0 +1,308 @@
+use crate::file_listing::file_entity::FileId;
+use crate::file_listing::query::attributes::AttributeFilter;
+use crate::file_listing::query::date::Clock;
+use crate::file_listing::query::date::DateFilter;
+use crate::file_listing::query::fold::MatchOptions;
+use crate::file_listing::query::fuzzy::FuzzyFilter;
+use crate::file_listing::query::glob::Glob;
+use crate::file_listing::query::name::NameFilter;
+use crate::file_listing::query::parser::Parser;
+use crate::file_listing::query::path::PathFilter;
+use crate::file_listing::query::path::PathScope;
+use crate::file_listing::query::regex_filter::RegexFilter;
+use crate::file_listing::query::size::SizeFilter;
+use crate::file_listing::storage::StorageItem;
+use crate::file_listing::storage::StorageIter;
+use failure::Error;
+
+pub use self::fold::fold_loosely;
+
+mod attributes;
+mod date;
+mod fold;
+mod fuzzy;
+mod glob;
+mod name;
+mod parser;
+mod path;
+mod regex_filter;
+mod size;
+
+pub trait FileTree {
+    fn items(&self) -> StorageIter;
+    fn get(&self, id: FileId) -> Option<StorageItem>;
+    fn path_of(&self, item: &StorageItem) -> String;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Predicate {
+    And(Vec<Predicate>),
+    Or(Vec<Predicate>),
+    Not(Box<Predicate>),
+    Name(NameFilter),
+    Glob(Glob),
+    Regex(RegexFilter),
+    Fuzzy(FuzzyFilter),
+    Path(PathFilter),
+    Date(DateFilter),
+    Size(SizeFilter),
+    Attributes(AttributeFilter),
+}
+
+impl Predicate {
+    pub fn matches(&self, item: &StorageItem, tree: &dyn FileTree) -> bool {
+        self.score(item, tree).is_some()
+    }
+
+    /// Rank of a matching item, only fuzzy terms contribute to it.
+    pub fn score(&self, item: &StorageItem, tree: &dyn FileTree) -> Option<i64> {
+        let matched = match self {
+            Predicate::And(predicates) => {
+                return predicates.iter().map(|p| p.score(item, tree)).sum();
+            }
+            Predicate::Or(predicates) => {
+                return predicates.iter().filter_map(|p| p.score(item, tree)).max();
+            }
+            Predicate::Not(predicate) => predicate.score(item, tree).is_none(),
+            Predicate::Name(name) => name.matches(item.name),
+            Predicate::Glob(glob) if glob.is_path() => {
+                glob.matches(&(tree.path_of(item) + item.name))
+            }
+            Predicate::Glob(glob) => glob.matches(item.name),
+            Predicate::Regex(regex) if regex.is_path() => {
+                regex.matches(&(tree.path_of(item) + item.name))
+            }
+            Predicate::Regex(regex) => regex.matches(item.name),
+            Predicate::Fuzzy(fuzzy) => return fuzzy.score(&tree.path_of(item), item.name),
+            Predicate::Path(path) => path.matches(item, tree),
+            Predicate::Date(date) => date.matches(&item.data.times()),
+            Predicate::Size(size) => !item.data.is_directory() && size.matches(item.data.size()),
+            Predicate::Attributes(attributes) => attributes.matches(item.data.attributes()),
+        };
+        if matched {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    fn resolve(&mut self, tree: &dyn FileTree) {
+        match self {
+            Predicate::And(predicates) | Predicate::Or(predicates) => {
+                predicates.iter_mut().for_each(|p| p.resolve(tree))
+            }
+            Predicate::Not(predicate) => predicate.resolve(tree),
+            Predicate::Path(path) => path.resolve(tree),
+            _ => {}
+        }
+    }
+
+    fn terms(&self) -> Vec<&Predicate> {
+        match self {
+            Predicate::And(predicates) => predicates.iter().flat_map(|p| p.terms()).collect(),
+            _ => vec![self],
+        }
+    }
+
+    /// True when every item matching this predicate also matches `other`. Only the cheap
+    /// cases are recognised, anything else is reported as not implied.
+    fn implies(&self, other: &Predicate) -> bool {
+        match (self, other) {
+            (_, Predicate::Name(name)) if name.is_empty() => true,
+            (Predicate::Name(name), Predicate::Name(other)) => name.narrows(other),
+            (Predicate::Fuzzy(fuzzy), Predicate::Fuzzy(other)) => fuzzy.narrows(other),
+            _ => self == other,
+        }
+    }
+
+    fn is_ranked(&self) -> bool {
+        match self {
+            Predicate::And(predicates) | Predicate::Or(predicates) => {
+                predicates.iter().any(|p| p.is_ranked())
+            }
+            Predicate::Fuzzy(fuzzy) => !fuzzy.is_empty(),
+            _ => false,
+        }
+    }
+
+    fn positive_highlights(&self, highlights: &mut Vec<Highlight>) {
+        match self {
+            Predicate::And(predicates) | Predicate::Or(predicates) => predicates
+                .iter()
+                .for_each(|p| p.positive_highlights(highlights)),
+            Predicate::Name(name) if !name.is_empty() => {
+                highlights.push(Highlight::Name(name.clone()))
+            }
+            Predicate::Glob(glob) => highlights.push(Highlight::Glob(glob.name_glob())),
+            Predicate::Regex(regex) if !regex.is_path() => {
+                highlights.push(Highlight::Regex(regex.clone()))
+            }
+            Predicate::Fuzzy(fuzzy) if !fuzzy.is_empty() => {
+                highlights.push(Highlight::Fuzzy(fuzzy.clone()))
+            }
+            Predicate::Path(path) if path.scope() == PathScope::Contains => {
+                if !path.name().is_empty() {
+                    highlights.push(Highlight::Name(path.name().clone()))
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Highlight {
+    Name(NameFilter),
+    Glob(Glob),
+    Regex(RegexFilter),
+    Fuzzy(FuzzyFilter),
+}
+
+impl Highlight {
+    pub fn ranges(&self, path: &str, name: &str) -> Vec<(usize, usize)> {
+        match self {
+            Highlight::Name(filter) => filter.find(name),
+            Highlight::Glob(glob) => glob.find(name).unwrap_or_default(),
+            Highlight::Regex(regex) => regex.find(name),
+            Highlight::Fuzzy(fuzzy) => fuzzy.find(path, name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    root: Predicate,
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Query, Error> {
+        Query::parse_with_clock(text, Clock::now())
+    }
+
+    fn parse_with_clock(text: &str, clock: Clock) -> Result<Query, Error> {
+        let root = Parser::new(text, clock).parse()?;
+        Ok(Query { root })
+    }
+
+    pub fn from_name(name: &str) -> Query {
+        Query {
+            root: Predicate::Name(NameFilter::new(name, MatchOptions::default())),
+        }
+    }
+
+    pub fn highlights(&self) -> Vec<Highlight> {
+        let mut highlights = Vec::new();
+        self.root.positive_highlights(&mut highlights);
+        highlights
+    }
+
+    pub fn matches(&self, item: &StorageItem, tree: &dyn FileTree) -> bool {
+        self.root.matches(item, tree)
+    }
+
+    pub fn score(&self, item: &StorageItem, tree: &dyn FileTree) -> Option<i64> {
+        self.root.score(item, tree)
+    }
+
+    /// Looks up the folders referenced by path terms, needed before matching items.
+    pub fn resolve(&mut self, tree: &dyn FileTree) {
+        self.root.resolve(tree)
+    }
+
+    pub fn is_ranked(&self) -> bool {
+        self.root.is_ranked()
+    }
+
+    /// Loosely folded text that the name of every matching item contains.
+    pub fn name_needles(&self) -> Vec<String> {
+        self.root
+            .terms()
+            .into_iter()
+            .filter_map(|term| match term {
+                Predicate::Name(name) => Some(name.loose_needle()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// True when the results of this query are a subset of the results of `previous`, as when
+    /// characters are appended to a term or a term is added, so that it can be run over them.
+    pub fn refines(&self, previous: &Query) -> bool {
+        let terms = self.root.terms();
+        previous
+            .root
+            .terms()
+            .iter()
+            .all(|p| terms.iter().any(|t| t.implies(p)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Query {
+        Query::parse_with_clock(text, Clock::new(19796, 0)).unwrap()
+    }
+
+    fn text(text: &str) -> Highlight {
+        Highlight::Name(NameFilter::new(text, MatchOptions::default()))
+    }
+
+    #[test]
+    fn highlights_skip_negated_terms() {
+        let query = parse("report (draft | \"final version\") -old !(tmp | bak)");
+        assert_eq!(
+            vec![text("report"), text("draft"), text("final version")],
+            query.highlights()
+        );
+    }
+
+    #[test]
+    fn filters_are_not_highlighted() {
+        let query = parse("movie size:>1gb dm:thisyear");
+        assert_eq!(vec![text("movie")], query.highlights());
+    }
+
+    #[test]
+    fn path_globs_highlight_the_name_part() {
+        let query = parse("src\\**\\*.rs");
+        assert_eq!(
+            vec![Highlight::Glob(Glob::new("*.rs", MatchOptions::default()))],
+            query.highlights()
+        );
+        assert_eq!(
+            vec![(4, 7)],
+            query.highlights()[0].ranges("src\\", "main.rs")
+        );
+    }
+
+    #[test]
+    fn refinements() {
+        assert!(parse("repo").refines(&parse("rep")));
+        assert!(parse("report draft").refines(&parse("report")));
+        assert!(parse("draft report").refines(&parse("rep")));
+        assert!(parse("report").refines(&parse("")));
+        assert!(parse("report size:>1mb").refines(&parse("size:>1mb")));
+        assert!(parse("fuzzy:flsto").refines(&parse("fuzzy:fls")));
+        assert!(!parse("rep").refines(&parse("repo")));
+        assert!(!parse("report | draft").refines(&parse("report")));
+        assert!(!parse("report").refines(&parse("report -old")));
+        assert!(!parse("*.rst").refines(&parse("*.rs")));
+    }
+
+    #[test]
+    fn name_needles_of_required_terms() {
+        assert_eq!(
+            vec!["report".to_string(), "czesc".to_string()],
+            parse("case:Report -old (a | b) \"Część\" *.doc").name_needles()
+        );
+        assert!(parse("report | notes").name_needles().is_empty());
+    }
+
+    #[test]
+    fn invalid_filter_fails() {
+        assert!(Query::parse_with_clock("report da:>someday", Clock::new(0, 0)).is_err());
+        assert!(Query::parse_with_clock("report size:big", Clock::new(0, 0)).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+use crate::file_listing::query::fold::fold;
+use crate::file_listing::query::fold::fold_loosely;
+use crate::file_listing::query::fold::Folded;
+use crate::file_listing::query::fold::MatchOptions;
+use twoway;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NameFilter {
+    needle: String,
+    options: MatchOptions,
+}
+
+impl NameFilter {
+    pub fn new(text: &str, options: MatchOptions) -> NameFilter {
+        NameFilter {
+            needle: fold(text, options),
+            options,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.needle.is_empty()
+    }
+
+    pub fn loose_needle(&self) -> String {
+        fold_loosely(&self.needle)
+    }
+
+    pub fn matches(&self, haystack: &str) -> bool {
+        if self.options.is_exact() {
+            twoway::find_str(haystack, &self.needle).is_some()
+        } else if haystack.is_ascii() {
+            self.find_ascii(haystack, 0).is_some()
+        } else {
+            let folded = Folded::new(haystack, self.options);
+            twoway::find_str(&folded.text, &self.needle).is_some()
+        }
+    }
+
+    /// True when every name matched by this filter is also matched by `other`.
+    pub fn narrows(&self, other: &NameFilter) -> bool {
+        self.options == other.options && self.needle.contains(&other.needle)
+    }
+
+    pub fn is_prefix_of(&self, haystack: &str) -> bool {
+        self.compare(haystack, |text, needle| text.starts_with(needle))
+    }
+
+    pub fn is_suffix_of(&self, haystack: &str) -> bool {
+        self.compare(haystack, |text, needle| text.ends_with(needle))
+    }
+
+    pub fn equals(&self, haystack: &str) -> bool {
+        self.compare(haystack, |text, needle| text == needle)
+    }
+
+    fn compare<F: Fn(&[u8], &[u8]) -> bool>(&self, haystack: &str, op: F) -> bool {
+        if self.options.is_exact() {
+            op(haystack.as_bytes(), self.needle.as_bytes())
+        } else if haystack.is_ascii() && !self.options.case_sensitive {
+            op(
+                haystack.to_ascii_lowercase().as_bytes(),
+                self.needle.as_bytes(),
+            )
+        } else {
+            op(
+                fold(haystack, self.options).as_bytes(),
+                self.needle.as_bytes(),
+            )
+        }
+    }
+
+    pub fn find(&self, haystack: &str) -> Vec<(usize, usize)> {
+        if self.needle.is_empty() {
+            return Vec::new();
+        }
+        let mut ranges = Vec::new();
+        if self.options.is_exact() {
+            let mut pos = 0;
+            while let Some(next) = twoway::find_str(&haystack[pos..], &self.needle) {
+                ranges.push((pos + next, pos + next + self.needle.len()));
+                pos += next + self.needle.len();
+            }
+        } else if haystack.is_ascii() {
+            let mut pos = 0;
+            while let Some(start) = self.find_ascii(haystack, pos) {
+                pos = start + self.needle.len();
+                ranges.push((start, pos));
+            }
+        } else {
+            let folded = Folded::new(haystack, self.options);
+            let mut pos = 0;
+            while let Some(next) = twoway::find_str(&folded.text[pos..], &self.needle) {
+                let start = pos + next;
+                pos = start + self.needle.len();
+                ranges.push(folded.original(start, pos));
+            }
+        }
+        ranges
+    }
+
+    // ascii text folds to itself, lowercased unless matching case
+    fn find_ascii(&self, haystack: &str, from: usize) -> Option<usize> {
+        if self.options.case_sensitive {
+            return twoway::find_str(&haystack[from..], &self.needle).map(|pos| from + pos);
+        }
+        let needle = self.needle.as_bytes();
+        if needle.is_empty() {
+            return Some(from);
+        }
+        haystack.as_bytes()[from..]
+            .windows(needle.len())
+            .position(|window| window.eq_ignore_ascii_case(needle))
+            .map(|pos| from + pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(text: &str, case_sensitive: bool, ignore_diacritics: bool) -> NameFilter {
+        NameFilter::new(
+            text,
+            MatchOptions {
+                case_sensitive,
+                ignore_diacritics,
+            },
+        )
+    }
+
+    #[test]
+    fn case_insensitive_by_default() {
+        let filter = NameFilter::new("readme", MatchOptions::default());
+        assert!(filter.matches("README.md"));
+        assert!(filter.matches("ReadMe.txt"));
+        assert!(NameFilter::new("CZĘŚĆ", MatchOptions::default()).matches("Część 1"));
+        assert_eq!(vec![(0, 6)], filter.find("README.md"));
+    }
+
+    #[test]
+    fn case_sensitive() {
+        let filter = filter("README", true, false);
+        assert!(filter.matches("README.md"));
+        assert!(!filter.matches("readme.md"));
+    }
+
+    #[test]
+    fn anchored_comparisons() {
+        let filter = NameFilter::new("Proj", MatchOptions::default());
+        assert!(filter.is_prefix_of("projects"));
+        assert!(filter.is_suffix_of("my_PROJ"));
+        assert!(!filter.equals("projects"));
+        assert!(filter.equals("proj"));
+    }
+
+    #[test]
+    fn longer_needles_narrow_shorter_ones() {
+        let report = NameFilter::new("Report", MatchOptions::default());
+        assert!(report.narrows(&NameFilter::new("rep", MatchOptions::default())));
+        assert!(report.narrows(&NameFilter::new("", MatchOptions::default())));
+        assert!(!report.narrows(&NameFilter::new("reports", MatchOptions::default())));
+        assert!(!report.narrows(&filter("rep", true, false)));
+    }
+
+    #[test]
+    fn diacritics_insensitive() {
+        let filter = filter("czesc", false, true);
+        assert!(filter.matches("Część 1"));
+        assert!(!NameFilter::new("czesc", MatchOptions::default()).matches("Część 1"));
+        assert_eq!(vec![(0, 8), (12, 20)], filter.find("Część 1 -Część 2"));
+    }
+}
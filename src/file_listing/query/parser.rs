@@ -0,0 +1,376 @@
+use crate::file_listing::query::attributes::AttributeFilter;
+use crate::file_listing::query::date::Clock;
+use crate::file_listing::query::date::DateField;
+use crate::file_listing::query::date::DateFilter;
+use crate::file_listing::query::fold::MatchOptions;
+use crate::file_listing::query::fuzzy::FuzzyFilter;
+use crate::file_listing::query::glob::Glob;
+use crate::file_listing::query::name::NameFilter;
+use crate::file_listing::query::path::PathFilter;
+use crate::file_listing::query::path::PathScope;
+use crate::file_listing::query::regex_filter::RegexFilter;
+use crate::file_listing::query::size::SizeFilter;
+use crate::file_listing::query::Predicate;
+use failure::Error;
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Term(String),
+    Phrase(String, String),
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+pub struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    clock: Clock,
+}
+
+impl Parser {
+    pub fn new(text: &str, clock: Clock) -> Parser {
+        Parser {
+            tokens: tokenize(text),
+            pos: 0,
+            clock,
+        }
+    }
+
+    pub fn parse(mut self) -> Result<Predicate, Error> {
+        self.parse_or()
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Predicate, Error> {
+        let mut alternatives = Vec::new();
+        loop {
+            match self.parse_and()? {
+                Predicate::And(ref terms) if terms.is_empty() => {}
+                alternative => alternatives.push(alternative),
+            }
+            if self.peek() != Some(&Token::Or) {
+                break;
+            }
+            self.next();
+        }
+        Ok(match alternatives.len() {
+            0 => Predicate::And(Vec::new()),
+            1 => alternatives.remove(0),
+            _ => Predicate::Or(alternatives),
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Predicate, Error> {
+        let mut terms = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(Token::Or) | Some(Token::Close) => break,
+                _ => {
+                    if let Some(term) = self.parse_unary()? {
+                        terms.push(term);
+                    }
+                }
+            }
+        }
+        Ok(match terms.len() {
+            1 => terms.remove(0),
+            _ => Predicate::And(terms),
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<Option<Predicate>, Error> {
+        match self.next() {
+            Some(Token::Not) => match self.peek() {
+                None | Some(Token::Or) | Some(Token::Close) => Ok(None),
+                _ => Ok(self.parse_unary()?.map(|p| Predicate::Not(Box::new(p)))),
+            },
+            Some(Token::Open) => {
+                let group = self.parse_or()?;
+                if self.peek() == Some(&Token::Close) {
+                    self.next();
+                }
+                Ok(Some(group))
+            }
+            Some(Token::Phrase(prefix, phrase)) => self.term(&prefix, Some(&phrase)).map(Some),
+            Some(Token::Term(term)) => self.term(&term, None).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn term(&self, text: &str, phrase: Option<&str>) -> Result<Predicate, Error> {
+        let mut text = text;
+        let mut options = MatchOptions::default();
+        loop {
+            if text.starts_with("case:") {
+                options.case_sensitive = true;
+                text = &text[5..];
+            } else if text.starts_with("nodiacritics:") {
+                options.ignore_diacritics = true;
+                text = &text[13..];
+            } else {
+                break;
+            }
+        }
+        let (filter, value) = match text.find(':') {
+            Some(pos) => text.split_at(pos + 1),
+            None => ("", text),
+        };
+        let value = value.to_string() + phrase.unwrap_or("");
+        let predicate = match filter {
+            "dm:" => Predicate::Date(DateFilter::parse(DateField::Modified, &value, self.clock)?),
+            "dc:" => Predicate::Date(DateFilter::parse(DateField::Created, &value, self.clock)?),
+            "da:" => Predicate::Date(DateFilter::parse(DateField::Accessed, &value, self.clock)?),
+            "size:" => Predicate::Size(SizeFilter::parse(&value)?),
+            "attrib:" => Predicate::Attributes(AttributeFilter::parse(&value)?),
+            "regex:" => Predicate::Regex(RegexFilter::new(&value, false, options)?),
+            "pathregex:" => Predicate::Regex(RegexFilter::new(&value, true, options)?),
+            "fuzzy:" => Predicate::Fuzzy(FuzzyFilter::new(&value, options)),
+            "parent:" => Predicate::Path(PathFilter::new(&value, PathScope::Children, options)),
+            "infolder:" => {
+                Predicate::Path(PathFilter::new(&value, PathScope::Descendants, options))
+            }
+            _ => {
+                let text = filter.to_string() + &value;
+                if phrase.is_none() && Glob::is_glob(&text) {
+                    Predicate::Glob(Glob::new(&text, options))
+                } else if PathFilter::is_path(&text) {
+                    Predicate::Path(PathFilter::new(&text, PathScope::Contains, options))
+                } else {
+                    Predicate::Name(NameFilter::new(&text, options))
+                }
+            }
+        };
+        Ok(predicate)
+    }
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut depth = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '|' => tokens.push(Token::Or),
+            '!' | '-' => tokens.push(Token::Not),
+            '(' => {
+                depth += 1;
+                tokens.push(Token::Open);
+            }
+            ')' if depth > 0 => {
+                depth -= 1;
+                tokens.push(Token::Close);
+            }
+            '"' => tokens.push(Token::Phrase(String::new(), phrase(&mut chars))),
+            c => {
+                let mut term = c.to_string();
+                term.push_str(&word(&mut chars, depth > 0));
+                if term.ends_with(':') && chars.peek() == Some(&'"') {
+                    chars.next();
+                    tokens.push(Token::Phrase(term, phrase(&mut chars)));
+                } else {
+                    tokens.push(Token::Term(term));
+                }
+            }
+        }
+    }
+    tokens
+}
+
+fn phrase(chars: &mut Peekable<Chars>) -> String {
+    chars.take_while(|c| *c != '"').collect()
+}
+
+fn word(chars: &mut Peekable<Chars>, in_group: bool) -> String {
+    let mut word = String::new();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() || c == '|' || c == '"' || (in_group && c == ')') {
+            break;
+        }
+        word.push(c);
+        chars.next();
+    }
+    word
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_listing::query::Predicate::*;
+
+    fn parse(text: &str) -> Predicate {
+        Parser::new(text, Clock::new(0, 0)).parse().unwrap()
+    }
+
+    fn name(name: &str) -> Predicate {
+        Name(NameFilter::new(name, MatchOptions::default()))
+    }
+
+    #[test]
+    fn tokenize_operators() {
+        assert_eq!(
+            vec![
+                Token::Not,
+                Token::Open,
+                Token::Term("a".to_string()),
+                Token::Or,
+                Token::Phrase(String::new(), "b c".to_string()),
+                Token::Close,
+                Token::Term("d-e(1)".to_string()),
+            ],
+            tokenize("!(a|\"b c\") d-e(1)")
+        );
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        assert_eq!(And(vec![]), parse(""));
+        assert_eq!(And(vec![]), parse("  "));
+    }
+
+    #[test]
+    fn spaces_are_and() {
+        assert_eq!(And(vec![name("my"), name("file")]), parse("my  file"));
+    }
+
+    #[test]
+    fn or_binds_weaker_than_and() {
+        assert_eq!(
+            Or(vec![And(vec![name("a"), name("b")]), name("c")]),
+            parse("a b | c")
+        );
+    }
+
+    #[test]
+    fn not_and_groups() {
+        assert_eq!(
+            And(vec![
+                name("a"),
+                Not(Box::new(Or(vec![name("b"), name("c")]))),
+                Not(Box::new(name("d"))),
+            ]),
+            parse("a !(b | c) -d")
+        );
+    }
+
+    #[test]
+    fn quoted_phrases_are_literal() {
+        assert_eq!(name("a | -b (c)"), parse("\"a | -b (c)\""));
+        assert_eq!(name("unterminated phrase"), parse("\"unterminated phrase"));
+    }
+
+    #[test]
+    fn incomplete_input_is_tolerated() {
+        assert_eq!(name("a"), parse("a |"));
+        assert_eq!(name("a"), parse("a -"));
+        assert_eq!(name("a"), parse("(a"));
+        assert_eq!(name("a)"), parse("a)"));
+    }
+
+    #[test]
+    fn quoted_filter_values() {
+        assert_eq!(
+            vec![
+                Token::Phrase("regex:".to_string(), "a|b c".to_string()),
+                Token::Term("d".to_string())
+            ],
+            tokenize("regex:\"a|b c\" d")
+        );
+    }
+
+    #[test]
+    fn regex_terms() {
+        assert_eq!(
+            Regex(RegexFilter::new("^a.*z$", false, MatchOptions::default()).unwrap()),
+            parse("regex:^a.*z$")
+        );
+        assert_eq!(
+            Regex(RegexFilter::new("src.+mod", true, MatchOptions::default()).unwrap()),
+            parse("pathregex:src.+mod")
+        );
+        assert!(Parser::new("regex:(", Clock::new(0, 0)).parse().is_err());
+    }
+
+    #[test]
+    fn wildcard_terms_are_globs() {
+        assert_eq!(
+            And(vec![
+                Glob(super::Glob::new("*.rs", MatchOptions::default())),
+                name("[1]")
+            ]),
+            parse("*.rs [1]")
+        );
+        assert_eq!(name("*.rs"), parse("\"*.rs\""));
+    }
+
+    #[test]
+    fn match_modifiers() {
+        let exact = MatchOptions {
+            case_sensitive: true,
+            ignore_diacritics: false,
+        };
+        assert_eq!(Name(NameFilter::new("ReadMe", exact)), parse("case:ReadMe"));
+        assert_eq!(
+            Name(NameFilter::new("Final Version", exact)),
+            parse("case:\"Final Version\"")
+        );
+        let plain = MatchOptions {
+            case_sensitive: true,
+            ignore_diacritics: true,
+        };
+        assert_eq!(
+            Glob(super::Glob::new("Cz*", plain)),
+            parse("nodiacritics:case:Cz*")
+        );
+    }
+
+    #[test]
+    fn path_terms() {
+        let path = |text, scope| Path(PathFilter::new(text, scope, MatchOptions::default()));
+        assert_eq!(path("c:\\users", PathScope::Contains), parse("c:\\users"));
+        assert_eq!(
+            path("My Documents/notes", PathScope::Contains),
+            parse("\"My Documents/notes\"")
+        );
+        assert_eq!(
+            And(vec![
+                path("src\\", PathScope::Children),
+                path("docs", PathScope::Descendants)
+            ]),
+            parse("parent:src\\ infolder:docs")
+        );
+    }
+
+    #[test]
+    fn attribute_terms() {
+        assert_eq!(
+            And(vec![
+                Attributes(AttributeFilter::parse("hs").unwrap()),
+                name("log")
+            ]),
+            parse("attrib:hs log")
+        );
+        assert!(Parser::new("attrib:q", Clock::new(0, 0)).parse().is_err());
+    }
+
+    #[test]
+    fn fuzzy_terms() {
+        assert_eq!(
+            Fuzzy(FuzzyFilter::new("flsto", MatchOptions::default())),
+            parse("fuzzy:flsto")
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use crate::file_listing::file_entity::FileId;
+use crate::file_listing::query::fold::MatchOptions;
+use crate::file_listing::query::name::NameFilter;
+use crate::file_listing::query::FileTree;
+use crate::file_listing::storage::StorageItem;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PathScope {
+    /// The full path contains the text.
+    Contains,
+    /// Direct children of the folder.
+    Children,
+    /// Anything below the folder.
+    Descendants,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathFilter {
+    folders: Vec<NameFilter>,
+    name: NameFilter,
+    scope: PathScope,
+    resolved: HashSet<FileId>,
+}
+
+impl PathFilter {
+    pub fn is_path(text: &str) -> bool {
+        text.contains(is_separator)
+    }
+
+    pub fn new(text: &str, scope: PathScope, options: MatchOptions) -> PathFilter {
+        let text = match scope {
+            PathScope::Contains => text,
+            _ => text.trim_end_matches(is_separator),
+        };
+        let mut folders = text
+            .split(is_separator)
+            .map(|component| NameFilter::new(component, options))
+            .collect::<Vec<_>>();
+        let name = folders.pop().unwrap();
+        PathFilter {
+            folders,
+            name,
+            scope,
+            resolved: HashSet::new(),
+        }
+    }
+
+    pub fn name(&self) -> &NameFilter {
+        &self.name
+    }
+
+    pub fn scope(&self) -> PathScope {
+        self.scope
+    }
+
+    /// Finds the files where the last component of the text lands: names starting with it for
+    /// `Contains`, folders named after it otherwise. Items are then matched by their ancestors.
+    pub fn resolve(&mut self, tree: &dyn FileTree) {
+        let mut parents = HashMap::new();
+        let mut resolved = HashSet::new();
+        for item in tree.items() {
+            let candidate = match self.scope {
+                PathScope::Contains => self.name.is_prefix_of(item.name),
+                _ => item.data.is_directory() && self.name.equals(item.name),
+            };
+            if !candidate || resolved.contains(&item.data.id()) {
+                continue;
+            }
+            let folders_match = *parents
+                .entry(item.parent_id)
+                .or_insert_with(|| self.folders_match(tree, item.parent_id));
+            if folders_match {
+                resolved.insert(item.data.id());
+            }
+        }
+        self.resolved = resolved;
+    }
+
+    pub fn matches(&self, item: &StorageItem, tree: &dyn FileTree) -> bool {
+        match self.scope {
+            PathScope::Contains => {
+                self.resolved.contains(&item.data.id()) || self.is_below(item, tree)
+            }
+            PathScope::Children => self.resolved.contains(&item.parent_id),
+            PathScope::Descendants => self.is_below(item, tree),
+        }
+    }
+
+    fn is_below(&self, item: &StorageItem, tree: &dyn FileTree) -> bool {
+        if self.resolved.is_empty() {
+            return false;
+        }
+        let mut parent_id = item.parent_id;
+        loop {
+            if self.resolved.contains(&parent_id) {
+                return true;
+            }
+            let parent = match tree.get(parent_id) {
+                Some(parent) => parent,
+                None => return false,
+            };
+            if parent.data.id() == parent.parent_id {
+                return false;
+            }
+            parent_id = parent.parent_id;
+        }
+    }
+
+    fn folders_match(&self, tree: &dyn FileTree, parent_id: FileId) -> bool {
+        let mut parent_id = parent_id;
+        for (i, folder) in self.folders.iter().enumerate().rev() {
+            let parent = match tree.get(parent_id) {
+                Some(parent) => parent,
+                None => return false,
+            };
+            let matched = if i == 0 && self.scope == PathScope::Contains {
+                folder.is_suffix_of(parent.name)
+            } else {
+                folder.equals(parent.name)
+            };
+            if !matched {
+                return false;
+            }
+            if i > 0 {
+                if parent.data.id() == parent.parent_id {
+                    return false;
+                }
+                parent_id = parent.parent_id;
+            }
+        }
+        true
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    c == '\\' || c == '/'
+}
@@ -0,0 +1,125 @@
+use crate::errors::MyErrorKind::InvalidQuery;
+use crate::file_listing::query::fold::Folded;
+use crate::file_listing::query::fold::MatchOptions;
+use failure::Error;
+use failure::ResultExt;
+use regex::Regex;
+use regex::RegexBuilder;
+
+#[derive(Clone, Debug)]
+pub struct RegexFilter {
+    regex: Regex,
+    is_path: bool,
+    options: MatchOptions,
+}
+
+impl RegexFilter {
+    pub fn new(pattern: &str, is_path: bool, options: MatchOptions) -> Result<RegexFilter, Error> {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(!options.case_sensitive)
+            .build()
+            .context(InvalidQuery("invalid regular expression"))?;
+        // case is handled by the regex itself, only diacritics are folded away from the text
+        let options = MatchOptions {
+            case_sensitive: true,
+            ..options
+        };
+        Ok(RegexFilter {
+            regex,
+            is_path,
+            options,
+        })
+    }
+
+    pub fn is_path(&self) -> bool {
+        self.is_path
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        if self.options.is_exact() {
+            self.regex.is_match(text)
+        } else {
+            self.regex.is_match(&Folded::new(text, self.options).text)
+        }
+    }
+
+    /// Byte ranges of the capture groups of every match, or of the whole matches when the
+    /// pattern has no groups.
+    pub fn find(&self, text: &str) -> Vec<(usize, usize)> {
+        if self.options.is_exact() {
+            return self.find_folded(text);
+        }
+        let folded = Folded::new(text, self.options);
+        self.find_folded(&folded.text)
+            .into_iter()
+            .map(|(start, end)| folded.original(start, end))
+            .collect()
+    }
+
+    fn find_folded(&self, text: &str) -> Vec<(usize, usize)> {
+        let first_group = if self.regex.captures_len() > 1 { 1 } else { 0 };
+        self.regex
+            .captures_iter(text)
+            .flat_map(|captures| {
+                (first_group..captures.len())
+                    .filter_map(|group| captures.get(group))
+                    .map(|m| (m.start(), m.end()))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|(start, end)| start < end)
+            .collect()
+    }
+}
+
+impl PartialEq for RegexFilter {
+    fn eq(&self, other: &RegexFilter) -> bool {
+        self.regex.as_str() == other.regex.as_str()
+            && self.is_path == other.is_path
+            && self.options == other.options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::failure_to_line;
+
+    fn regex(pattern: &str) -> Result<RegexFilter, Error> {
+        RegexFilter::new(pattern, false, MatchOptions::default())
+    }
+
+    #[test]
+    fn invalid_pattern_is_an_error() {
+        let error = regex("report(").unwrap_err();
+        assert_eq!(
+            "Invalid query: invalid regular expression",
+            error.to_string()
+        );
+        assert_eq!(
+            "Invalid query: invalid regular expression: error: unclosed group",
+            failure_to_line(&error)
+        );
+    }
+
+    #[test]
+    fn spans_of_matches_and_groups() {
+        let filter = regex(r"\d+").unwrap();
+        assert!(filter.matches("report_2024_03"));
+        assert_eq!(vec![(7, 11), (12, 14)], filter.find("report_2024_03"));
+
+        let filter = regex(r"(\w+)_(\d+)\.").unwrap();
+        assert_eq!(vec![(0, 6), (7, 9)], filter.find("report_03.xlsx"));
+    }
+
+    #[test]
+    fn folded_matching() {
+        assert!(regex("^readme").unwrap().matches("README.md"));
+        let options = MatchOptions {
+            case_sensitive: true,
+            ignore_diacritics: true,
+        };
+        let filter = RegexFilter::new("^C(zesc)", false, options).unwrap();
+        assert!(!filter.matches("część"));
+        assert_eq!(vec![(1, 8)], filter.find("Część"));
+    }
+}
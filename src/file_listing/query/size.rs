@@ -0,0 +1,99 @@
+use crate::errors::MyErrorKind::InvalidQuery;
+use crate::file_listing::list::item::parse_size;
+use crate::file_listing::list::item::KB;
+use crate::file_listing::list::item::MB;
+use failure::Error;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SizeFilter {
+    min: Option<i64>,
+    max: Option<i64>,
+}
+
+impl SizeFilter {
+    pub fn parse(value: &str) -> Result<SizeFilter, Error> {
+        let (min, max) = match value {
+            "empty" => (Some(0), Some(0)),
+            "tiny" => (Some(1), Some(10 * KB)),
+            "small" => (Some(10 * KB + 1), Some(100 * KB)),
+            "medium" => (Some(100 * KB + 1), Some(MB)),
+            "large" => (Some(MB + 1), Some(16 * MB)),
+            "huge" => (Some(16 * MB + 1), Some(128 * MB)),
+            "gigantic" => (Some(128 * MB + 1), None),
+            _ if value.starts_with(">=") => (Some(size(&value[2..])?), None),
+            _ if value.starts_with('>') => (Some(size(&value[1..])?.saturating_add(1)), None),
+            _ if value.starts_with("<=") => (None, Some(size(&value[2..])?)),
+            _ if value.starts_with('<') => (None, Some(size(&value[1..])?.saturating_sub(1))),
+            _ => match value.find("..") {
+                Some(pos) => (Some(size(&value[..pos])?), Some(size(&value[pos + 2..])?)),
+                None => {
+                    let size = size(value)?;
+                    (Some(size), Some(size))
+                }
+            },
+        };
+        Ok(SizeFilter { min, max })
+    }
+
+    pub fn matches(&self, size: i64) -> bool {
+        self.min.map_or(true, |min| size >= min) && self.max.map_or(true, |max| size <= max)
+    }
+}
+
+fn size(value: &str) -> Result<i64, Error> {
+    match parse_size(value) {
+        Some(size) => Ok(size),
+        None => Err(InvalidQuery("invalid size"))?,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(value: &str) -> SizeFilter {
+        SizeFilter::parse(value).unwrap()
+    }
+
+    #[test]
+    fn comparisons() {
+        assert!(filter(">10mb").matches(10 * MB + 1));
+        assert!(!filter(">10mb").matches(10 * MB));
+        assert!(filter(">=10mb").matches(10 * MB));
+        assert!(filter("<1kb").matches(KB - 1));
+        assert!(!filter("<1kb").matches(KB));
+        assert!(filter("<=1kb").matches(KB));
+    }
+
+    #[test]
+    fn ranges_include_both_ends() {
+        let filter = filter("1kb..5mb");
+        assert!(!filter.matches(KB - 1));
+        assert!(filter.matches(KB));
+        assert!(filter.matches(5 * MB));
+        assert!(!filter.matches(5 * MB + 1));
+    }
+
+    #[test]
+    fn exact_size() {
+        assert!(filter("2kb").matches(2048));
+        assert!(!filter("2kb").matches(2049));
+    }
+
+    #[test]
+    fn named_buckets() {
+        assert!(filter("empty").matches(0));
+        assert!(!filter("tiny").matches(0));
+        assert!(filter("tiny").matches(10 * KB));
+        assert!(filter("huge").matches(100 * MB));
+        assert!(!filter("huge").matches(200 * MB));
+        assert!(filter("gigantic").matches(200 * MB));
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        for value in &["", "big", ">", "1kb..", "-5", ">inf", "1e30", "<nan"] {
+            assert!(SizeFilter::parse(value).is_err());
+        }
+    }
+}
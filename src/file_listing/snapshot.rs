@@ -0,0 +1,264 @@
+use crate::errors::MyErrorKind::InvalidSnapshot;
+use crate::file_listing::file_entity::FileId;
+use crate::file_listing::file_entity::FileTimes;
+use crate::file_listing::file_entity::FileType;
+use crate::file_listing::files::FileData;
+use crate::file_listing::files::Files;
+use crate::file_listing::files::Link;
+use crate::file_listing::files::NameId;
+use crate::file_listing::storage::Storage;
+use crate::ntfs::attributes::FileAttributes;
+use crate::ntfs::change_journal::JournalPosition;
+use byteorder::ByteOrder;
+use byteorder::LittleEndian;
+use byteorder::ReadBytesExt;
+use byteorder::WriteBytesExt;
+use failure::Error;
+use failure::ResultExt;
+use std::fs;
+use std::io;
+use std::io::Cursor;
+use std::io::Read;
+use std::path::Path;
+use std::str;
+use std::sync::Arc;
+
+const MAGIC: &[u8] = b"CLOPPYSN";
+const FORMAT_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 12;
+const CHECKSUM_SIZE: usize = 8;
+
+/// Writes the files and the journal position they are up to. The snapshot goes to a temporary
+/// file first, a crash never leaves a torn one behind.
+pub fn save<P: AsRef<Path>>(path: P, files: &Files) -> Result<(), Error> {
+    let bytes = encode(files)?;
+    let tmp = path.as_ref().with_extension("tmp");
+    fs::write(&tmp, &bytes)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Reads a snapshot in one go, rejecting it when it is corrupt. Whether the journal can still
+/// bring it up to date is for the caller to check.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Files, Error> {
+    let bytes = fs::read(path)?;
+    decode(&bytes)
+}
+
+fn encode(files: &Files) -> io::Result<Vec<u8>> {
+    let storage = files.storage();
+    let journal = files.journal_position();
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.write_u32::<LittleEndian>(FORMAT_VERSION)?;
+    out.write_u64::<LittleEndian>(journal.volume_serial)?;
+    out.write_u64::<LittleEndian>(journal.usn_journal_id)?;
+    out.write_i64::<LittleEndian>(journal.next_usn)?;
+    out.write_u32::<LittleEndian>(storage.names().len() as u32)?;
+    for name in storage.names() {
+        out.write_u32::<LittleEndian>(name.len() as u32)?;
+        out.extend_from_slice(name.as_bytes());
+    }
+    for records in &[storage.dir_data(), storage.file_data()] {
+        out.write_u32::<LittleEndian>(records.len() as u32)?;
+        for data in records.iter() {
+            write_record(&mut out, data)?;
+        }
+    }
+    let checksum = fnv1a(&out);
+    out.write_u64::<LittleEndian>(checksum)?;
+    Ok(out)
+}
+
+fn write_record(out: &mut Vec<u8>, data: &FileData) -> io::Result<()> {
+    write_id(out, data.id())?;
+    out.write_u16::<LittleEndian>(data.flags())?;
+    out.write_u8(data.deleted() as u8)?;
+    out.write_i64::<LittleEndian>(data.size())?;
+    let times = data.times();
+    for time in &[
+        times.created,
+        times.modified,
+        times.mft_modified,
+        times.accessed,
+    ] {
+        out.write_i64::<LittleEndian>(*time)?;
+    }
+    out.write_u32::<LittleEndian>(data.attributes().bits())?;
+    // positions of removed links only matter to the results of this run
+    out.write_u16::<LittleEndian>(data.live_links().count() as u16)?;
+    for link in data.live_links() {
+        write_id(out, link.parent_id())?;
+        out.write_u32::<LittleEndian>(link.name_id().0)?;
+    }
+    Ok(())
+}
+
+fn write_id(out: &mut Vec<u8>, id: FileId) -> io::Result<()> {
+    out.write_u32::<LittleEndian>(id.id())?;
+    out.write_u8(match id.f_type() {
+        FileType::DIRECTORY => 0,
+        FileType::FILE => 1,
+    })
+}
+
+fn decode(bytes: &[u8]) -> Result<Files, Error> {
+    if bytes.len() < HEADER_SIZE + CHECKSUM_SIZE || &bytes[..MAGIC.len()] != MAGIC {
+        Err(InvalidSnapshot("not a snapshot"))?
+    }
+    let (content, checksum) = bytes.split_at(bytes.len() - CHECKSUM_SIZE);
+    if fnv1a(content) != LittleEndian::read_u64(checksum) {
+        Err(InvalidSnapshot("checksum mismatch"))?
+    }
+    if LittleEndian::read_u32(&content[MAGIC.len()..]) != FORMAT_VERSION {
+        Err(InvalidSnapshot("unsupported format version"))?
+    }
+    let mut input = Cursor::new(&content[HEADER_SIZE..]);
+    let files = read_files(&mut input).context(InvalidSnapshot("malformed content"))?;
+    Ok(files)
+}
+
+fn read_files(input: &mut Cursor<&[u8]>) -> Result<Files, Error> {
+    let journal = JournalPosition {
+        volume_serial: input.read_u64::<LittleEndian>()?,
+        usn_journal_id: input.read_u64::<LittleEndian>()?,
+        next_usn: input.read_i64::<LittleEndian>()?,
+    };
+    let name_count = input.read_u32::<LittleEndian>()? as usize;
+    let mut names: Vec<Arc<str>> = Vec::with_capacity(name_count);
+    for _ in 0..name_count {
+        let len = input.read_u32::<LittleEndian>()? as usize;
+        let mut name = vec![0u8; len];
+        input.read_exact(&mut name)?;
+        names.push(Arc::from(str::from_utf8(&name)?));
+    }
+    let mut records = Vec::with_capacity(2);
+    for f_type in &[FileType::DIRECTORY, FileType::FILE] {
+        let count = input.read_u32::<LittleEndian>()? as usize;
+        let mut data = Vec::with_capacity(count);
+        for _ in 0..count {
+            let record = read_record(input, names.len())?;
+            if record.id().f_type() != *f_type {
+                Err(InvalidSnapshot("record in the wrong list"))?
+            }
+            data.push(record);
+        }
+        if data.windows(2).any(|pair| pair[0].id() >= pair[1].id()) {
+            Err(InvalidSnapshot("records out of order"))?
+        }
+        records.push(data);
+    }
+    let file_data = records.pop().unwrap();
+    let dir_data = records.pop().unwrap();
+    let storage = Storage::from_parts(names, dir_data, file_data);
+    Ok(Files::from_storage(storage, journal))
+}
+
+fn read_record(input: &mut Cursor<&[u8]>, names: usize) -> Result<FileData, Error> {
+    let id = read_id(input)?;
+    let flags = input.read_u16::<LittleEndian>()?;
+    let deleted = input.read_u8()? != 0;
+    let size = input.read_i64::<LittleEndian>()?;
+    let times = FileTimes {
+        created: input.read_i64::<LittleEndian>()?,
+        modified: input.read_i64::<LittleEndian>()?,
+        mft_modified: input.read_i64::<LittleEndian>()?,
+        accessed: input.read_i64::<LittleEndian>()?,
+    };
+    let attributes = FileAttributes::from_bits_truncate(input.read_u32::<LittleEndian>()?);
+    let link_count = input.read_u16::<LittleEndian>()?;
+    let mut links = Vec::with_capacity(link_count as usize);
+    for _ in 0..link_count {
+        let mut link = Link::new(read_id(input)?);
+        link.set_name_id(NameId(input.read_u32::<LittleEndian>()?));
+        links.push(link);
+    }
+    if links.is_empty() || links.iter().any(|l| l.name_id().0 as usize >= names) {
+        Err(InvalidSnapshot("invalid links"))?
+    }
+    let mut data = FileData::new(id, links[0].parent_id(), size, flags, deleted);
+    data.set_times(times);
+    data.set_attributes(attributes);
+    *data.links_mut() = links;
+    Ok(data)
+}
+
+fn read_id(input: &mut Cursor<&[u8]>) -> io::Result<FileId> {
+    let id = input.read_u32::<LittleEndian>()?;
+    match input.read_u8()? {
+        0 => Ok(FileId::directory(id)),
+        _ => Ok(FileId::file(id)),
+    }
+}
+
+/// 64 bit FNV-1a, enough to catch a torn or corrupted file.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_listing::query::Query;
+
+    const FILE: u16 = 1;
+    const DIR: u16 = 2;
+
+    fn journal() -> JournalPosition {
+        JournalPosition {
+            volume_serial: 0x608c_fe84_8cfe_53ce,
+            usn_journal_id: 7,
+            next_usn: 4096,
+        }
+    }
+
+    fn test_data() -> Files {
+        let mut storage = Storage::new();
+        let root = FileData::new(FileId::directory(5), FileId::directory(5), 0, DIR, false);
+        storage.upsert(root, &["C:"]);
+        let mut report = FileData::new(FileId::file(7), FileId::directory(5), 42, FILE, false);
+        report.add_link(FileId::directory(5));
+        report.set_times(FileTimes {
+            modified: 131_000_000_000_000_000,
+            ..FileTimes::default()
+        });
+        report.set_attributes(FileAttributes::HIDDEN);
+        storage.upsert(report, &["report.doc", "raport.doc"]);
+        let old = FileData::new(FileId::file(8), FileId::directory(5), 0, FILE, true);
+        storage.upsert(old, &["Część.txt"]);
+        Files::from_storage(storage, journal())
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let files = test_data();
+        let loaded = decode(&encode(&files).unwrap()).unwrap();
+
+        assert_eq!(journal(), loaded.journal_position());
+        assert_eq!(files.storage().names(), loaded.storage().names());
+        assert_eq!(files.storage().dir_data(), loaded.storage().dir_data());
+        let data = loaded.get_file(FileId::file(7)).data;
+        assert_eq!(2, data.links().len());
+        assert_eq!(42, data.size());
+        assert_eq!(files.get_file(FileId::file(7)).data.times(), data.times());
+        assert_eq!(FileAttributes::HIDDEN, data.attributes());
+        assert!(loaded.get_file(FileId::file(8)).data.deleted());
+        let query = Query::parse("rt.doc").unwrap();
+        assert_eq!(files.search(&query), loaded.search(&query));
+        assert!(loaded.name_index_stats().is_some());
+    }
+
+    #[test]
+    fn corrupt_snapshot_is_rejected() {
+        let mut bytes = encode(&test_data()).unwrap();
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 0xFF;
+        assert!(decode(&bytes).is_err());
+
+        let bytes = encode(&test_data()).unwrap();
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(decode(b"not a snapshot at all").is_err());
+    }
+}
@@ -0,0 +1,291 @@
+use crate::file_listing::file_entity::FileId;
+use crate::file_listing::file_entity::FileType;
+use crate::file_listing::file_entity::LinkId;
+use crate::file_listing::storage::Storage;
+use crate::file_listing::storage::StorageItem;
+use crate::settings::setting_to_str;
+use crate::settings::Setting;
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::u32;
+
+#[derive(AsStaticStr, EnumString, Display, Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SortColumn {
+    Name,
+    Path,
+    Size,
+    Extension,
+    Modified,
+    Created,
+}
+
+/// Order of the results, without one they come in storage order or by rank.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Sort {
+    pub column: SortColumn,
+    pub descending: bool,
+}
+
+impl Sort {
+    pub fn new(column: SortColumn, descending: bool) -> Sort {
+        Sort { column, descending }
+    }
+
+    /// Reads the sort saved in the settings, an unknown column means no sort.
+    pub fn from_settings(settings: &HashMap<Setting, String>) -> Option<Sort> {
+        let column = SortColumn::from_str(setting_to_str(Setting::SortColumn, settings)).ok()?;
+        let descending = setting_to_str(Setting::SortDescending, settings) == "true";
+        Some(Sort::new(column, descending))
+    }
+
+    pub fn to_settings(sort: Option<Sort>) -> HashMap<Setting, String> {
+        let mut settings = HashMap::new();
+        let (column, descending) = match sort {
+            Some(sort) => (sort.column.to_string(), sort.descending),
+            None => (String::new(), false),
+        };
+        settings.insert(Setting::SortColumn, column);
+        settings.insert(Setting::SortDescending, descending.to_string());
+        settings
+    }
+}
+
+/// Ranks of the names and directories, computed once so that sorting results only compares
+/// integers and never builds a path.
+pub struct SortKeys {
+    // by NameId, case insensitive
+    names: Vec<u32>,
+    // by NameId, extension first then name
+    extensions: Vec<u32>,
+    // by directory id, full paths in order
+    paths: Vec<u32>,
+}
+
+impl SortKeys {
+    pub fn build(storage: &Storage) -> SortKeys {
+        let names = storage.names();
+        let mut by_name = (0..names.len() as u32).collect::<Vec<_>>();
+        by_name.par_sort_by_cached_key(|id| {
+            let name = &names[*id as usize];
+            (name.to_lowercase(), name.clone())
+        });
+        let name_ranks = ranks(&by_name);
+        let mut by_extension = by_name;
+        by_extension.par_sort_by_cached_key(|id| {
+            (extension(&names[*id as usize]), name_ranks[*id as usize])
+        });
+        SortKeys {
+            extensions: ranks(&by_extension),
+            paths: path_ranks(storage, &name_ranks),
+            names: name_ranks,
+        }
+    }
+
+    /// Sorts the links, ties keep the storage order.
+    pub fn sort(&self, storage: &Storage, links: &mut [LinkId], sort: Sort) {
+        let mut keyed = links
+            .par_iter()
+            .map(|link| {
+                let key = storage
+                    .get_link(*link)
+                    .map(|item| self.key(&item, sort.column));
+                (key, *link)
+            })
+            .collect::<Vec<_>>();
+        keyed.par_sort_unstable_by(|(a, a_link), (b, b_link)| {
+            let order = if sort.descending { b.cmp(a) } else { a.cmp(b) };
+            order.then_with(|| storage_order(*a_link, *b_link))
+        });
+        for (slot, (_, link)) in links.iter_mut().zip(keyed) {
+            *slot = link;
+        }
+    }
+
+    fn key(&self, item: &StorageItem, column: SortColumn) -> (i64, i64) {
+        let name = i64::from(self.names[item.name_id.0 as usize]);
+        match column {
+            SortColumn::Name => (name, 0),
+            SortColumn::Path => (self.path_rank(item.parent_id), name),
+            SortColumn::Size => (item.data.size(), name),
+            SortColumn::Extension => (i64::from(self.extensions[item.name_id.0 as usize]), 0),
+            SortColumn::Modified => (item.data.times().modified, name),
+            SortColumn::Created => (item.data.times().created, name),
+        }
+    }
+
+    fn path_rank(&self, parent_id: FileId) -> i64 {
+        let rank = self
+            .paths
+            .get(parent_id.id() as usize)
+            .cloned()
+            .unwrap_or(u32::MAX);
+        i64::from(rank)
+    }
+}
+
+pub fn storage_order(a: LinkId, b: LinkId) -> Ordering {
+    (a.id().f_type(), a.id().id(), a.link()).cmp(&(b.id().f_type(), b.id().id(), b.link()))
+}
+
+fn ranks(ordered: &[u32]) -> Vec<u32> {
+    let mut ranks = vec![0; ordered.len()];
+    for (rank, id) in ordered.iter().enumerate() {
+        ranks[*id as usize] = rank as u32;
+    }
+    ranks
+}
+
+fn extension(name: &str) -> String {
+    match name.rfind('.') {
+        Some(pos) if pos > 0 => name[pos + 1..].to_lowercase(),
+        _ => String::new(),
+    }
+}
+
+/// Visits the directory tree depth first, children by name, so that a directory ranks right
+/// after its parent and before its parent's next sibling. Directories out of the tree go last.
+fn path_ranks(storage: &Storage, name_ranks: &[u32]) -> Vec<u32> {
+    let mut children: HashMap<FileId, Vec<(u32, FileId)>> = HashMap::new();
+    let mut roots = Vec::new();
+    let mut max_id = 0;
+    for item in storage.iter() {
+        let id = item.data.id();
+        if id.f_type() != FileType::DIRECTORY {
+            break;
+        }
+        max_id = max_id.max(id.id() as usize);
+        let name = name_ranks[item.name_id.0 as usize];
+        if item.parent_id == id {
+            roots.push((name, id));
+        } else {
+            children
+                .entry(item.parent_id)
+                .or_insert_with(Vec::new)
+                .push((name, id));
+        }
+    }
+    children.values_mut().for_each(|c| c.sort());
+    roots.sort();
+
+    let mut paths = vec![u32::MAX; max_id + 1];
+    let mut next = 0;
+    let mut stack = roots.into_iter().rev().collect::<Vec<_>>();
+    while let Some((_, id)) = stack.pop() {
+        if paths[id.id() as usize] != u32::MAX {
+            continue;
+        }
+        paths[id.id() as usize] = next;
+        next += 1;
+        if let Some(children) = children.get(&id) {
+            stack.extend(children.iter().rev().cloned());
+        }
+    }
+    for rank in paths.iter_mut().filter(|rank| **rank == u32::MAX) {
+        *rank = next;
+        next += 1;
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_listing::files::FileData;
+
+    const FILE: u16 = 1;
+    const DIR: u16 = 2;
+
+    fn test_data() -> Storage {
+        let mut storage = Storage::new();
+        let dirs = [
+            (1, 1, "C:"),
+            (2, 1, "b_dir"),
+            (3, 1, "A_dir"),
+            (4, 3, "z_sub"),
+        ];
+        for (id, parent, name) in dirs.iter() {
+            let dir = FileData::new(
+                FileId::directory(*id),
+                FileId::directory(*parent),
+                0,
+                DIR,
+                false,
+            );
+            storage.upsert(dir, &[name]);
+        }
+        let files = [
+            (1, 2, "notes.txt", 30),
+            (2, 4, "b.doc", 10),
+            (3, 3, "C.txt", 20),
+        ];
+        for (id, parent, name, size) in files.iter() {
+            let file = FileData::new(
+                FileId::file(*id),
+                FileId::directory(*parent),
+                *size,
+                FILE,
+                false,
+            );
+            storage.upsert(file, &[name]);
+        }
+        storage
+    }
+
+    fn sorted(storage: &Storage, sort: Sort) -> Vec<&str> {
+        let mut links = storage
+            .iter()
+            .filter(|item| !item.data.is_directory())
+            .map(|item| item.link)
+            .collect::<Vec<_>>();
+        SortKeys::build(storage).sort(storage, &mut links, sort);
+        links
+            .into_iter()
+            .map(|link| storage.get_link(link).unwrap().name)
+            .collect()
+    }
+
+    #[test]
+    fn sorts_by_name_ignoring_case() {
+        let storage = test_data();
+        assert_eq!(
+            vec!["b.doc", "C.txt", "notes.txt"],
+            sorted(&storage, Sort::new(SortColumn::Name, false))
+        );
+        assert_eq!(
+            vec!["notes.txt", "C.txt", "b.doc"],
+            sorted(&storage, Sort::new(SortColumn::Name, true))
+        );
+    }
+
+    #[test]
+    fn sorts_by_path_depth_first() {
+        let storage = test_data();
+        assert_eq!(
+            vec!["C.txt", "b.doc", "notes.txt"],
+            sorted(&storage, Sort::new(SortColumn::Path, false))
+        );
+    }
+
+    #[test]
+    fn sorts_by_size_and_extension() {
+        let storage = test_data();
+        assert_eq!(
+            vec!["b.doc", "C.txt", "notes.txt"],
+            sorted(&storage, Sort::new(SortColumn::Size, false))
+        );
+        assert_eq!(
+            vec!["notes.txt", "C.txt", "b.doc"],
+            sorted(&storage, Sort::new(SortColumn::Extension, true))
+        );
+    }
+
+    #[test]
+    fn sort_is_saved_in_settings() {
+        let sort = Some(Sort::new(SortColumn::Modified, true));
+        assert_eq!(sort, Sort::from_settings(&Sort::to_settings(sort)));
+        assert_eq!(None, Sort::from_settings(&Sort::to_settings(None)));
+        assert_eq!(None, Sort::from_settings(&HashMap::new()));
+    }
+}
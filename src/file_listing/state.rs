@@ -1,23 +1,84 @@
-use crate::file_listing::file_entity::FileId;
+use crate::file_listing::file_entity::LinkId;
 use crate::file_listing::list::item::DisplayItem;
+use crate::file_listing::query::Highlight;
+use crate::file_listing::query::Query;
+use crate::file_listing::sort::Sort;
 use crate::plugin::PluginState;
 use std::any::Any;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 #[derive(Default)]
 pub struct FilesState {
-    current_search: Vec<FileId>,
+    current_search: Arc<Vec<LinkId>>,
+    query: Option<Query>,
+    version: u64,
+    sort: Option<Sort>,
+    highlights: Vec<Highlight>,
     item_cache: HashMap<u32, DisplayItem>,
 }
 
 impl FilesState {
-    pub fn new(current_search: Vec<FileId>) -> FilesState {
+    pub fn new(
+        current_search: Arc<Vec<LinkId>>,
+        query: Query,
+        version: u64,
+        sort: Option<Sort>,
+    ) -> FilesState {
         FilesState {
             current_search,
+            highlights: query.highlights(),
+            query: Some(query),
+            version,
+            sort,
             item_cache: HashMap::new(),
         }
     }
 
+    /// No results yet, the next query sorts them by `sort`.
+    pub fn empty(sort: Option<Sort>) -> FilesState {
+        FilesState {
+            sort,
+            ..FilesState::default()
+        }
+    }
+
+    /// The same results in the order given by `sort`.
+    pub fn sorted(&self, current_search: Arc<Vec<LinkId>>, sort: Option<Sort>) -> FilesState {
+        FilesState {
+            current_search,
+            sort,
+            ..self.clone()
+        }
+    }
+
+    pub fn sort(&self) -> Option<Sort> {
+        self.sort
+    }
+
+    pub fn query(&self) -> Option<&Query> {
+        self.query.as_ref()
+    }
+
+    pub fn results(&self) -> &[LinkId] {
+        &self.current_search
+    }
+
+    /// Results found by a query which `query` refines and the version of the files they were
+    /// found in.
+    pub fn results_to_refine(&self, query: &Query) -> Option<(&[LinkId], u64)> {
+        match self.query {
+            Some(ref previous) if query.refines(previous) => {
+                Some((&self.current_search[..], self.version))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn highlights(&self) -> &[Highlight] {
+        &self.highlights
+    }
+
     pub fn item_cache(&self) -> &HashMap<u32, DisplayItem> {
         &self.item_cache
     }
@@ -26,14 +87,21 @@ impl FilesState {
         &mut self.item_cache
     }
 
-    pub fn file_in_current_search(&self, pos: usize) -> Option<&FileId> {
+    pub fn file_in_current_search(&self, pos: usize) -> Option<&LinkId> {
         self.current_search.get(pos)
     }
 }
 
 impl Clone for FilesState {
     fn clone(&self) -> Self {
-        FilesState::new(self.current_search.clone())
+        FilesState {
+            current_search: self.current_search.clone(),
+            query: self.query.clone(),
+            version: self.version,
+            sort: self.sort,
+            highlights: self.highlights.clone(),
+            item_cache: HashMap::new(),
+        }
     }
 }
 
@@ -1,20 +1,47 @@
 use crate::file_listing::file_entity::FileEntity;
 use crate::file_listing::file_entity::FileId;
 use crate::file_listing::file_entity::FileType;
+use crate::file_listing::file_entity::LinkId;
 use crate::file_listing::files::FileData;
+use crate::file_listing::files::Link;
 use crate::file_listing::files::NameId;
+use crate::file_listing::trigram_index::IndexStats;
+use crate::file_listing::trigram_index::TrigramIndex;
 use std::borrow::Borrow;
 use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::iter::Chain;
 use std::iter::Iterator;
 use std::mem;
 use std::slice::Iter;
+use std::sync::Arc;
 
+/// Files and directories sorted by id, with their names interned. A `NameId` never changes
+/// once given, new names are appended.
 pub struct Storage {
     file_data: Vec<FileData>,
     dir_data: Vec<FileData>,
-    names: Vec<String>,
+    names: Vec<Arc<str>>,
+    name_ids: HashMap<Arc<str>, NameId>,
+    // files having a link with each name, sorted
+    name_files: Vec<Vec<FileId>>,
+    name_index: Option<TrigramIndex>,
+    tombstones: usize,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct CompactionStats {
+    pub records: usize,
+    pub names: usize,
+}
+
+/// What the name index narrowed a search down to.
+pub struct NameCandidates {
+    /// Flags the names that may match, by `NameId`.
+    pub names: Vec<bool>,
+    /// Files with a link under any of those names, in iteration order.
+    pub files: Vec<FileId>,
 }
 
 impl Storage {
@@ -26,69 +53,262 @@ impl Storage {
             file_data,
             dir_data,
             names,
+            name_ids: HashMap::new(),
+            name_files: Vec::new(),
+            name_index: None,
+            tombstones: 0,
+        }
+    }
+
+    /// Rebuilds a storage saved as its names and records, both record lists sorted by id.
+    pub fn from_parts(
+        names: Vec<Arc<str>>,
+        dir_data: Vec<FileData>,
+        file_data: Vec<FileData>,
+    ) -> Storage {
+        let mut storage = Storage {
+            name_ids: names
+                .iter()
+                .enumerate()
+                .map(|(id, name)| (name.clone(), NameId(id as u32)))
+                .collect(),
+            names,
+            dir_data,
+            file_data,
+            name_files: Vec::new(),
+            name_index: None,
+            tombstones: 0,
+        };
+        storage.rebuild_name_files();
+        storage.tombstones = storage.all_data().filter(|f| f.deleted()).count();
+        storage.build_name_index();
+        storage
+    }
+
+    pub fn dir_data(&self) -> &[FileData] {
+        &self.dir_data
+    }
+
+    pub fn file_data(&self) -> &[FileData] {
+        &self.file_data
+    }
+
+    pub fn len(&self) -> usize {
+        self.file_data.len() + self.dir_data.len()
+    }
+
+    /// Interned names by `NameId`.
+    pub fn names(&self) -> &[Arc<str>] {
+        &self.names
+    }
+
+    /// Records kept only to flag a deleted file.
+    pub fn tombstones(&self) -> usize {
+        self.tombstones
+    }
+
+    /// Drops the deleted records and the removed links except those of the files in `keep` and
+    /// of the folders above them, then the names no link uses any more. Names get new ids and
+    /// the indexes are rebuilt; `FileId`s are unchanged.
+    pub fn compact(&mut self, keep: &HashSet<FileId>) -> CompactionStats {
+        let keep = &self.with_ancestors(keep);
+        let records = self.len();
+        let kept = |f: &FileData| !f.deleted() || keep.contains(&f.id());
+        self.file_data.retain(kept);
+        self.dir_data.retain(kept);
+        for data in self.dir_data.iter_mut().chain(self.file_data.iter_mut()) {
+            if !keep.contains(&data.id()) {
+                data.links_mut().retain(|l| !l.removed());
+            }
+        }
+
+        let mut new_ids: Vec<Option<NameId>> = vec![None; self.names.len()];
+        let mut names = Vec::new();
+        let old_names = &self.names;
+        for data in self.dir_data.iter_mut().chain(self.file_data.iter_mut()) {
+            for link in data.links_mut() {
+                let old = link.name_id().0 as usize;
+                let new = *new_ids[old].get_or_insert_with(|| {
+                    names.push(old_names[old].clone());
+                    NameId(names.len() as u32 - 1)
+                });
+                link.set_name_id(new);
+            }
+        }
+        let stats = CompactionStats {
+            records: records - self.len(),
+            names: self.names.len() - names.len(),
+        };
+        self.names = names;
+        self.name_ids = self
+            .names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| (name.clone(), NameId(id as u32)))
+            .collect();
+        self.rebuild_name_files();
+        if self.name_index.is_some() {
+            self.build_name_index();
+        }
+        self.tombstones = self.all_data().filter(|f| f.deleted()).count();
+        stats
+    }
+
+    /// The files and every folder their paths go through.
+    fn with_ancestors(&self, files: &HashSet<FileId>) -> HashSet<FileId> {
+        let mut found = files.clone();
+        let mut pending = files.iter().cloned().collect::<Vec<_>>();
+        while let Some(id) = pending.pop() {
+            if let Some(data) = self.find(id) {
+                for link in data.links() {
+                    if found.insert(link.parent_id()) {
+                        pending.push(link.parent_id());
+                    }
+                }
+            }
         }
+        found
+    }
+
+    fn all_data(&self) -> impl Iterator<Item = &FileData> {
+        self.dir_data.iter().chain(self.file_data.iter())
+    }
+
+    fn rebuild_name_files(&mut self) {
+        let mut name_files = vec![Vec::new(); self.names.len()];
+        for data in self.all_data() {
+            for link in data.live_links() {
+                name_files[link.name_id().0 as usize].push(data.id());
+            }
+        }
+        for posting in &mut name_files {
+            posting.sort();
+            posting.dedup();
+        }
+        self.name_files = name_files;
+    }
+
+    pub fn build_name_index(&mut self) {
+        self.name_index = Some(TrigramIndex::build(&self.names));
+    }
+
+    pub fn name_index_stats(&self) -> Option<IndexStats> {
+        self.name_index.as_ref().map(|index| index.stats())
+    }
+
+    /// Names that may contain every loosely folded needle and the files using them, `None`
+    /// when there is no index or the needles are too short to use it.
+    pub fn name_candidates<T: AsRef<str>>(&self, needles: &[T]) -> Option<NameCandidates> {
+        let candidates = self.name_index.as_ref()?.candidates(needles)?;
+        let mut names = vec![false; self.names.len()];
+        let mut files = Vec::new();
+        for id in candidates {
+            names[id as usize] = true;
+            files.extend_from_slice(&self.name_files[id as usize]);
+        }
+        files.sort_by_key(|id| (id.f_type(), id.id()));
+        files.dedup();
+        Some(NameCandidates { names, files })
     }
 
     pub fn bulk_insert(&mut self, files: Vec<FileEntity>) {
         let names = files
             .iter()
-            .map(|f| f.name().to_string())
+            .flat_map(|f| f.names())
+            .map(|n| n.name().to_string())
             .collect::<BTreeSet<String>>();
-        {
-            let mut names_idx: HashMap<&str, u32> = HashMap::new();
-
-            for (pos, name) in names.iter().enumerate() {
-                names_idx.insert(name, pos as u32);
-            }
-            let mut files = files
-                .into_iter()
-                .map(|f| {
-                    let name_id = names_idx.get(f.name()).unwrap();
-                    let mut data: FileData = f.into();
-                    data.set_name_id(NameId(*name_id));
-                    data
-                })
-                .collect::<Vec<FileData>>();
-            files.sort_unstable_by_key(|f| f.id());
-            for f in files {
-                if f.is_directory() {
-                    self.dir_data.push(f);
-                } else {
-                    self.file_data.push(f);
+        for name in names {
+            self.upsert_name(name.as_str());
+        }
+        let mut files = files
+            .into_iter()
+            .map(|f| {
+                let name_ids = f
+                    .names()
+                    .iter()
+                    .map(|n| self.name_ids[n.name()])
+                    .collect::<Vec<NameId>>();
+                let mut data: FileData = f.into();
+                for (link, name_id) in data.links_mut().iter_mut().zip(name_ids) {
+                    link.set_name_id(name_id);
+                }
+                data
+            })
+            .collect::<Vec<FileData>>();
+        files.sort_by_key(|f| f.id());
+        for f in files {
+            let files = match f.is_directory() {
+                true => &mut self.dir_data,
+                false => &mut self.file_data,
+            };
+            match files.last_mut() {
+                Some(ref mut last) if last.id() == f.id() => {
+                    last.links_mut().extend_from_slice(f.links())
                 }
+                _ => files.push(f),
             }
         }
+        self.rebuild_name_files();
+        self.tombstones = self.all_data().filter(|f| f.deleted()).count();
+        self.build_name_index();
+    }
 
-        mem::replace(&mut self.names, names.into_iter().collect());
+    fn upsert_name(&mut self, name: &str) -> NameId {
+        if let Some(id) = self.name_ids.get(name) {
+            return *id;
+        }
+        let id = NameId(self.names.len() as u32);
+        let name: Arc<str> = Arc::from(name);
+        if let Some(index) = self.name_index.as_mut() {
+            index.insert(id, &name);
+        }
+        self.names.push(name.clone());
+        self.name_ids.insert(name, id);
+        self.name_files.push(Vec::new());
+        id
     }
 
-    fn update_file_name_ids(&mut self, new_name_id: NameId) {
-        self.file_data
-            .iter_mut()
-            .chain(self.dir_data.iter_mut())
-            .filter(|f| f.name_id().0 >= new_name_id.0)
-            .for_each(|f| {
-                let old = f.name_id().0;
-                f.set_name_id(NameId(old + 1));
-            })
+    fn add_to_posting(&mut self, name_id: NameId, id: FileId) {
+        let posting = &mut self.name_files[name_id.0 as usize];
+        if let Err(pos) = posting.binary_search(&id) {
+            posting.insert(pos, id);
+        }
     }
 
-    fn upsert_name<T: Into<String>>(&mut self, name: T) -> NameId {
-        let name = name.into();
-        match self.names.binary_search(&name) {
-            Ok(pos) => NameId(pos as u32),
-            Err(pos) => {
-                self.names.insert(pos, name);
-                let new = NameId(pos as u32);
-                self.update_file_name_ids(new);
-                new
-            }
+    fn remove_from_posting(&mut self, name_id: NameId, id: FileId) {
+        let posting = &mut self.name_files[name_id.0 as usize];
+        if let Ok(pos) = posting.binary_search(&id) {
+            posting.remove(pos);
         }
     }
 
-    pub fn upsert<T: Into<String>>(&mut self, mut data: FileData, name: T) {
-        let new_name_id = self.upsert_name(name);
-        data.set_name_id(new_name_id);
+    /// Adds or replaces a file, its links already known keep their positions and those it
+    /// lost are kept as removed.
+    pub fn upsert<T: AsRef<str>>(&mut self, mut data: FileData, names: &[T]) {
+        debug_assert_eq!(data.links().len(), names.len());
+        for (pos, name) in names.iter().enumerate() {
+            let new_name_id = self.upsert_name(name.as_ref());
+            data.links_mut()[pos].set_name_id(new_name_id);
+        }
+        let (old_name_ids, was_deleted) = match self.find_mut(&data.id()) {
+            Some(old) => {
+                let links = merge_links(old.links(), data.links());
+                *data.links_mut() = links;
+                (
+                    old.live_links().map(|l| l.name_id()).collect(),
+                    old.deleted(),
+                )
+            }
+            None => (Vec::new(), false),
+        };
+        self.tombstones = self.tombstones + data.deleted() as usize - was_deleted as usize;
+        for name_id in old_name_ids {
+            self.remove_from_posting(name_id, data.id());
+        }
+        let new_name_ids = data.live_links().map(|l| l.name_id()).collect::<Vec<_>>();
+        for name_id in new_name_ids {
+            self.add_to_posting(name_id, data.id());
+        }
         let files = match data.is_directory() {
             true => &mut self.dir_data,
             false => &mut self.file_data,
@@ -103,25 +323,108 @@ impl Storage {
     }
 
     pub fn delete<T: Borrow<FileId>>(&mut self, id: T) {
-        let files = match id.borrow().f_type() {
-            FileType::DIRECTORY => &mut self.dir_data,
-            FileType::FILE => &mut self.file_data,
+        match self.find_mut(id.borrow()) {
+            None => {}
+            Some(ref data) if data.deleted() => {}
+            Some(data) => {
+                data.set_deleted(true);
+                self.tombstones += 1;
+            }
+        }
+    }
+
+    /// Removes a link of the file, deleting the file with its last one. The link stays in
+    /// place as removed until a compaction.
+    pub fn delete_link(&mut self, id: FileId, parent_id: FileId, name: &str) {
+        let name_id = match self.name_ids.get(name) {
+            Some(name_id) => *name_id,
+            None => return,
         };
-        match files.binary_search_by_key(id.borrow(), |f| f.id()) {
-            Err(_) => println!("Delete file\tNot found\t{:?}", id.borrow()),
-            Ok(pos) => files.get_mut(pos).unwrap().set_deleted(true),
+        let removed = match self.find_mut(&id) {
+            None => return,
+            Some(data) => {
+                let pos = data.links().iter().position(|l| {
+                    !l.removed() && l.parent_id() == parent_id && l.name_id() == name_id
+                });
+                match pos {
+                    None => return,
+                    Some(_) if data.live_links().count() == 1 => {
+                        if !data.deleted() {
+                            data.set_deleted(true);
+                            self.tombstones += 1;
+                        }
+                        false
+                    }
+                    Some(pos) => {
+                        data.links_mut()[pos].set_removed(true);
+                        data.live_links().all(|l| l.name_id() != name_id)
+                    }
+                }
+            }
+        };
+        if removed {
+            self.remove_from_posting(name_id, id);
         }
     }
 
-    pub fn get<T: Borrow<FileId>>(&self, id: T) -> StorageItem {
-        let files = match id.borrow().f_type() {
+    /// Items of every link of the file.
+    pub fn items_of(&self, id: FileId) -> StorageIter {
+        let files = match id.f_type() {
             FileType::DIRECTORY => &self.dir_data,
             FileType::FILE => &self.file_data,
         };
-        let pos = files.binary_search_by_key(id.borrow(), |f| f.id()).unwrap();
-        let data = files.get(pos).unwrap();
-        let name = self.names.get(data.name_id().0 as usize).unwrap();
-        StorageItem { data, name }
+        let found = match files.binary_search_by_key(&id, |f| f.id()) {
+            Ok(pos) => &files[pos..=pos],
+            Err(_) => &files[..0],
+        };
+        StorageIter {
+            names: &self.names,
+            inner: found.iter().chain([].iter()),
+            current: None,
+        }
+    }
+
+    fn find(&self, id: FileId) -> Option<&FileData> {
+        let files = match id.f_type() {
+            FileType::DIRECTORY => &self.dir_data,
+            FileType::FILE => &self.file_data,
+        };
+        match files.binary_search_by_key(&id, |f| f.id()) {
+            Ok(pos) => files.get(pos),
+            Err(_) => None,
+        }
+    }
+
+    fn find_mut(&mut self, id: &FileId) -> Option<&mut FileData> {
+        let files = match id.f_type() {
+            FileType::DIRECTORY => &mut self.dir_data,
+            FileType::FILE => &mut self.file_data,
+        };
+        match files.binary_search_by_key(id, |f| f.id()) {
+            Ok(pos) => files.get_mut(pos),
+            Err(_) => None,
+        }
+    }
+
+    /// The file under its first link still in place.
+    pub fn get<T: Borrow<FileId>>(&self, id: T) -> StorageItem {
+        self.find_item(*id.borrow()).unwrap()
+    }
+
+    /// Like `get`, `None` when the file is not there.
+    pub fn find_item(&self, id: FileId) -> Option<StorageItem> {
+        let data = self.find(id)?;
+        let link = data.links().iter().position(|l| !l.removed()).unwrap_or(0);
+        Some(StorageItem::new(&self.names, data, link as u16))
+    }
+
+    /// The link at its position, removed or not, `None` when the file or the position is gone.
+    pub fn get_link(&self, link: LinkId) -> Option<StorageItem> {
+        let data = self.find(link.id())?;
+        if link.link() as usize >= data.links().len() {
+            return None;
+        }
+        Some(StorageItem::new(&self.names, data, link.link()))
     }
 
     pub fn iter(&self) -> StorageIter {
@@ -131,35 +434,102 @@ impl Storage {
         StorageIter {
             names: &self.names,
             inner,
+            current: None,
         }
     }
+
+    /// Splits the iteration in runs of `size` files, directories first, to be searched apart.
+    pub fn chunks(&self, size: usize) -> Vec<StorageIter> {
+        self.dir_data
+            .chunks(size)
+            .chain(self.file_data.chunks(size))
+            .map(|chunk| StorageIter {
+                names: &self.names,
+                inner: chunk.iter().chain([].iter()),
+                current: None,
+            })
+            .collect()
+    }
 }
 
 pub struct StorageItem<'a> {
     pub name: &'a str,
+    pub name_id: NameId,
+    pub parent_id: FileId,
+    pub link: LinkId,
+    /// The file lost this link since it was found.
+    pub removed: bool,
     pub data: &'a FileData,
 }
 
+impl<'a> StorageItem<'a> {
+    fn new(names: &'a [Arc<str>], data: &'a FileData, link: u16) -> StorageItem<'a> {
+        let file_link = data.links()[link as usize];
+        StorageItem {
+            name: &names[file_link.name_id().0 as usize],
+            name_id: file_link.name_id(),
+            parent_id: file_link.parent_id(),
+            link: LinkId::new(data.id(), link),
+            removed: file_link.removed(),
+            data,
+        }
+    }
+}
+
 pub struct StorageIter<'a> {
-    names: &'a [String],
+    names: &'a [Arc<str>],
     inner: Chain<Iter<'a, FileData>, Iter<'a, FileData>>,
+    current: Option<(&'a FileData, u16)>,
 }
 
 impl<'a> Iterator for StorageIter<'a> {
     type Item = StorageItem<'a>;
 
     fn next(&mut self) -> Option<StorageItem<'a>> {
-        self.inner.next().map(|data| StorageItem {
-            name: self.names.get(data.name_id().0 as usize).unwrap(),
-            data,
-        })
+        loop {
+            if let Some((data, link)) = self.current {
+                if (link as usize) < data.links().len() {
+                    self.current = Some((data, link + 1));
+                    if !data.links()[link as usize].removed() {
+                        return Some(StorageItem::new(self.names, data, link));
+                    }
+                    continue;
+                }
+            }
+            self.current = Some((self.inner.next()?, 0));
+        }
+    }
+}
+
+/// `old` links with those still in `new` at the same positions and the others removed, the
+/// new ones come last.
+fn merge_links(old: &[Link], new: &[Link]) -> Vec<Link> {
+    let mut merged = old.to_vec();
+    let mut kept = vec![false; merged.len()];
+    for link in new {
+        let pos = merged.iter().zip(&kept).position(|(l, kept)| {
+            !kept && l.parent_id() == link.parent_id() && l.name_id() == link.name_id()
+        });
+        match pos {
+            Some(pos) => kept[pos] = true,
+            None => {
+                merged.push(*link);
+                kept.push(true);
+            }
+        }
+    }
+    for (link, kept) in merged.iter_mut().zip(kept) {
+        link.set_removed(!kept);
     }
+    merged
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::file_listing::file_entity::FileId;
+    use crate::file_listing::files::Files;
+    use crate::ntfs::change_journal::JournalPosition;
 
     const FILE: u16 = 1;
     const DIR: u16 = 2;
@@ -174,13 +544,13 @@ mod tests {
         let dir2 = FileData::new(FileId::directory(2), FileId::directory(1), 0, DIR, false);
         let dir3 = FileData::new(FileId::directory(3), FileId::directory(2), 0, DIR, false);
 
-        storage.upsert(file2, "file2");
-        storage.upsert(file1, "file1");
-        storage.upsert(file0, "file0");
-        storage.upsert(dir3, "dir3");
-        storage.upsert(dir2, "dir2");
-        storage.upsert(dir1, "dir1");
-        storage.upsert(dir0, "dir0");
+        storage.upsert(file2, &["file2"]);
+        storage.upsert(file1, &["file1"]);
+        storage.upsert(file0, &["file0"]);
+        storage.upsert(dir3, &["dir3"]);
+        storage.upsert(dir2, &["dir2"]);
+        storage.upsert(dir1, &["dir1"]);
+        storage.upsert(dir0, &["dir0"]);
 
         storage
     }
@@ -190,7 +560,7 @@ mod tests {
         let storage = test_data();
 
         for x in 0..3 {
-            let StorageItem { name, data } = storage.get(FileId::file(x));
+            let StorageItem { name, data, .. } = storage.get(FileId::file(x));
             assert_eq!(name, format!("file{}", x));
             assert_eq!(FileId::file(x), data.id());
         }
@@ -202,10 +572,10 @@ mod tests {
 
         storage.upsert(
             FileData::new(FileId::file(2), FileId::directory(1), 25, FILE, false),
-            "new_file2",
+            &["new_file2"],
         );
 
-        let StorageItem { name, data } = storage.get(FileId::file(2));
+        let StorageItem { name, data, .. } = storage.get(FileId::file(2));
         assert_eq!(name, "new_file2");
         assert_eq!(data.size(), 25);
     }
@@ -215,7 +585,7 @@ mod tests {
         let storage = test_data();
 
         for x in 0..3 {
-            let StorageItem { name, data } = storage.get(FileId::directory(x));
+            let StorageItem { name, data, .. } = storage.get(FileId::directory(x));
             assert_eq!(name, format!("dir{}", x));
             assert_eq!(FileId::directory(x), data.id());
         }
@@ -226,9 +596,9 @@ mod tests {
         let mut storage = test_data();
 
         let updated_dir = FileData::new(FileId::directory(2), FileId::directory(1), 25, DIR, false);
-        storage.upsert(updated_dir, "new_dir2");
+        storage.upsert(updated_dir, &["new_dir2"]);
 
-        let StorageItem { name, data } = storage.get(FileId::directory(2));
+        let StorageItem { name, data, .. } = storage.get(FileId::directory(2));
         assert_eq!(name, "new_dir2");
         assert_eq!(data.size(), 25);
     }
@@ -256,6 +626,79 @@ mod tests {
         assert_eq!(4, dirs);
     }
 
+    #[test]
+    fn chunks_cover_the_iteration_in_order() {
+        let storage = test_data();
+
+        let chunks = storage.chunks(3);
+        assert_eq!(
+            vec![3, 1, 3],
+            chunks.into_iter().map(|c| c.count()).collect::<Vec<_>>()
+        );
+        let chunked = storage
+            .chunks(2)
+            .into_iter()
+            .flatten()
+            .map(|i| i.link)
+            .collect::<Vec<_>>();
+        assert_eq!(storage.iter().map(|i| i.link).collect::<Vec<_>>(), chunked);
+    }
+
+    #[test]
+    fn name_index_follows_new_names() {
+        let mut storage = test_data();
+        storage.build_name_index();
+        let candidates = storage.name_candidates(&["dir2"]).unwrap();
+        assert_eq!(vec![FileId::directory(2)], candidates.files);
+
+        let file = FileData::new(FileId::file(5), FileId::directory(1), 0, FILE, false);
+        storage.upsert(file, &["a_dir2_copy"]);
+        let candidates = storage.name_candidates(&["dir2"]).unwrap();
+        assert_eq!(
+            vec![FileId::directory(2), FileId::file(5)],
+            candidates.files
+        );
+        let names = storage
+            .iter()
+            .filter(|i| candidates.names[i.name_id.0 as usize])
+            .map(|i| i.name)
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["dir2", "a_dir2_copy"], names);
+        assert!(storage.name_candidates(&["di"]).is_none());
+    }
+
+    #[test]
+    fn name_ids_never_move() {
+        let mut storage = test_data();
+        let before = storage.get(FileId::file(1)).name_id;
+
+        let file = FileData::new(FileId::file(5), FileId::directory(1), 0, FILE, false);
+        storage.upsert(file, &["a_first_by_name"]);
+
+        assert_eq!(before, storage.get(FileId::file(1)).name_id);
+        assert_eq!("file1", storage.get(FileId::file(1)).name);
+    }
+
+    #[test]
+    fn postings_follow_renames_and_deleted_links() {
+        let mut storage = test_data();
+        let mut file = FileData::new(FileId::file(7), FileId::directory(1), 0, FILE, false);
+        file.add_link(FileId::directory(2));
+        storage.upsert(file, &["file1", "link_b"]);
+        let file1 = storage.name_ids["file1"];
+        assert_eq!(
+            vec![FileId::file(1), FileId::file(7)],
+            storage.name_files[file1.0 as usize]
+        );
+
+        storage.delete_link(FileId::file(7), FileId::directory(1), "file1");
+        assert_eq!(vec![FileId::file(1)], storage.name_files[file1.0 as usize]);
+
+        let renamed = FileData::new(FileId::file(1), FileId::directory(1), 0, FILE, false);
+        storage.upsert(renamed, &["file1_renamed"]);
+        assert!(storage.name_files[file1.0 as usize].is_empty());
+    }
+
     #[test]
     fn mark_file_as_deleted() {
         let mut storage = test_data();
@@ -273,7 +716,7 @@ mod tests {
         let prev_file_len = storage.file_data.len();
 
         let file = FileData::new(FileId::file(5), FileId::directory(1), 0, FILE, false);
-        storage.upsert(file, "file2");
+        storage.upsert(file, &["file2"]);
 
         assert_eq!(prev_name_len, storage.names.len());
         assert_eq!(prev_file_len + 1, storage.file_data.len());
@@ -285,7 +728,7 @@ mod tests {
         let prev_name_len = storage.names.len();
 
         let file = FileData::new(FileId::file(1), FileId::directory(1), 25, FILE, true);
-        storage.upsert(file, "file1");
+        storage.upsert(file, &["file1"]);
 
         assert_eq!(prev_name_len, storage.names.len());
         assert_eq!(25, storage.get(FileId::file(1)).data.size());
@@ -298,7 +741,7 @@ mod tests {
         let prev_name_len = storage.names.len();
 
         let file = FileData::new(FileId::file(1), FileId::directory(1), 25, FILE, true);
-        storage.upsert(file, "file1");
+        storage.upsert(file, &["file1"]);
 
         assert_eq!(prev_name_len, storage.names.len());
         assert_eq!(25, storage.get(FileId::file(1)).data.size());
@@ -311,7 +754,7 @@ mod tests {
         let prev_name_len = storage.names.len();
 
         let update_file = FileData::new(FileId::file(1), FileId::directory(1), 25, FILE, true);
-        storage.upsert(update_file, "file_update");
+        storage.upsert(update_file, &["file_update"]);
 
         assert_eq!(prev_name_len + 1, storage.names.len());
         assert_eq!(25, storage.get(FileId::file(1)).data.size());
@@ -322,11 +765,11 @@ mod tests {
     fn update_file_does_not_change_existing_files() {
         let mut storage = test_data();
         let new_file = FileData::new(FileId::file(4), FileId::directory(1), 42, FILE, false);
-        storage.upsert(new_file, "aaa_file");
+        storage.upsert(new_file, &["aaa_file"]);
         let prev_name_len = storage.names.len();
 
         let update_file = FileData::new(FileId::file(1), FileId::directory(1), 25, FILE, true);
-        storage.upsert(update_file, "file_update");
+        storage.upsert(update_file, &["file_update"]);
 
         assert_eq!(prev_name_len + 1, storage.names.len());
         assert_eq!("aaa_file", storage.get(FileId::file(4)).name);
@@ -334,6 +777,153 @@ mod tests {
     }
 
     #[test]
-    #[ignore]
-    fn old_names_are_removed() {}
+    fn iterates_over_every_link() {
+        let mut storage = test_data();
+        let mut file = FileData::new(FileId::file(7), FileId::directory(1), 0, FILE, false);
+        file.add_link(FileId::directory(2));
+        storage.upsert(file, &["link_a", "link_b"]);
+
+        let links = storage
+            .iter()
+            .filter(|i| i.data.id() == FileId::file(7))
+            .map(|i| (i.name, i.parent_id, i.link.link()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("link_a", FileId::directory(1), 0),
+                ("link_b", FileId::directory(2), 1),
+            ],
+            links
+        );
+    }
+
+    #[test]
+    fn delete_link_keeps_other_links() {
+        let mut storage = test_data();
+        let mut file = FileData::new(FileId::file(7), FileId::directory(1), 0, FILE, false);
+        file.add_link(FileId::directory(2));
+        storage.upsert(file, &["link_a", "link_b"]);
+
+        storage.delete_link(FileId::file(7), FileId::directory(1), "link_a");
+
+        let item = storage.get(FileId::file(7));
+        assert!(!item.data.deleted());
+        assert_eq!(1, item.data.live_links().count());
+        assert_eq!("link_b", item.name);
+        assert_eq!(FileId::directory(2), item.parent_id);
+        assert_eq!(
+            vec!["link_b"],
+            storage
+                .items_of(FileId::file(7))
+                .map(|i| i.name)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn links_keep_their_position_once_others_are_gone() {
+        let mut storage = test_data();
+        let mut file = FileData::new(FileId::file(7), FileId::directory(1), 0, FILE, false);
+        file.add_link(FileId::directory(2));
+        storage.upsert(file, &["link_a", "link_b"]);
+        let link_a = LinkId::new(FileId::file(7), 0);
+        let link_b = LinkId::new(FileId::file(7), 1);
+
+        storage.delete_link(FileId::file(7), FileId::directory(1), "link_a");
+        let item = storage.get_link(link_a).unwrap();
+        assert_eq!(("link_a", true), (item.name, item.removed));
+        let item = storage.get_link(link_b).unwrap();
+        assert_eq!(("link_b", false), (item.name, item.removed));
+
+        let mut renamed = FileData::new(FileId::file(7), FileId::directory(3), 0, FILE, false);
+        renamed.add_link(FileId::directory(2));
+        storage.upsert(renamed, &["link_c", "link_b"]);
+        assert_eq!("link_b", storage.get_link(link_b).unwrap().name);
+        let link_c = LinkId::new(FileId::file(7), 2);
+        assert_eq!("link_c", storage.get_link(link_c).unwrap().name);
+        assert!(storage.get_link(LinkId::new(FileId::file(7), 3)).is_none());
+        assert!(storage.get_link(LinkId::new(FileId::file(9), 0)).is_none());
+
+        storage.compact(&HashSet::new());
+        assert_eq!(2, storage.get(FileId::file(7)).data.links().len());
+        assert!(storage.get_link(link_c).is_none());
+    }
+
+    #[test]
+    fn delete_last_link_marks_file_as_deleted() {
+        let mut storage = test_data();
+
+        storage.delete_link(FileId::file(1), FileId::directory(2), "file1");
+        assert!(!storage.get(FileId::file(1)).data.deleted());
+
+        storage.delete_link(FileId::file(1), FileId::directory(1), "file1");
+        assert!(storage.get(FileId::file(1)).data.deleted());
+    }
+
+    #[test]
+    fn old_names_are_removed() {
+        let mut storage = test_data();
+        storage.build_name_index();
+        let renamed = FileData::new(FileId::file(1), FileId::directory(1), 0, FILE, false);
+        storage.upsert(renamed, &["file1_renamed"]);
+        let prev_name_len = storage.names.len();
+
+        let stats = storage.compact(&HashSet::new());
+
+        assert_eq!(
+            CompactionStats {
+                records: 0,
+                names: 1
+            },
+            stats
+        );
+        assert_eq!(prev_name_len - 1, storage.names.len());
+        assert!(!storage.name_ids.contains_key("file1"));
+        assert_eq!("file1_renamed", storage.get(FileId::file(1)).name);
+        assert_eq!("dir3", storage.get(FileId::directory(3)).name);
+        let candidates = storage.name_candidates(&["file2"]).unwrap();
+        assert_eq!(vec![FileId::file(2)], candidates.files);
+    }
+
+    #[test]
+    fn compaction_keeps_the_folders_of_kept_files() {
+        let mut storage = test_data();
+        let file = FileData::new(FileId::file(7), FileId::directory(3), 0, FILE, false);
+        storage.upsert(file, &["report"]);
+        storage.delete(FileId::file(7));
+        storage.delete(FileId::directory(3));
+        storage.delete(FileId::directory(2));
+
+        let keep = vec![FileId::file(7)].into_iter().collect();
+        let stats = storage.compact(&keep);
+
+        assert_eq!(0, stats.records);
+        let files = Files::from_storage(storage, JournalPosition::default());
+        let report = files.get_file(FileId::file(7));
+        assert_eq!("dir1\\dir2\\dir3\\", files.path_of(&report));
+    }
+
+    #[test]
+    fn compaction_drops_tombstones_not_kept() {
+        let mut storage = test_data();
+        storage.delete(FileId::file(0));
+        storage.delete_link(FileId::file(1), FileId::directory(1), "file1");
+        storage.delete(FileId::file(1));
+        assert_eq!(2, storage.tombstones());
+
+        let keep = vec![FileId::file(1)].into_iter().collect();
+        let stats = storage.compact(&keep);
+
+        assert_eq!(
+            CompactionStats {
+                records: 1,
+                names: 1
+            },
+            stats
+        );
+        assert_eq!(1, storage.tombstones());
+        assert!(storage.get(FileId::file(1)).data.deleted());
+        assert_eq!("file2", storage.get(FileId::file(2)).name);
+        assert_eq!(6, storage.iter().count());
+    }
 }
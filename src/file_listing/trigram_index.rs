@@ -0,0 +1,140 @@
+use crate::file_listing::files::NameId;
+use crate::file_listing::query::fold_loosely;
+use std::collections::HashMap;
+use std::mem;
+use std::time::Duration;
+use std::time::Instant;
+
+type Trigram = [u8; 3];
+
+/// Maps every three bytes run of the loosely folded names to the names containing it, so that
+/// only the names holding all the trigrams of a needle have to be verified.
+pub struct TrigramIndex {
+    postings: HashMap<Trigram, Vec<u32>>,
+    build_time: Duration,
+}
+
+#[derive(Debug)]
+pub struct IndexStats {
+    pub trigrams: usize,
+    pub postings: usize,
+    pub memory: usize,
+    pub build_time: Duration,
+}
+
+impl TrigramIndex {
+    pub fn build<T: AsRef<str>>(names: &[T]) -> TrigramIndex {
+        let start = Instant::now();
+        let mut postings: HashMap<Trigram, Vec<u32>> = HashMap::new();
+        for (id, name) in names.iter().enumerate() {
+            // ids are visited in order, postings stay sorted
+            for trigram in trigrams(&fold_loosely(name.as_ref())) {
+                postings
+                    .entry(trigram)
+                    .or_insert_with(Vec::new)
+                    .push(id as u32);
+            }
+        }
+        postings.values_mut().for_each(|p| p.shrink_to_fit());
+        TrigramIndex {
+            postings,
+            build_time: start.elapsed(),
+        }
+    }
+
+    /// Adds a name appended after every indexed one.
+    pub fn insert(&mut self, id: NameId, name: &str) {
+        for trigram in trigrams(&fold_loosely(name)) {
+            let posting = self.postings.entry(trigram).or_insert_with(Vec::new);
+            debug_assert!(posting.last().map_or(true, |last| *last < id.0));
+            posting.push(id.0);
+        }
+    }
+
+    /// Sorted ids of the names that may contain every loosely folded needle, `None` when no
+    /// needle is long enough to narrow the search.
+    pub fn candidates<T: AsRef<str>>(&self, needles: &[T]) -> Option<Vec<u32>> {
+        let needle_trigrams = needles
+            .iter()
+            .flat_map(|needle| trigrams(needle.as_ref()))
+            .collect::<Vec<_>>();
+        if needle_trigrams.is_empty() {
+            return None;
+        }
+        let empty = Vec::new();
+        let mut postings = needle_trigrams
+            .iter()
+            .map(|trigram| self.postings.get(trigram).unwrap_or(&empty))
+            .collect::<Vec<_>>();
+        postings.sort_by_key(|p| p.len());
+        let mut candidates = postings[0].clone();
+        for posting in &postings[1..] {
+            candidates.retain(|id| posting.binary_search(id).is_ok());
+            if candidates.is_empty() {
+                break;
+            }
+        }
+        Some(candidates)
+    }
+
+    pub fn stats(&self) -> IndexStats {
+        let postings = self.postings.values().map(|p| p.len()).sum();
+        let memory = self
+            .postings
+            .values()
+            .map(|p| p.capacity() * mem::size_of::<u32>())
+            .sum::<usize>()
+            + self.postings.capacity() * mem::size_of::<(Trigram, Vec<u32>)>();
+        IndexStats {
+            trigrams: self.postings.len(),
+            postings,
+            memory,
+            build_time: self.build_time,
+        }
+    }
+}
+
+fn trigrams(text: &str) -> Vec<Trigram> {
+    let mut trigrams = text
+        .as_bytes()
+        .windows(3)
+        .map(|w| [w[0], w[1], w[2]])
+        .collect::<Vec<_>>();
+    trigrams.sort();
+    trigrams.dedup();
+    trigrams
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn candidates_hold_every_trigram() {
+        let index = TrigramIndex::build(&names(&["Report.doc", "notes.txt", "report_old.doc"]));
+        assert_eq!(Some(vec![0, 2]), index.candidates(&["report"]));
+        assert_eq!(Some(vec![0, 2]), index.candidates(&["rep", ".doc"]));
+        assert_eq!(Some(vec![]), index.candidates(&["report", "notes"]));
+        assert_eq!(None, index.candidates(&["re", ""]));
+    }
+
+    #[test]
+    fn names_are_folded_loosely() {
+        let index = TrigramIndex::build(&names(&["Część.txt", "ŻÓŁW"]));
+        assert_eq!(Some(vec![0]), index.candidates(&["czesc"]));
+        assert_eq!(Some(vec![1]), index.candidates(&["zolw"]));
+    }
+
+    #[test]
+    fn appended_names() {
+        let mut index = TrigramIndex::build(&names(&["alpha", "gamma"]));
+        index.insert(NameId(2), "beta_gamma");
+        assert_eq!(Some(vec![2]), index.candidates(&["beta"]));
+        assert_eq!(Some(vec![1, 2]), index.candidates(&["gamma"]));
+        assert_eq!(Some(vec![0]), index.candidates(&["alpha"]));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::file_listing::sort::Sort;
+use crate::file_listing::sort::SortColumn;
 use crate::gui::event::Event;
 use crate::gui::get_string;
 use crate::gui::wnd::Wnd;
@@ -8,6 +10,12 @@ use winapi::shared::windef::*;
 use winapi::um::commctrl::*;
 
 const COLUMN_WIDTH: i32 = 200;
+const SORT_COLUMNS: [SortColumn; 4] = [
+    SortColumn::Name,
+    SortColumn::Path,
+    SortColumn::Size,
+    SortColumn::Modified,
+];
 
 pub struct ListHeader {
     wnd: Wnd,
@@ -34,6 +42,12 @@ impl ListHeader {
             column.iSubItem as WPARAM,
             &column as *const _ as LPARAM,
         );
+        let column = new_column(3, get_string("file_modified"), "file_modified".len() as i32);
+        list.send_message(
+            LVM_INSERTCOLUMNW,
+            column.iSubItem as WPARAM,
+            &column as *const _ as LPARAM,
+        );
         let hwnd = list.send_message(LVM_GETHEADER, 0, 0) as HWND;
         ListHeader {
             wnd: Wnd { hwnd },
@@ -45,42 +59,71 @@ impl ListHeader {
         &self.wnd
     }
 
-    fn reset_old_header(&self) {
+    fn format(&self, column: usize) -> i32 {
         let mut item = unsafe { mem::zeroed::<HDITEMW>() };
         item.mask = HDI_FORMAT;
         self.wnd.send_message(
             HDM_GETITEMW,
-            self.sorted_by_column as WPARAM,
+            column as WPARAM,
             &mut item as *mut _ as LPARAM,
         );
-        item.fmt = reset_order(item.fmt);
+        item.fmt
+    }
+
+    fn set_format(&self, column: usize, fmt: i32) {
+        let mut item = unsafe { mem::zeroed::<HDITEMW>() };
+        item.mask = HDI_FORMAT;
+        item.fmt = fmt;
         self.wnd.send_message(
             HDM_SETITEMW,
-            self.sorted_by_column as WPARAM,
+            column as WPARAM,
             &mut item as *mut _ as LPARAM,
         );
     }
 
-    pub fn add_sort_arrow_to_header(&mut self, event: Event) {
+    fn reset_old_header(&self) {
+        let fmt = reset_order(self.format(self.sorted_by_column));
+        self.set_format(self.sorted_by_column, fmt);
+    }
+
+    /// Moves the arrow to the clicked column or to the next order, returning the sort shown.
+    pub fn add_sort_arrow_to_header(&mut self, event: Event) -> Option<Sort> {
         let list_view = event.as_list_view();
         assert!(list_view.iSubItem >= 0);
         if list_view.iSubItem as usize != self.sorted_by_column {
             self.reset_old_header();
             self.sorted_by_column = list_view.iSubItem as usize;
         }
-        let mut item = unsafe { mem::zeroed::<HDITEMW>() };
-        item.mask = HDI_FORMAT;
-        self.wnd.send_message(
-            HDM_GETITEMW,
-            self.sorted_by_column as WPARAM,
-            &mut item as *mut _ as LPARAM,
-        );
-        item.fmt = next_order(item.fmt);
-        self.wnd.send_message(
-            HDM_SETITEMW,
-            self.sorted_by_column as WPARAM,
-            &mut item as *mut _ as LPARAM,
-        );
+        let fmt = next_order(self.format(self.sorted_by_column));
+        self.set_format(self.sorted_by_column, fmt);
+        sort_of(self.sorted_by_column, fmt)
+    }
+
+    /// Shows the arrow of a sort restored from the settings, sorts by columns not in the
+    /// list show none.
+    pub fn show_sort(&mut self, sort: Option<Sort>) {
+        self.reset_old_header();
+        let column = sort.and_then(|sort| {
+            SORT_COLUMNS
+                .iter()
+                .position(|column| *column == sort.column)
+                .map(|column| (column, sort.descending))
+        });
+        if let Some((column, descending)) = column {
+            self.sorted_by_column = column;
+            let order = if descending { HDF_SORTDOWN } else { HDF_SORTUP };
+            let fmt = reset_order(self.format(column)) | order;
+            self.set_format(column, fmt);
+        }
+    }
+}
+
+fn sort_of(column: usize, fmt: i32) -> Option<Sort> {
+    let column = *SORT_COLUMNS.get(column)?;
+    match fmt {
+        v if (v & HDF_SORTUP) == HDF_SORTUP => Some(Sort::new(column, false)),
+        v if (v & HDF_SORTDOWN) == HDF_SORTDOWN => Some(Sort::new(column, true)),
+        _ => None,
     }
 }
 
@@ -145,6 +188,20 @@ mod tests {
         assert_eq!(0, next_order(HDF_SORTDOWN));
     }
 
+    #[test]
+    fn header_order_to_sort() {
+        assert_eq!(None, sort_of(1, 0));
+        assert_eq!(
+            Some(Sort::new(SortColumn::Path, false)),
+            sort_of(1, HDF_SORTUP)
+        );
+        assert_eq!(
+            Some(Sort::new(SortColumn::Modified, true)),
+            sort_of(3, HDF_SORTDOWN + 1)
+        );
+        assert_eq!(None, sort_of(4, HDF_SORTUP));
+    }
+
     #[test]
     fn next_order_keeps_other_fmt() {
         assert_eq!(HDF_SORTUP + 1, next_order(1));
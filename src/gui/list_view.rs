@@ -1,5 +1,6 @@
 use crate::dispatcher::GuiDispatcher;
 use crate::errors::MyErrorKind::WindowsError;
+use crate::file_listing::sort::Sort;
 use crate::gui::event::Event;
 use crate::gui::get_string;
 use crate::gui::list_header::ListHeader;
@@ -68,6 +69,10 @@ impl ItemList {
         &self.header
     }
 
+    pub fn header_mut(&mut self) -> &mut ListHeader {
+        &mut self.header
+    }
+
     pub fn scroll_to_top(&self) {
         self.wnd.send_message(LVM_ENSUREVISIBLE, 0, false as isize);
     }
@@ -76,8 +81,8 @@ impl ItemList {
         &self.wnd
     }
 
-    pub fn on_header_click(&mut self, event: Event) {
-        self.header.add_sort_arrow_to_header(event);
+    pub fn on_header_click(&mut self, event: Event) -> Option<Sort> {
+        self.header.add_sort_arrow_to_header(event)
     }
 
     pub fn update(&self, state: &State) -> Result<(), Error> {
@@ -68,6 +68,7 @@ lazy_static! {
         m.insert("", "".to_wide_null());
         m.insert("file_path", "file_path".to_wide_null());
         m.insert("file_size", "file_size".to_wide_null());
+        m.insert("file_modified", "file_modified".to_wide_null());
         m.insert("file", "file".to_wide_null());
         m.insert(FILE_LIST_NAME, FILE_LIST_NAME.to_wide_null());
         m.insert(INPUT_TEXT, INPUT_TEXT.to_wide_null());
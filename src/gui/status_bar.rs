@@ -43,7 +43,13 @@ impl StatusBar {
     }
 
     pub fn update(&self, state: &State) -> Result<(), Error> {
-        let msg = state.count().to_string() + " objects found";
+        let msg = match state.error() {
+            Some(error) => error.to_string(),
+            None if state.is_searching() => {
+                format!("searching… {} objects found", state.count())
+            }
+            None => state.count().to_string() + " objects found",
+        };
         set_string(STATUS_BAR_CONTENT, msg.to_string());
         let w_param = (SB_SIMPLEID & (0 << 8)) as WPARAM;
         match self.wnd.send_message(
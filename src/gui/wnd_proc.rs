@@ -110,7 +110,7 @@ pub unsafe extern "system" fn wnd_proc(
                     0
                 }
                 LVN_COLUMNCLICK => {
-                    gui.item_list.on_header_click(event);
+                    gui.handle_action(SimpleAction::SortResults, event);
                     0
                 }
                 _ => DefWindowProcW(wnd, message, w_param, l_param),
@@ -17,6 +17,7 @@ extern crate lazy_static;
 extern crate num;
 extern crate parking_lot;
 extern crate rayon;
+extern crate regex;
 #[macro_use]
 extern crate rusqlite;
 #[macro_use]
@@ -30,25 +31,33 @@ extern crate time;
 extern crate twoway;
 #[macro_use]
 extern crate typed_builder;
+extern crate unicode_normalization;
 extern crate winapi;
 
 use crate::dispatcher::GuiDispatcher;
 use crate::dispatcher::UiAsyncMessage;
 use crate::errors::failure_to_string;
 use crate::errors::MyErrorKind::UserSettingsError;
+use crate::file_listing::sort::Sort;
 use crate::gui::GuiCreateParams;
 use crate::gui::Wnd;
 use crate::plugin::Plugin;
 use crate::plugin::State;
 use crate::plugin_handler::PluginHandler;
+use crate::settings::setting_to_str;
+use crate::settings::Setting;
 use crate::settings::UserSettings;
+use crate::store::Store;
 use crossbeam_channel as channel;
 use failure::Error;
 use failure::ResultExt;
+use std::collections::HashMap;
+use std::env;
 use std::sync::Arc;
 use std::thread;
 
 mod actions;
+mod async_io;
 mod dispatcher;
 mod errors;
 pub mod file_listing;
@@ -60,33 +69,93 @@ mod plugin_handler;
 mod resources;
 mod settings;
 mod sql;
+mod store;
+#[cfg(test)]
+mod test_utils;
 mod windows;
 
 fn main() {
     let logger = logger::setup();
-    let result = ntfs::parse_operation::run(logger.clone())
-        .and_then(|_| try_main(logger.clone()))
-        .map_err(failure_to_string);
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    let result = match parse_offline(&logger, &args) {
+        Some(result) => result,
+        None => try_main(logger.clone(), &args),
+    }
+    .map_err(failure_to_string);
     match result {
         Ok(code) => ::std::process::exit(code),
         Err(msg) => error!(logger, "Error: {}", msg),
     }
 }
 
-fn try_main(logger: slog::Logger) -> Result<i32, Error> {
+/// `--image <file>` and `--mft <file> [record size]` parse a copy of a volume or an extracted
+/// $MFT instead of starting the ui, every name of every record found is printed with its record
+/// and parent numbers.
+fn parse_offline(logger: &slog::Logger, args: &[String]) -> Option<Result<i32, Error>> {
+    let files = match args {
+        [flag, path] if flag == "--image" => ntfs::volume_image::parse_image(logger.clone(), path),
+        [flag, path] if flag == "--mft" => {
+            ntfs::parse_operation::parse_mft_file(logger.clone(), path, None)
+        }
+        [flag, path, record_size] if flag == "--mft" => match record_size.parse() {
+            Ok(size) => ntfs::parse_operation::parse_mft_file(logger.clone(), path, Some(size)),
+            Err(e) => Err(e.into()),
+        },
+        _ => return None,
+    };
+    Some(files.map(|files| {
+        for file in &files {
+            for name in file.name_attrs.iter().filter(|n| n.namespace != 2) {
+                println!(
+                    "{}\t{}\t{}",
+                    file.header.fr_number, name.parent_id, name.name
+                );
+            }
+        }
+        0
+    }))
+}
+
+/// `--index <name>` and `--volume <path>` pick the index and the volume it holds instead of
+/// the `Index` and `Volume` settings.
+fn index_options(
+    args: &[String],
+    settings: &HashMap<Setting, String>,
+) -> Result<(String, String), Error> {
+    let mut index = setting_to_str(Setting::Index, settings).to_string();
+    let mut volume = setting_to_str(Setting::Volume, settings).to_string();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--index", Some(name)) => index = name.clone(),
+            ("--volume", Some(path)) => volume = path.clone(),
+            _ => bail!("Unknown argument {}", arg),
+        }
+    }
+    Ok((index, volume))
+}
+
+fn try_main(logger: slog::Logger, args: &[String]) -> Result<i32, Error> {
     let settings = UserSettings::load(logger.clone()).context(UserSettingsError)?;
+    let settings_ui = settings.get_settings();
+    let (index, volume) = index_options(args, &settings_ui)?;
+    let store = Store::from_settings(&settings, &index)?;
+    info!(logger, "index store"; "index" => store.name(), "volume" => &volume,
+        "db" => store.db_path().to_str(), "snapshot" => store.snapshot_path().to_str());
+    let files = ntfs::parse_operation::run(logger.clone(), &store, &volume)?;
     let (req_snd, req_rcv) = channel::unbounded();
-    let arena = sql::load_all_arena().unwrap();
     let files = Arc::new(file_listing::FileListing::create(
-        arena,
+        files,
         req_snd.clone(),
+        Sort::from_settings(&settings_ui),
+        store,
+        volume,
         &logger,
     ));
     let state = State::new("", 0, files.default_plugin_state());
 
     let logger_ui = logger.new(o!("thread" => "ui"));
     let dispatcher_ui = GuiDispatcher::new(files.clone(), Box::new(state.clone()), req_snd);
-    let settings_ui = settings.get_settings();
     thread::Builder::new()
         .name("producer".to_string())
         .spawn(move || {
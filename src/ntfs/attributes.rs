@@ -5,6 +5,7 @@ use std::io::Cursor;
 const DATARUN_END: u8 = 0x00;
 const END1: u32 = 0xFFFF_FFFF;
 const STANDARD: u32 = 0x10;
+pub const ATTRIBUTE_LIST: u32 = 0x20;
 pub const FILENAME: u32 = 0x30;
 pub const DATA: u32 = 0x80;
 
@@ -13,6 +14,7 @@ pub enum AttributeType {
     Standard(StandardAttr),
     Filename(FilenameAttr),
     Data(DataAttr),
+    AttributeList(AttributeList),
 }
 
 #[derive(Debug, PartialEq)]
@@ -24,13 +26,44 @@ pub struct Attribute {
 #[derive(Debug, PartialEq, Default)]
 pub struct DataAttr {
     pub size: i64,
+    pub starting_vcn: u64,
     pub datarun: Vec<Datarun>,
 }
 
+#[derive(Debug, PartialEq)]
+pub enum AttributeList {
+    Resident(Vec<AttributeListEntry>),
+    NonResident(DataAttr),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AttributeListEntry {
+    pub attr_type: u32,
+    pub starting_vcn: u64,
+    pub file_reference: i64,
+}
+
 #[derive(Debug, PartialEq, Default)]
 pub struct StandardAttr {
-    pub modified: i64,
     pub created: i64,
+    pub modified: i64,
+    pub mft_modified: i64,
+    pub accessed: i64,
+    pub attributes: FileAttributes,
+}
+
+bitflags! {
+    #[derive(Default)]
+    pub struct FileAttributes: u32 {
+        const READONLY = 0x0000_0001;
+        const HIDDEN = 0x0000_0002;
+        const SYSTEM = 0x0000_0004;
+        const ARCHIVE = 0x0000_0020;
+        const SPARSE_FILE = 0x0000_0200;
+        const REPARSE_POINT = 0x0000_0400;
+        const COMPRESSED = 0x0000_0800;
+        const ENCRYPTED = 0x0000_4000;
+    }
 }
 
 #[derive(Debug, PartialEq, Default)]
@@ -47,11 +80,59 @@ pub struct Datarun {
     pub offset_lcn: i64,
 }
 
-const SEC_TO_UNIX_EPOCH: i64 = 11_644_473_600;
-const WINDOWS_TICK: i64 = 10_000_000;
+impl DataAttr {
+    pub fn is_empty(&self) -> bool {
+        self.size == 0 && self.datarun.is_empty()
+    }
+
+    pub fn append_extent(&mut self, mut extent: DataAttr) {
+        if self.is_empty() {
+            *self = extent;
+            return;
+        }
+        if extent.starting_vcn == 0 {
+            self.size = extent.size;
+        }
+        let last_lcn = self.datarun.iter().map(|run| run.offset_lcn).sum::<i64>();
+        if let Some(first) = extent.datarun.first_mut() {
+            first.offset_lcn -= last_lcn;
+        }
+        self.datarun.append(&mut extent.datarun);
+    }
 
-fn win_to_unix_time(win32time: i64) -> i64 {
-    win32time / WINDOWS_TICK - SEC_TO_UNIX_EPOCH
+    pub fn disk_offset(&self, offset: u64, bytes_per_cluster: u64) -> Option<u64> {
+        let vcn = offset / bytes_per_cluster;
+        let mut lcn = 0i64;
+        let mut run_start = 0;
+        for run in &self.datarun {
+            lcn += run.offset_lcn;
+            if vcn < run_start + run.length_lcn {
+                let cluster = lcn as u64 + vcn - run_start;
+                return Some(cluster * bytes_per_cluster + offset % bytes_per_cluster);
+            }
+            run_start += run.length_lcn;
+        }
+        None
+    }
+}
+
+pub fn attribute_list_entries(input: &[u8]) -> Vec<AttributeListEntry> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while offset + 0x1A <= input.len() {
+        let attr_type = LittleEndian::read_u32(&input[offset..]);
+        let entry_length = LittleEndian::read_u16(&input[offset + 0x04..]) as usize;
+        if attr_type == END1 || entry_length == 0 {
+            break;
+        }
+        entries.push(AttributeListEntry {
+            attr_type,
+            starting_vcn: LittleEndian::read_u64(&input[offset + 0x08..]),
+            file_reference: LittleEndian::read_i64(&input[offset + 0x10..]),
+        });
+        offset += entry_length;
+    }
+    entries
 }
 
 fn length_in_lcn(input: &[u8]) -> u64 {
@@ -115,9 +196,24 @@ fn filename_attr(input: &[u8]) -> FilenameAttr {
 }
 
 fn standard_attr(input: &[u8]) -> StandardAttr {
-    let created = win_to_unix_time(LittleEndian::read_i64(input));
-    let modified = win_to_unix_time(LittleEndian::read_i64(&input[0x08..]));
-    StandardAttr { modified, created }
+    StandardAttr {
+        created: LittleEndian::read_i64(input),
+        modified: LittleEndian::read_i64(&input[0x08..]),
+        mft_modified: LittleEndian::read_i64(&input[0x10..]),
+        accessed: LittleEndian::read_i64(&input[0x18..]),
+        attributes: FileAttributes::from_bits_truncate(LittleEndian::read_u32(&input[0x20..])),
+    }
+}
+
+fn non_resident_attr(input: &[u8]) -> DataAttr {
+    let starting_vcn = LittleEndian::read_u64(&input[0x10..]);
+    let datarun_offset = LittleEndian::read_u16(&input[0x20..]) as usize;
+    let size = LittleEndian::read_u64(&input[0x30..]);
+    DataAttr {
+        datarun: data_attr(&input[datarun_offset..]),
+        starting_vcn,
+        size: size as i64,
+    }
 }
 
 pub fn parse_attributes(input: &[u8], last_attr: u32) -> Vec<Attribute> {
@@ -148,19 +244,29 @@ pub fn parse_attributes(input: &[u8], last_attr: u32) -> Vec<Attribute> {
                     attr_type: AttributeType::Filename(filename),
                 });
             }
+        } else if attr_type == ATTRIBUTE_LIST {
+            let attribute_list = if non_resident {
+                AttributeList::NonResident(non_resident_attr(&input[offset..]))
+            } else {
+                let attr_offset = LittleEndian::read_u16(&input[offset + 0x14..]) as usize;
+                let length = LittleEndian::read_u32(&input[offset + 0x10..]) as usize;
+                let content = &input[offset + attr_offset..offset + attr_offset + length];
+                AttributeList::Resident(attribute_list_entries(content))
+            };
+            parsed_attributes.push(Attribute {
+                attr_flags,
+                attr_type: AttributeType::AttributeList(attribute_list),
+            });
         } else if attr_type == DATA && unnamed {
-            let attr_offset = LittleEndian::read_u16(&input[offset + 0x20..]) as usize;
-            let (datarun, size) = if non_resident {
-                let size = LittleEndian::read_u64(&input[offset + 0x30..]);
-                let vec = data_attr(&input[offset + attr_offset..]);
-                (vec, size)
+            let data = if non_resident {
+                non_resident_attr(&input[offset..])
             } else {
                 let size = LittleEndian::read_u32(&input[offset + 0x10..]);
-                (Vec::new(), size as u64)
-            };
-            let data = DataAttr {
-                datarun,
-                size: size as i64,
+                DataAttr {
+                    datarun: Vec::new(),
+                    starting_vcn: 0,
+                    size: i64::from(size),
+                }
             };
             parsed_attributes.push(Attribute {
                 attr_flags,
@@ -176,6 +282,7 @@ pub fn parse_attributes(input: &[u8], last_attr: u32) -> Vec<Attribute> {
 mod tests {
     use super::*;
     use crate::ntfs::attributes::AttributeType::*;
+    use crate::ntfs::test_utils::{attribute_list_attr, non_resident_data_attr};
 
     #[test]
     fn run() {
@@ -313,8 +420,11 @@ mod tests {
             172, 15, 209, 1, 82, 131, 14, 254, 172, 15, 209, 1, 6, 0, 0, 0,
         ];
         let output = StandardAttr {
-            modified: 1445836384,
-            created: 1445836384,
+            created: 130903099841610578,
+            modified: 130903099841610578,
+            mft_modified: 130903099841610578,
+            accessed: 130903099841610578,
+            attributes: FileAttributes::HIDDEN | FileAttributes::SYSTEM,
         };
         assert_eq!(output, standard_attr(&input));
     }
@@ -325,8 +435,11 @@ mod tests {
             Attribute {
                 attr_flags: 0,
                 attr_type: Standard(StandardAttr {
-                    modified: 1445836384,
-                    created: 1445836384,
+                    created: 130903099841610578,
+                    modified: 130903099841610578,
+                    mft_modified: 130903099841610578,
+                    accessed: 130903099841610578,
+                    attributes: FileAttributes::HIDDEN | FileAttributes::SYSTEM,
                 }),
             },
             Attribute {
@@ -341,6 +454,7 @@ mod tests {
             Attribute {
                 attr_flags: 0,
                 attr_type: Data(DataAttr {
+                    starting_vcn: 0,
                     datarun: vec![
                         Datarun {
                             length_lcn: 51232,
@@ -421,6 +535,7 @@ mod tests {
             attr_flags: 0,
             attr_type: Data(DataAttr {
                 size: 131072,
+                starting_vcn: 0,
                 datarun: vec![Datarun {
                     length_lcn: 32,
                     offset_lcn: 3,
@@ -468,6 +583,7 @@ mod tests {
             attr_flags: 0,
             attr_type: Data(DataAttr {
                 size: 186,
+                starting_vcn: 0,
                 datarun: vec![],
             }),
         }];
@@ -518,8 +634,11 @@ mod tests {
             Attribute {
                 attr_flags: 0,
                 attr_type: Standard(StandardAttr {
-                    modified: 1445836384,
-                    created: 1445836384,
+                    created: 130903099841610578,
+                    modified: 130903099841610578,
+                    mft_modified: 130903099841610578,
+                    accessed: 130903099841610578,
+                    attributes: FileAttributes::HIDDEN | FileAttributes::SYSTEM,
                 }),
             },
             Attribute {
@@ -535,6 +654,7 @@ mod tests {
                 attr_flags: 0,
                 attr_type: Data(DataAttr {
                     size: 131072,
+                    starting_vcn: 0,
                     datarun: vec![Datarun {
                         length_lcn: 32,
                         offset_lcn: 3,
@@ -575,4 +695,66 @@ mod tests {
         ];
         assert!(data_attr(&input).is_empty());
     }
+
+    #[test]
+    fn parse_resident_attribute_list() {
+        let mut input = attribute_list_attr(&[(0x10, 0, 1 << 48), (0x80, 4, 7 | 1 << 48)]);
+        input.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF]);
+        let attrs = parse_attributes(&input, DATA);
+        let expected = super::AttributeList::Resident(vec![
+            AttributeListEntry {
+                attr_type: 0x10,
+                starting_vcn: 0,
+                file_reference: 1 << 48,
+            },
+            AttributeListEntry {
+                attr_type: 0x80,
+                starting_vcn: 4,
+                file_reference: 7 | 1 << 48,
+            },
+        ]);
+        assert_eq!(AttributeType::AttributeList(expected), attrs[0].attr_type);
+    }
+
+    #[test]
+    fn parse_non_resident_attribute_list() {
+        let mut input = non_resident_data_attr(200, 1, 9);
+        LittleEndian::write_u32(&mut input, ATTRIBUTE_LIST);
+        input.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF]);
+        let attrs = parse_attributes(&input, DATA);
+        let expected = super::AttributeList::NonResident(DataAttr {
+            size: 200,
+            starting_vcn: 0,
+            datarun: vec![Datarun {
+                length_lcn: 1,
+                offset_lcn: 9,
+            }],
+        });
+        assert_eq!(AttributeType::AttributeList(expected), attrs[0].attr_type);
+    }
+
+    #[test]
+    fn append_and_locate_extents() {
+        let mut data = DataAttr {
+            size: 4096,
+            starting_vcn: 0,
+            datarun: vec![Datarun {
+                length_lcn: 2,
+                offset_lcn: 100,
+            }],
+        };
+        data.append_extent(DataAttr {
+            size: 0,
+            starting_vcn: 2,
+            datarun: vec![Datarun {
+                length_lcn: 2,
+                offset_lcn: 40,
+            }],
+        });
+        assert_eq!(4096, data.size);
+        assert_eq!(-60, data.datarun[1].offset_lcn);
+        assert_eq!(Some(100 * 1024 + 10), data.disk_offset(10, 1024));
+        assert_eq!(Some(41 * 1024 + 5), data.disk_offset(3 * 1024 + 5, 1024));
+        assert_eq!(None, data.disk_offset(4 * 1024, 1024));
+    }
 }
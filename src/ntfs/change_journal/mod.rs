@@ -1,5 +1,6 @@
 pub use self::usn_record::UsnChange;
 pub use self::usn_record::UsnRecord;
+pub use crate::ntfs::change_journal::usn_journal::JournalPosition;
 pub use crate::ntfs::change_journal::usn_journal::UsnJournal;
 
 mod usn_journal;
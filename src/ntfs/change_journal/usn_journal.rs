@@ -1,3 +1,4 @@
+use crate::errors::MyErrorKind::StaleJournalPosition;
 use crate::errors::MyErrorKind::UsnJournalError;
 use crate::ntfs::change_journal::usn_record::UsnChange;
 use crate::ntfs::change_journal::usn_record::UsnRecord;
@@ -11,36 +12,77 @@ use crate::ntfs::windows_api::UsnJournal as WinJournal;
 use byteorder::{ByteOrder, LittleEndian};
 use failure::{Error, ResultExt};
 use std::fs::File;
+use std::io;
 use std::mem;
 use std::path::Path;
 use winapi::shared::minwindef::BYTE;
+use winapi::shared::winerror::ERROR_FILE_NOT_FOUND;
+use winapi::shared::winerror::ERROR_HANDLE_EOF;
 use winapi::um::winioctl::NTFS_FILE_RECORD_OUTPUT_BUFFER;
 
+/// How far the change journal of a volume has been read.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct JournalPosition {
+    pub volume_serial: u64,
+    pub usn_journal_id: u64,
+    pub next_usn: i64,
+}
+
 pub struct UsnJournal {
     volume: File,
     volume_data: VolumeData,
+    volume_serial: u64,
     usn_journal_id: u64,
     next_usn: i64,
+    // changes before this one were already dropped from the journal
+    first_valid_usn: i64,
 }
 
 impl UsnJournal {
     pub fn new<P: AsRef<Path>>(volume_path: P) -> Result<Self, Error> {
         let volume = File::open(volume_path).context(UsnJournalError)?;
-        let volume_data = get_volume_data(&volume)
-            .map(VolumeData::new)
-            .context(UsnJournalError)?;
+        let raw_volume_data = get_volume_data(&volume).context(UsnJournalError)?;
+        // NTFS_VOLUME_DATA_BUFFER starts with the volume serial number
+        let volume_serial = LittleEndian::read_u64(&raw_volume_data[..8]);
+        let volume_data = VolumeData::new(raw_volume_data);
         let WinJournal {
             usn_journal_id,
+            first_usn,
             next_usn,
+            lowest_valid_usn,
         } = get_usn_journal(&volume).context(UsnJournalError)?;
         Ok(UsnJournal {
             volume,
             volume_data,
+            volume_serial,
             usn_journal_id,
             next_usn,
+            first_valid_usn: first_usn.max(lowest_valid_usn),
         })
     }
 
+    /// Reads the changes from `position` on instead of only the new ones. Fails when some of
+    /// them are gone: the journal was recreated or wrapped past `position`.
+    pub fn resume_from(&mut self, position: JournalPosition) -> Result<(), Error> {
+        check_resume(position, self.position(), self.first_valid_usn)?;
+        self.next_usn = position.next_usn;
+        Ok(())
+    }
+
+    /// Position after the last changes read.
+    pub fn position(&self) -> JournalPosition {
+        JournalPosition {
+            volume_serial: self.volume_serial,
+            usn_journal_id: self.usn_journal_id,
+            next_usn: self.next_usn,
+        }
+    }
+
+    /// Fails when the changes since `saved` can't be read from the journal anymore.
+    pub fn check_resume(&self, saved: JournalPosition) -> Result<(), Error> {
+        check_resume(saved, self.position(), self.first_valid_usn)
+    }
+
     pub fn get_new_changes(&mut self) -> Result<Vec<UsnChange>, Error> {
         let mut buffer = vec![0u8; self.volume_data.bytes_per_cluster as usize];
         let mut output_buffer =
@@ -62,14 +104,117 @@ impl UsnJournal {
             let record = UsnRecord::new(&buffer[offset..]).context(UsnJournalError)?;
             offset += record.length;
 
-            let fr_buffer =
-                get_file_record(&self.volume, record.fr_number, &mut output_buffer).unwrap();
-            let entry = FileRecord::parse_mft_entry(fr_buffer, self.volume_data);
-            if let Some(f) = entry {
-                usn_records.push(record.into_change(f))
+            match self.file_record(record.fr_number, &mut output_buffer)? {
+                Some(f) => usn_records.push(record.into_change(f)),
+                // the record of a deleted file may be gone already, the usn record is enough
+                None if record.is_delete() => {
+                    usn_records.push(record.into_change(FileRecord::default()))
+                }
+                None => {}
             }
         }
         self.next_usn = next_usn;
         Ok(usn_records)
     }
+
+    fn file_record(
+        &mut self,
+        fr_number: i64,
+        output_buffer: &mut [u8],
+    ) -> Result<Option<FileRecord>, Error> {
+        let fr_buffer = match get_file_record(&self.volume, fr_number, output_buffer) {
+            Ok(buffer) => buffer,
+            Err(ref e) if is_missing_record(e) => return Ok(None),
+            Err(e) => Err(e.context(UsnJournalError))?,
+        };
+        let mut entry = match FileRecord::parse_mft_entry(fr_buffer, self.volume_data) {
+            Some(f) => f,
+            None => return Ok(None),
+        };
+        entry
+            .resolve_attribute_list(&mut self.volume, self.volume_data)
+            .context(UsnJournalError)?;
+        let mut extensions = Vec::new();
+        for reference in entry.extension_records() {
+            let fr_buffer = match get_file_record(&self.volume, reference, output_buffer) {
+                Ok(buffer) => buffer,
+                Err(ref e) if is_missing_record(e) => continue,
+                Err(e) => Err(e.context(UsnJournalError))?,
+            };
+            if let Some(extension) = FileRecord::parse_mft_entry(fr_buffer, self.volume_data) {
+                extensions.push(extension);
+            }
+        }
+        entry.merge_extensions(extensions);
+        Ok(Some(entry))
+    }
+}
+
+/// The record was freed and dropped from the end of the MFT since the change, records freed
+/// otherwise still come back with the number of the nearest one in use.
+fn is_missing_record(error: &Error) -> bool {
+    match error
+        .find_root_cause()
+        .downcast_ref::<io::Error>()
+        .and_then(io::Error::raw_os_error)
+    {
+        Some(code) => code == ERROR_FILE_NOT_FOUND as i32 || code == ERROR_HANDLE_EOF as i32,
+        None => false,
+    }
+}
+
+fn check_resume(
+    saved: JournalPosition,
+    current: JournalPosition,
+    first_valid_usn: i64,
+) -> Result<(), Error> {
+    if saved.volume_serial != current.volume_serial {
+        Err(StaleJournalPosition("taken from another volume"))?
+    }
+    if saved.usn_journal_id != current.usn_journal_id {
+        Err(StaleJournalPosition("the change journal was recreated"))?
+    }
+    if saved.next_usn > current.next_usn {
+        Err(StaleJournalPosition("ahead of the change journal"))?
+    }
+    if saved.next_usn < first_valid_usn {
+        Err(StaleJournalPosition("the change journal wrapped past it"))?
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved() -> JournalPosition {
+        JournalPosition {
+            volume_serial: 0x608c_fe84_8cfe_53ce,
+            usn_journal_id: 7,
+            next_usn: 4096,
+        }
+    }
+
+    #[test]
+    fn resume_only_when_changes_are_still_in_the_journal() {
+        let saved = saved();
+        assert!(check_resume(saved, saved, 0).is_ok());
+        let ahead = JournalPosition {
+            next_usn: 8192,
+            ..saved
+        };
+        assert!(check_resume(saved, ahead, 4096).is_ok());
+        assert!(check_resume(saved, ahead, 4097).is_err());
+        assert!(check_resume(ahead, saved, 0).is_err());
+        let recreated = JournalPosition {
+            usn_journal_id: 8,
+            ..saved
+        };
+        assert!(check_resume(saved, recreated, 0).is_err());
+        let other_volume = JournalPosition {
+            volume_serial: 1,
+            ..saved
+        };
+        assert!(check_resume(saved, other_volume, 0).is_err());
+    }
 }
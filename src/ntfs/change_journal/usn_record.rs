@@ -99,6 +99,10 @@ impl UsnRecord {
     pub fn is_dir(&self) -> bool {
         self.flags == 0x16
     }
+
+    pub fn is_delete(&self) -> bool {
+        WinUsnChanges::from_bits_truncate(self.reason).contains(WinUsnChanges::FILE_DELETE)
+    }
 }
 
 #[cfg(test)]
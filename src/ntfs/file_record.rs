@@ -1,8 +1,10 @@
 use crate::ntfs::attributes::*;
 use crate::ntfs::volume_data::VolumeData;
 use byteorder::{ByteOrder, LittleEndian};
-
-const DOS_NAMESPACE: u8 = 2;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
 
 #[derive(Debug, Default, PartialEq)]
 pub struct FileRecordHeader {
@@ -42,6 +44,7 @@ pub struct FileRecord {
     pub data_attr: DataAttr,
     pub name_attrs: Vec<FilenameAttr>,
     pub standard_attr: StandardAttr,
+    pub attribute_list: Option<AttributeList>,
     pub header: FileRecordHeader,
 }
 
@@ -63,27 +66,69 @@ impl FileRecord {
     }
 
     pub fn is_unused(&self) -> bool {
-        self.header.flags % 2 == 0 || self.name_attrs.is_empty()
+        !self.is_in_use() || self.name_attrs.is_empty()
+    }
+
+    pub fn is_in_use(&self) -> bool {
+        self.header.flags % 2 != 0
+    }
+
+    pub fn is_extension(&self) -> bool {
+        self.header.base_record != 0
     }
 
     pub fn is_directory(&self) -> bool {
         self.header.flags == 3
     }
 
-    pub fn has_name(&self) -> bool {
-        !(self.name_attrs.len() == 1 && self.name_attrs[0].namespace == DOS_NAMESPACE)
+    pub fn fr_number(&self) -> i64 {
+        i64::from(self.header.fr_number) | i64::from(self.header.seq_number) << 48
     }
 
-    pub fn is_candidate_for_fixes(&self) -> bool {
-        self.header.base_record != 0 && self.is_directory()
+    pub fn resolve_attribute_list<R: Read + Seek>(
+        &mut self,
+        volume: &mut R,
+        volume_data: VolumeData,
+    ) -> io::Result<()> {
+        if let Some(AttributeList::NonResident(ref list)) = self.attribute_list {
+            let bytes_per_cluster = u64::from(volume_data.bytes_per_cluster);
+            let mut content = Vec::with_capacity(list.size as usize);
+            let mut lcn = 0i64;
+            for run in &list.datarun {
+                lcn += run.offset_lcn;
+                let mut buffer = vec![0u8; (run.length_lcn * bytes_per_cluster) as usize];
+                volume.seek(SeekFrom::Start(lcn as u64 * bytes_per_cluster))?;
+                volume.read_exact(&mut buffer)?;
+                content.append(&mut buffer);
+            }
+            content.truncate(list.size as usize);
+            self.attribute_list = Some(AttributeList::Resident(attribute_list_entries(&content)));
+        }
+        Ok(())
     }
 
-    pub fn requires_name_fix(&self) -> bool {
-        self.is_directory() && !self.has_name() && self.header.base_record == 0
+    pub fn extension_records(&self) -> Vec<i64> {
+        let mut references = match self.attribute_list {
+            Some(AttributeList::Resident(ref entries)) => entries
+                .iter()
+                .map(|e| e.file_reference)
+                .filter(|r| *r as u32 != self.header.fr_number)
+                .collect::<Vec<i64>>(),
+            _ => Vec::new(),
+        };
+        references.sort();
+        references.dedup();
+        references
     }
 
-    pub fn fr_number(&self) -> i64 {
-        i64::from(self.header.fr_number) | i64::from(self.header.seq_number) << 48
+    pub fn merge_extensions(&mut self, mut extensions: Vec<FileRecord>) {
+        extensions.sort_by_key(|e| e.data_attr.starting_vcn);
+        for extension in extensions {
+            self.name_attrs.extend(extension.name_attrs);
+            if !extension.data_attr.is_empty() {
+                self.data_attr.append_extent(extension.data_attr);
+            }
+        }
     }
 
     pub fn new(attrs: Vec<Attribute>, header: FileRecordHeader) -> Self {
@@ -105,6 +150,9 @@ impl FileRecord {
                     data_count += 1;
                     acc.data_attr = val;
                 }
+                AttributeType::AttributeList(val) => {
+                    acc.attribute_list = Some(val);
+                }
             }
             acc
         });
@@ -0,0 +1,155 @@
+use crate::errors::MyErrorKind::InvalidFileRecord;
+use crate::errors::MyErrorKind::MftFileReadError;
+use crate::ntfs::file_record::FileRecord;
+use crate::ntfs::mft_records::MftRecords;
+use crate::ntfs::volume_data::VolumeData;
+use crate::ntfs::FR_AT_ONCE;
+use byteorder::{ByteOrder, LittleEndian};
+use failure::Error;
+use failure::ResultExt;
+use slog::Logger;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+const FILE_RECORD_HEADER_SIZE: usize = 0x30;
+const DEFAULT_BYTES_PER_SECTOR: u32 = 512;
+
+fn volume_data_from_header(header: &[u8], record_size: Option<u32>) -> Result<VolumeData, Error> {
+    let is_file_record = header[..4] == b"FILE"[..];
+    let fixup_size = LittleEndian::read_u16(&header[0x06..]) as u32;
+    let bytes_per_file_record = match record_size {
+        Some(size) => size,
+        None if is_file_record => LittleEndian::read_u32(&header[0x1C..]),
+        None => Err(InvalidFileRecord("missing FILE header"))?,
+    };
+    let bytes_per_sector = match fixup_size {
+        v if is_file_record && v > 1 => bytes_per_file_record / (v - 1),
+        _ => DEFAULT_BYTES_PER_SECTOR,
+    };
+    if bytes_per_file_record == 0 || bytes_per_sector == 0 {
+        Err(InvalidFileRecord("invalid record size"))?
+    }
+    Ok(VolumeData {
+        mft_start_lcn: 0,
+        bytes_per_cluster: bytes_per_file_record,
+        bytes_per_sector,
+        bytes_per_file_record,
+    })
+}
+
+fn read_records<R: Read>(input: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match input.read(&mut buffer[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+pub fn parse_mft_file<R: Read + Seek>(
+    logger: Logger,
+    mut input: R,
+    record_size: Option<u32>,
+) -> Result<Vec<FileRecord>, Error> {
+    info!(logger, "parse mft file"; "status" => "started");
+    let mut header = [0u8; FILE_RECORD_HEADER_SIZE];
+    input.read_exact(&mut header).context(MftFileReadError)?;
+    let volume_data = volume_data_from_header(&header, record_size)?;
+    let file_size = input.seek(SeekFrom::End(0)).context(MftFileReadError)?;
+    input.seek(SeekFrom::Start(0)).context(MftFileReadError)?;
+
+    let bytes_per_file_record = volume_data.bytes_per_file_record as usize;
+    let capacity = file_size as usize / bytes_per_file_record;
+    let mut records = MftRecords::with_capacity(volume_data, capacity);
+    info!(logger, "{:?}", volume_data; "estimated size" => capacity);
+    let mut buffer = vec![0u8; FR_AT_ONCE as usize * bytes_per_file_record];
+    loop {
+        let read = read_records(&mut input, &mut buffer).context(MftFileReadError)?;
+        let fr_count = read / bytes_per_file_record;
+        records.parse_buffer(&mut buffer, fr_count);
+        if read < buffer.len() {
+            break;
+        }
+    }
+    records.merge_extensions();
+    info!(logger, "parse mft file"; "status" => "finished", "files count"=> records.files.len());
+    Ok(records.files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ntfs::test_utils::*;
+    use slog::Discard;
+    use std::io::Cursor;
+
+    fn mft_file() -> Vec<u8> {
+        let mft_data = non_resident_data_attr(4 * RECORD as u64, 4, 4);
+        let records = [
+            file_record(0, 1, &[filename_attr("$MFT", 5), mft_data]),
+            vec![0u8; RECORD],
+            file_record(2, 1, &[filename_attr("file.txt", 5)]),
+            file_record(3, 3, &[filename_attr("dir", 5)]),
+        ];
+        records.concat()
+    }
+
+    fn logger() -> Logger {
+        Logger::root(Discard, o!())
+    }
+
+    fn names(files: &[FileRecord]) -> Vec<&str> {
+        files
+            .iter()
+            .map(|f| f.name_attrs[0].name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn detect_record_size_from_header() {
+        let volume_data = volume_data_from_header(&mft_file(), None).unwrap();
+        assert_eq!(RECORD as u32, volume_data.bytes_per_file_record);
+        assert_eq!(SECTOR as u32, volume_data.bytes_per_sector);
+    }
+
+    #[test]
+    fn detection_requires_file_header() {
+        let input = vec![0u8; RECORD];
+        assert!(volume_data_from_header(&input, None).is_err());
+        let volume_data = volume_data_from_header(&input, Some(4096)).unwrap();
+        assert_eq!(4096, volume_data.bytes_per_file_record);
+        assert_eq!(DEFAULT_BYTES_PER_SECTOR, volume_data.bytes_per_sector);
+    }
+
+    #[test]
+    fn parse_files_from_mft_file() {
+        let files = parse_mft_file(logger(), Cursor::new(mft_file()), None).unwrap();
+        assert_eq!(vec!["$MFT", "file.txt", "dir"], names(&files));
+    }
+
+    #[test]
+    fn parse_files_with_explicit_record_size() {
+        let files = parse_mft_file(logger(), Cursor::new(mft_file()), Some(RECORD as u32)).unwrap();
+        assert_eq!(3, files.len());
+    }
+
+    #[test]
+    fn parse_files_across_multiple_batches() {
+        let mut input = Vec::new();
+        let count = FR_AT_ONCE as u32 * 2 + 3;
+        for fr_number in 0..count {
+            let name = format!("file{}", fr_number);
+            input.extend(file_record(fr_number, 1, &[filename_attr(&name, 5)]));
+        }
+        input.extend(vec![0u8; RECORD / 2]);
+        let files = parse_mft_file(logger(), Cursor::new(input), None).unwrap();
+        assert_eq!(count as usize, files.len());
+        assert_eq!("file130", files.last().unwrap().name_attrs[0].name);
+    }
+}
@@ -1,116 +1,195 @@
+use crate::async_io::{BufferPool, CompletedOperation, CompletionPort};
+use crate::errors::MyErrorKind::AsyncReadError;
 use crate::ntfs::file_record::FileRecord;
 use crate::ntfs::mft_reader::MftReader;
+use crate::ntfs::mft_records::MftRecords;
 use crate::ntfs::volume_data::VolumeData;
 use crate::ntfs::FR_AT_ONCE;
-use crate::windows::async_io::{BufferPool, IOCompletionPort, OutputOperation};
+use failure::Error;
+use failure::ResultExt;
 use slog::Logger;
-use std::collections::HashMap;
+use std::cmp;
 use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::thread;
 
-pub struct MftParser {
+pub struct MftParser<C: CompletionPort> {
     logger: Logger,
-    volume_data: VolumeData,
     counter: Arc<AtomicUsize>,
     pool: BufferPool,
-    iocp: Arc<IOCompletionPort>,
-    pub candidates: HashMap<i64, FileRecord>,
-    pub faulty: Vec<FileRecord>,
-    pub files: Vec<FileRecord>,
+    port: Arc<C>,
+    bytes_per_file_record: usize,
+    pub records: MftRecords,
 }
 
-impl MftParser {
-    pub fn new(logger: Logger, mft: &FileRecord, volume_data: VolumeData) -> Self {
+impl<C: CompletionPort> MftParser<C> {
+    pub fn new(logger: Logger, mft: &FileRecord, volume_data: VolumeData, port: C) -> Self {
         let counter = Arc::new(AtomicUsize::new(0));
         let pool = BufferPool::new(
             16,
             FR_AT_ONCE as usize * volume_data.bytes_per_file_record as usize,
         );
-        let iocp = Arc::new(IOCompletionPort::new(1).unwrap());
+        let port = Arc::new(port);
 
-        let candidates = HashMap::new();
-        let faulty = Vec::new();
-        let capacity = MftParser::estimate_capacity(&mft, &volume_data);
-        info!(logger, "{:?}", volume_data; "estimated size" => capacity);
-        let files = Vec::with_capacity(capacity);
+        let records = MftRecords::new(&mft, volume_data);
+        info!(logger, "{:?}", volume_data; "estimated size" => records.files.capacity());
         MftParser {
-            volume_data,
             counter,
-            pool: pool.clone(),
-            iocp: iocp.clone(),
-            files,
-            candidates,
-            faulty,
+            pool,
+            port,
+            bytes_per_file_record: volume_data.bytes_per_file_record as usize,
+            records,
             logger,
         }
     }
-    pub fn parse_iocp_buffer(&mut self) {
+    /// Parses the reads as they complete. A failed read or wait doesn't stop it, the buffers of
+    /// the others still go back to the pool so the reader can finish, the first error is
+    /// returned at the end.
+    pub fn parse_iocp_buffer(&mut self) -> Result<(), Error> {
         let mut operations_count = 0;
         let mut finish = false;
         let mut end = false;
+        let mut error = None;
         while !end {
+            let mut operation = match self.port.get_completed() {
+                Ok(operation) => operation,
+                // nothing was dequeued, the operations still pending are waited for
+                Err(e) => {
+                    error = error.or(Some(e));
+                    continue;
+                }
+            };
             operations_count += 1;
-            let mut operation = self.iocp.get().unwrap();
             if operation.completion_key() != 42 {
                 finish = true;
             }
-            self.iocp_buffer_to_files(&mut operation);
+            match operation.bytes_read() {
+                // whatever a short read left after the last whole record is from a previous read
+                Ok(read) if error.is_none() => {
+                    let fr_count =
+                        cmp::min(operation.content_len(), read / self.bytes_per_file_record);
+                    self.records.parse_buffer(operation.buffer_mut(), fr_count);
+                }
+                Ok(_) => {}
+                Err(e) => error = error.or(Some(e)),
+            }
             self.pool.put(operation.into_buffer());
             end = finish && operations_count == self.counter.load(Ordering::SeqCst);
         }
-        self.fix_dir_hardlinks();
-    }
-
-    pub fn fix_dir_hardlinks(&mut self) {
-        debug_assert_eq!(self.faulty.len(), self.candidates.len());
-        for mut f in self.faulty.drain(..) {
-            debug_assert!(self.candidates.contains_key(&f.fr_number()));
-            let fix = self.candidates.remove(&f.fr_number()).unwrap();
-            f.name_attrs = fix.name_attrs;
-            self.files.push(f);
+        if let Some(e) = error {
+            Err(e).context(AsyncReadError)?
         }
+        self.records.merge_extensions();
+        Ok(())
     }
 
-    pub fn new_reader<P: AsRef<Path>>(&mut self, file: P, completion_key: usize) -> MftReader {
+    pub fn new_reader<P: AsRef<Path>>(
+        &mut self,
+        file: P,
+        completion_key: usize,
+    ) -> Result<MftReader<C>, Error> {
         MftReader::new(
             self.pool.clone(),
-            self.iocp.clone(),
+            self.port.clone(),
             file,
             completion_key,
             self.counter.clone(),
             self.logger.clone(),
         )
     }
-    fn estimate_capacity(mft: &FileRecord, volume: &VolumeData) -> usize {
-        let clusters = mft
-            .data_attr
-            .datarun
-            .iter()
-            .map(|d| d.length_lcn as u32)
-            .sum::<u32>();
-        (clusters * volume.bytes_per_cluster / volume.bytes_per_file_record) as usize
+}
+
+/// Reads the records of `mft` from `path` through `port` and parses them as the reads complete.
+pub fn parse_all<C, P>(
+    logger: Logger,
+    port: C,
+    path: P,
+    mft: FileRecord,
+    volume_data: VolumeData,
+) -> Result<Vec<FileRecord>, Error>
+where
+    C: CompletionPort + 'static,
+    C::File: 'static,
+    P: AsRef<Path>,
+{
+    let mut parser = MftParser::new(logger, &mft, volume_data, port);
+    let mut reader = parser.new_reader(path, 42)?;
+    let read_thread = thread::Builder::new()
+        .name("producer".to_string())
+        .spawn(move || reader.read_all(&mft, volume_data))?;
+    let parsed = parser.parse_iocp_buffer();
+    match read_thread.join() {
+        Ok(read) => read.context(AsyncReadError)?,
+        Err(_) => Err(AsyncReadError)?,
     }
+    parsed?;
+    Ok(parser.records.files)
+}
 
-    fn iocp_buffer_to_files(&mut self, operation: &mut OutputOperation) {
-        let fr_count = operation.content_len();
-        for buff in operation
-            .buffer_mut()
-            .chunks_mut(self.volume_data.bytes_per_file_record as usize)
-            .take(fr_count)
-        {
-            if let Some(f) = FileRecord::parse_mft_entry(buff, self.volume_data) {
-                if f.is_unused() {
-                    continue;
-                }
-                if f.requires_name_fix() {
-                    self.faulty.push(f);
-                } else if f.is_candidate_for_fixes() {
-                    self.candidates.insert(f.header.base_record as i64, f);
-                } else {
-                    self.files.push(f);
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::async_io::ThreadPort;
+    use crate::ntfs::test_utils::*;
+    use crate::test_utils::TempFile;
+    use slog::Discard;
+
+    const MFT_LCN: usize = 1;
+    const MFT_RECORDS: usize = 4;
+
+    fn volume_data() -> VolumeData {
+        VolumeData {
+            mft_start_lcn: MFT_LCN as u64,
+            bytes_per_cluster: RECORD as u32,
+            bytes_per_sector: SECTOR as u32,
+            bytes_per_file_record: RECORD as u32,
         }
     }
+
+    fn mft_record() -> Vec<u8> {
+        let mft_data = non_resident_data_attr(
+            (MFT_RECORDS * RECORD) as u64,
+            MFT_RECORDS as u8,
+            MFT_LCN as u8,
+        );
+        file_record(0, 1, &[filename_attr("$MFT", 5), mft_data])
+    }
+
+    fn volume() -> Vec<u8> {
+        let mut volume = vec![0u8; MFT_LCN * RECORD];
+        volume.extend(mft_record());
+        volume.extend(vec![0u8; RECORD]);
+        volume.extend(file_record(2, 1, &[filename_attr("file.txt", 5)]));
+        volume.extend(file_record(3, 3, &[filename_attr("dir", 5)]));
+        volume
+    }
+
+    fn parse(volume: &[u8]) -> Result<Vec<String>, Error> {
+        let tmp = TempFile::with_content("mft_parser", volume);
+        let logger = Logger::root(Discard, o!());
+        let mft = FileRecord::parse_mft_entry(&mut mft_record(), volume_data()).unwrap();
+        let port = ThreadPort::new(2).unwrap();
+
+        let files = parse_all(logger, port, tmp.path(), mft, volume_data())?;
+        let mut names = files
+            .iter()
+            .map(|f| f.name_attrs[0].name.clone())
+            .collect::<Vec<_>>();
+        names.sort();
+        Ok(names)
+    }
+
+    #[test]
+    fn parse_volume_with_thread_port() {
+        assert_eq!(vec!["$MFT", "dir", "file.txt"], parse(&volume()).unwrap());
+    }
+
+    #[test]
+    fn short_read_parses_whole_records_only() {
+        let mut volume = volume();
+        let len = volume.len();
+        volume.truncate(len - RECORD / 2);
+        assert_eq!(vec!["$MFT", "file.txt"], parse(&volume).unwrap());
+    }
 }
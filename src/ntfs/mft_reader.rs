@@ -1,81 +1,93 @@
+use crate::async_io::{BufferPool, CompletionPort};
 use crate::ntfs::file_record::FileRecord;
 use crate::ntfs::volume_data::VolumeData;
 use crate::ntfs::FR_AT_ONCE;
-use crate::windows::async_io::{AsyncFile, BufferPool, IOCompletionPort, InputOperation};
 use failure::Error;
 use slog::Logger;
 use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-pub struct MftReader {
+pub struct MftReader<C: CompletionPort> {
     pool: BufferPool,
     logger: Logger,
-    iocp: Arc<IOCompletionPort>,
-    file: AsyncFile,
+    port: Arc<C>,
+    file: C::File,
     counter: Arc<AtomicUsize>,
 }
 
-impl MftReader {
+impl<C: CompletionPort> MftReader<C> {
     pub fn new<P: AsRef<Path>>(
         pool: BufferPool,
-        iocp: Arc<IOCompletionPort>,
+        port: Arc<C>,
         file_path: P,
         completion_key: usize,
         counter: Arc<AtomicUsize>,
         logger: Logger,
-    ) -> Self {
-        let file = iocp.associate_file(file_path, completion_key).unwrap();
-        MftReader {
+    ) -> Result<Self, Error> {
+        let file = port.associate_file(file_path, completion_key)?;
+        Ok(MftReader {
             file,
-            iocp,
+            port,
             pool,
             counter,
             logger,
-        }
+        })
     }
 
     pub fn finish(&self) -> Result<(), Error> {
-        let operation = Box::new(InputOperation::empty());
         self.counter.fetch_add(1, Ordering::SeqCst);
-        self.iocp.post(operation, 99)
+        self.port.post_empty(99)
     }
 
     pub fn read(&mut self, offset: u64, content_len: usize) -> Result<(), Error> {
         let buffer = self.pool.get();
-        let operation = Box::new(InputOperation::new(buffer, offset, content_len));
+        self.port
+            .submit_read(&self.file, buffer, offset, content_len)?;
+        // only reads submitted ever complete, the parser waits for as many
         self.counter.fetch_add(1, Ordering::SeqCst);
-        IOCompletionPort::submit(&self.file, operation)
+        Ok(())
+    }
+
+    /// Submits a read for every record of `mft`, stopping at the first that fails. The parser is
+    /// told it was the last one either way.
+    pub fn read_all(&mut self, mft: &FileRecord, volume_data: VolumeData) -> Result<(), Error> {
+        let read = self.read_runs(mft, volume_data);
+        let finished = self.finish();
+        read.and(finished)
     }
 
-    pub fn read_all(&mut self, mft: &FileRecord, volume_data: VolumeData) {
+    fn read_runs(&mut self, mft: &FileRecord, volume_data: VolumeData) -> Result<(), Error> {
         use std::time::Instant;
         let now = Instant::now();
         let mut absolute_lcn_offset = 0i64;
         for (i, run) in mft.data_attr.datarun.iter().enumerate() {
             absolute_lcn_offset += run.offset_lcn;
-            let absolute_offset = absolute_lcn_offset as u64 * u64::from(volume_data.bytes_per_cluster);
-            let file_record_count = run.length_lcn * u64::from(volume_data.clusters_per_fr());
+            let absolute_offset =
+                absolute_lcn_offset as u64 * u64::from(volume_data.bytes_per_cluster);
+            // clusters can be smaller than records on images
+            let file_record_count = run.length_lcn * u64::from(volume_data.bytes_per_cluster)
+                / u64::from(volume_data.bytes_per_file_record);
 
             let full_runs_count = file_record_count / FR_AT_ONCE;
             let partial_run_size = file_record_count % FR_AT_ONCE;
             let datarun_info = o!("file count" => file_record_count, "full runs count" => full_runs_count, "full run size" => FR_AT_ONCE, "partial run size" => partial_run_size);
             info!(&self.logger, "mft reader" ; "datarun" => i, "status" => "started", datarun_info);
             for run in 0..full_runs_count {
-                let offset =
-                    absolute_offset + run * FR_AT_ONCE * u64::from(volume_data.bytes_per_file_record);
+                let offset = absolute_offset
+                    + run * FR_AT_ONCE * u64::from(volume_data.bytes_per_file_record);
                 debug!(&self.logger, "mft reader - full run" ; "run" => run, "offset" => offset);
-                self.read(offset, FR_AT_ONCE as usize).unwrap();
+                self.read(offset, FR_AT_ONCE as usize)?;
             }
             if partial_run_size > 0 {
                 let offset = absolute_offset
                     + full_runs_count * FR_AT_ONCE * u64::from(volume_data.bytes_per_file_record);
                 debug!(&self.logger, "mft reader - partial run" ; "run" => full_runs_count, "offset" => offset);
-                self.read(offset, partial_run_size as usize).unwrap();
+                self.read(offset, partial_run_size as usize)?;
             }
             info!(&self.logger, "mft reader"; "datarun" => i, "status" => "finished", "Time" => ?Instant::now().duration_since(now));
         }
         info!(&self.logger, "mft reader - finished";"Time" => ?Instant::now().duration_since(now));
-        self.finish().unwrap();
+        Ok(())
     }
 }
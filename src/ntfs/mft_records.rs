@@ -0,0 +1,160 @@
+use crate::ntfs::file_record::FileRecord;
+use crate::ntfs::volume_data::VolumeData;
+use std::collections::HashMap;
+
+pub struct MftRecords {
+    volume_data: VolumeData,
+    pub extensions: HashMap<i64, Vec<FileRecord>>,
+    pub files: Vec<FileRecord>,
+}
+
+impl MftRecords {
+    pub fn new(mft: &FileRecord, volume_data: VolumeData) -> Self {
+        let capacity = MftRecords::estimate_capacity(mft, &volume_data);
+        MftRecords::with_capacity(volume_data, capacity)
+    }
+
+    pub fn with_capacity(volume_data: VolumeData, capacity: usize) -> Self {
+        MftRecords {
+            volume_data,
+            extensions: HashMap::new(),
+            files: Vec::with_capacity(capacity),
+        }
+    }
+
+    fn estimate_capacity(mft: &FileRecord, volume: &VolumeData) -> usize {
+        let clusters = mft
+            .data_attr
+            .datarun
+            .iter()
+            .map(|d| d.length_lcn)
+            .sum::<u64>();
+        (clusters * u64::from(volume.bytes_per_cluster) / u64::from(volume.bytes_per_file_record))
+            as usize
+    }
+
+    pub fn parse_buffer(&mut self, buffer: &mut [u8], fr_count: usize) {
+        for buff in buffer
+            .chunks_mut(self.volume_data.bytes_per_file_record as usize)
+            .take(fr_count)
+        {
+            if let Some(f) = FileRecord::parse_mft_entry(buff, self.volume_data) {
+                if !f.is_in_use() {
+                    continue;
+                }
+                if f.is_extension() {
+                    self.extensions
+                        .entry(f.header.base_record as i64)
+                        .or_insert_with(Vec::new)
+                        .push(f);
+                } else {
+                    self.files.push(f);
+                }
+            }
+        }
+    }
+
+    pub fn merge_extensions(&mut self) {
+        let extensions = &mut self.extensions;
+        for f in &mut self.files {
+            if let Some(records) = extensions.remove(&f.fr_number()) {
+                f.merge_extensions(records);
+            }
+        }
+        self.files.retain(|f| !f.is_unused());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ntfs::attributes::Datarun;
+    use crate::ntfs::test_utils::*;
+
+    fn volume_data() -> VolumeData {
+        VolumeData {
+            mft_start_lcn: 0,
+            bytes_per_cluster: RECORD as u32,
+            bytes_per_sector: SECTOR as u32,
+            bytes_per_file_record: RECORD as u32,
+        }
+    }
+
+    fn reference(fr_number: u32) -> i64 {
+        i64::from(fr_number) | 1 << 48
+    }
+
+    fn parse(records: &[Vec<u8>]) -> Vec<FileRecord> {
+        let mut buffer = records.concat();
+        let mut parsed = MftRecords::with_capacity(volume_data(), records.len());
+        parsed.parse_buffer(&mut buffer, records.len());
+        parsed.merge_extensions();
+        parsed.files
+    }
+
+    #[test]
+    fn merge_data_extents() {
+        let attribute_list = attribute_list_attr(&[
+            (0x30, 0, reference(0)),
+            (0x80, 0, reference(0)),
+            (0x80, 8, reference(1)),
+        ]);
+        let files = parse(&[
+            file_record(
+                0,
+                1,
+                &[
+                    attribute_list,
+                    filename_attr("big.bin", 5),
+                    data_extent_attr(0, 16 * RECORD as u64, 8, 10),
+                ],
+            ),
+            extension_record(1, reference(0), &[data_extent_attr(8, 0, 8, 30)]),
+        ]);
+        assert_eq!(1, files.len());
+        assert_eq!(vec![reference(1)], files[0].extension_records());
+        assert_eq!(16 * RECORD as i64, files[0].data_attr.size);
+        assert_eq!(
+            vec![
+                Datarun {
+                    length_lcn: 8,
+                    offset_lcn: 10,
+                },
+                Datarun {
+                    length_lcn: 8,
+                    offset_lcn: 20,
+                },
+            ],
+            files[0].data_attr.datarun
+        );
+    }
+
+    #[test]
+    fn merge_directory_names() {
+        let attribute_list = attribute_list_attr(&[(0x30, 0, reference(2))]);
+        let files = parse(&[
+            extension_record(0, reference(1), &[filename_attr("dir", 5)]),
+            file_record(1, 3, &[attribute_list]),
+            extension_record(2, reference(1), &[filename_attr("other", 5)]),
+        ]);
+        assert_eq!(1, files.len());
+        assert!(files[0].is_directory());
+        let mut names = files[0]
+            .name_attrs
+            .iter()
+            .map(|n| n.name.as_str())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(vec!["dir", "other"], names);
+    }
+
+    #[test]
+    fn drop_records_without_names() {
+        let files = parse(&[
+            file_record(0, 1, &[]),
+            file_record(1, 0, &[filename_attr("deleted", 5)]),
+            extension_record(2, reference(7), &[filename_attr("orphan", 5)]),
+        ]);
+        assert!(files.is_empty());
+    }
+}
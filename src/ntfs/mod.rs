@@ -1,10 +1,15 @@
 pub mod attributes;
 pub mod change_journal;
 pub mod file_record;
+mod mft_file;
 mod mft_parser;
 mod mft_reader;
+mod mft_records;
 pub mod parse_operation;
+#[cfg(test)]
+mod test_utils;
 mod volume_data;
+pub mod volume_image;
 mod windows_api;
 
 //TODO make this value 'smart' depending on the HD
@@ -1,54 +1,94 @@
+use crate::errors::failure_to_line;
+use crate::file_listing::files::Files;
+use crate::ntfs::change_journal::UsnJournal;
 use crate::ntfs::file_record::FileRecord;
-use crate::ntfs::mft_parser::MftParser;
+use crate::ntfs::mft_file;
+use crate::ntfs::mft_parser;
 use crate::ntfs::volume_data::VolumeData;
+use crate::ntfs::volume_image;
 use crate::ntfs::windows_api::get_volume_data;
-use crate::sql::insert_files;
+use crate::store::Store;
+use crate::windows::async_io::IOCompletionPort;
 use failure::Error;
 use slog::Logger;
 use std::fs::File;
-use std::io::prelude::*;
-use std::io::SeekFrom;
 use std::path::Path;
-use std::thread;
 
-fn parse_volume<P: AsRef<Path>>(logger: Logger, path: P) -> Vec<FileRecord> {
+fn parse_volume<P: AsRef<Path>>(logger: Logger, path: P) -> Result<Vec<FileRecord>, Error> {
     info!(logger, "parse volume"; "status" => "started");
     let (mft, volume) = read_mft(path.as_ref());
+    let iocp = IOCompletionPort::new(1)?;
+    let files = mft_parser::parse_all(logger.clone(), iocp, path, mft, volume)?;
+    info!(logger, "parse volume"; "status" => "finished", "files count"=> files.len());
+    Ok(files)
+}
 
-    let mut parser = MftParser::new(logger.clone(), &mft, volume);
-    let mut reader = parser.new_reader(path, 42);
-
-    let read_thread = thread::Builder::new()
-        .name("producer".to_string())
-        .spawn(move || {
-            reader.read_all(&mft, volume);
-        })
-        .unwrap();
-    parser.parse_iocp_buffer();
-    read_thread.join().expect("reader panic");
-    info!(logger, "parse volume"; "status" => "finished", "files count"=> parser.files.len());
-    parser.files
+pub fn parse_mft_file<P: AsRef<Path>>(
+    logger: Logger,
+    path: P,
+    record_size: Option<u32>,
+) -> Result<Vec<FileRecord>, Error> {
+    let mft = File::open(path)?;
+    mft_file::parse_mft_file(logger, mft, record_size)
 }
 
 fn read_mft<P: AsRef<Path>>(volume_path: P) -> (FileRecord, VolumeData) {
     let mut file = File::open(volume_path).expect("Failed to open volume handle");
     let volume_data = VolumeData::new(get_volume_data(&file).unwrap());
-    let mut buffer = vec![0u8; volume_data.bytes_per_file_record as usize];
-
-    file.seek(SeekFrom::Start(volume_data.initial_offset()))
-        .unwrap();
-    file.read_exact(&mut buffer).unwrap();
-    let mft = FileRecord::parse_mft_entry(&mut buffer, volume_data).unwrap();
+    let mft = volume_image::read_mft(&mut file, volume_data).unwrap();
 
     (mft, volume_data)
 }
 
-pub fn run(parent_logger: Logger) -> Result<(), Error> {
-    let volume_path = "\\\\.\\C:";
-    let logger = parent_logger.new(o!("type" =>"files", "volume" => volume_path.to_string()));
-    if !Path::new("./test.db").exists() {
-        let files = parse_volume(logger, volume_path);
-        insert_files(&files);
+/// Loads the files from the snapshot of the store when the change journal still has every change
+/// made since it was taken, those are replayed once the journal is read. Otherwise the volume is
+/// parsed again and a snapshot is written for the next start.
+pub fn run(parent_logger: Logger, store: &Store, volume_path: &str) -> Result<Files, Error> {
+    let logger = parent_logger.new(o!("type" =>"files", "volume" => volume_path.to_string(),
+        "index" => store.name().to_string()));
+    let journal = UsnJournal::new(volume_path)?;
+    match load_snapshot(store, &journal) {
+        Ok(files) => {
+            info!(logger, "snapshot loaded"; "file" => store.snapshot_path().to_str(),
+                "next usn" => files.journal_position().next_usn);
+            return Ok(files);
+        }
+        Err(e) => {
+            info!(logger, "snapshot not loaded, rescanning"; "file" => store.snapshot_path().to_str(),
+                "reason" => failure_to_line(&e));
+        }
     }
-    Ok(())
+    scan(logger, store, volume_path, &journal)
+}
+
+/// Parses the volume again once its change journal can't be read, the files returned are up
+/// to the journal position taken before.
+pub fn rescan(parent_logger: Logger, store: &Store, volume_path: &str) -> Result<Files, Error> {
+    let logger = parent_logger.new(o!("volume" => volume_path.to_string()));
+    let journal = UsnJournal::new(volume_path)?;
+    scan(logger, store, volume_path, &journal)
+}
+
+fn scan(
+    logger: Logger,
+    store: &Store,
+    volume_path: &str,
+    journal: &UsnJournal,
+) -> Result<Files, Error> {
+    // taken before parsing, changes made while it runs are replayed afterwards
+    let position = journal.position();
+    let records = parse_volume(logger.clone(), volume_path)?;
+    store.insert_files(volume_path, &records)?;
+    let mut files = store.load_files(volume_path)?;
+    files.set_journal_position(position);
+    if let Err(e) = store.save_snapshot(&files) {
+        warn!(logger, "snapshot not saved"; "reason" => failure_to_line(&e));
+    }
+    Ok(files)
+}
+
+fn load_snapshot(store: &Store, journal: &UsnJournal) -> Result<Files, Error> {
+    let files = store.load_snapshot()?;
+    journal.check_resume(files.journal_position())?;
+    Ok(files)
 }
@@ -0,0 +1,86 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+pub const SECTOR: usize = 512;
+pub const RECORD: usize = 1024;
+
+pub fn filename_attr(name: &str, parent_id: i64) -> Vec<u8> {
+    let name = name.encode_utf16().collect::<Vec<u16>>();
+    let content_len = 0x42 + 2 * name.len();
+    let len = (0x18 + content_len + 7) & !7;
+    let mut attr = vec![0u8; len];
+    LittleEndian::write_u32(&mut attr[0x00..], 0x30);
+    LittleEndian::write_u32(&mut attr[0x04..], len as u32);
+    LittleEndian::write_u32(&mut attr[0x10..], content_len as u32);
+    LittleEndian::write_u16(&mut attr[0x14..], 0x18);
+    let content = &mut attr[0x18..];
+    LittleEndian::write_i64(content, parent_id);
+    content[0x40] = name.len() as u8;
+    content[0x41] = 1;
+    for (i, c) in name.iter().enumerate() {
+        LittleEndian::write_u16(&mut content[0x42 + 2 * i..], *c);
+    }
+    attr
+}
+
+pub fn non_resident_data_attr(size: u64, length_lcn: u8, offset_lcn: u8) -> Vec<u8> {
+    data_extent_attr(0, size, length_lcn, offset_lcn)
+}
+
+pub fn data_extent_attr(starting_vcn: u64, size: u64, length_lcn: u8, offset_lcn: u8) -> Vec<u8> {
+    let mut attr = vec![0u8; 0x48];
+    LittleEndian::write_u32(&mut attr[0x00..], 0x80);
+    LittleEndian::write_u32(&mut attr[0x04..], 0x48);
+    attr[0x08] = 1;
+    LittleEndian::write_u64(&mut attr[0x10..], starting_vcn);
+    LittleEndian::write_u16(&mut attr[0x20..], 0x40);
+    LittleEndian::write_u64(&mut attr[0x30..], size);
+    attr[0x40..0x43].copy_from_slice(&[0x11, length_lcn, offset_lcn]);
+    attr
+}
+
+pub fn attribute_list_attr(entries: &[(u32, u64, i64)]) -> Vec<u8> {
+    let content_len = 0x20 * entries.len();
+    let len = 0x18 + content_len;
+    let mut attr = vec![0u8; len];
+    LittleEndian::write_u32(&mut attr[0x00..], 0x20);
+    LittleEndian::write_u32(&mut attr[0x04..], len as u32);
+    LittleEndian::write_u32(&mut attr[0x10..], content_len as u32);
+    LittleEndian::write_u16(&mut attr[0x14..], 0x18);
+    for (i, (attr_type, starting_vcn, file_reference)) in entries.iter().enumerate() {
+        let entry = &mut attr[0x18 + 0x20 * i..];
+        LittleEndian::write_u32(&mut entry[0x00..], *attr_type);
+        LittleEndian::write_u16(&mut entry[0x04..], 0x20);
+        LittleEndian::write_u64(&mut entry[0x08..], *starting_vcn);
+        LittleEndian::write_i64(&mut entry[0x10..], *file_reference);
+    }
+    attr
+}
+
+pub fn extension_record(fr_number: u32, base_record: i64, attributes: &[Vec<u8>]) -> Vec<u8> {
+    let mut record = file_record(fr_number, 1, attributes);
+    LittleEndian::write_i64(&mut record[0x20..], base_record);
+    record
+}
+
+pub fn file_record(fr_number: u32, flags: u16, attributes: &[Vec<u8>]) -> Vec<u8> {
+    let mut record = vec![0u8; RECORD];
+    record[..4].copy_from_slice(b"FILE");
+    LittleEndian::write_u16(&mut record[0x04..], 0x30);
+    LittleEndian::write_u16(&mut record[0x06..], (RECORD / SECTOR + 1) as u16);
+    LittleEndian::write_u16(&mut record[0x10..], 1);
+    LittleEndian::write_u16(&mut record[0x14..], 0x38);
+    LittleEndian::write_u16(&mut record[0x16..], flags);
+    LittleEndian::write_u32(&mut record[0x1C..], RECORD as u32);
+    LittleEndian::write_u32(&mut record[0x2C..], fr_number);
+    let mut offset = 0x38;
+    for attr in attributes {
+        record[offset..offset + attr.len()].copy_from_slice(attr);
+        offset += attr.len();
+    }
+    LittleEndian::write_u32(&mut record[offset..], 0xFFFF_FFFF);
+    LittleEndian::write_u16(&mut record[0x30..], 1);
+    for sector in 1..=RECORD / SECTOR {
+        LittleEndian::write_u16(&mut record[sector * SECTOR - 2..], 1);
+    }
+    record
+}
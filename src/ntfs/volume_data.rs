@@ -1,4 +1,9 @@
+use crate::errors::MyErrorKind::InvalidBootSector;
 use byteorder::{ByteOrder, LittleEndian};
+use failure::Error;
+
+const NTFS_OEM_ID: &[u8] = b"NTFS    ";
+pub const BOOT_SECTOR_SIZE: usize = 512;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct VolumeData {
@@ -21,6 +26,37 @@ impl VolumeData {
             bytes_per_cluster,
         }
     }
+    pub fn from_boot_sector(input: &[u8]) -> Result<VolumeData, Error> {
+        if input.len() < BOOT_SECTOR_SIZE {
+            Err(InvalidBootSector("boot sector too short"))?
+        }
+        if &input[0x03..0x0B] != NTFS_OEM_ID {
+            Err(InvalidBootSector("missing NTFS oem id"))?
+        }
+        if input[0x1FE..0x200] != [0x55, 0xAA] {
+            Err(InvalidBootSector("missing boot sector signature"))?
+        }
+        let bytes_per_sector = u32::from(LittleEndian::read_u16(&input[0x0B..]));
+        let bytes_per_cluster = match input[0x0D] {
+            v if v > 0x80 => bytes_per_sector << (256 - u32::from(v)),
+            v => bytes_per_sector * u32::from(v),
+        };
+        let mft_start_lcn = LittleEndian::read_u64(&input[0x30..]);
+        let bytes_per_file_record = match input[0x40] as i8 {
+            v if v < 0 => 1 << -i32::from(v),
+            v => bytes_per_cluster * v as u32,
+        };
+        if bytes_per_sector == 0 || bytes_per_cluster == 0 || bytes_per_file_record == 0 {
+            Err(InvalidBootSector("invalid volume geometry"))?
+        }
+        Ok(VolumeData {
+            mft_start_lcn,
+            bytes_per_file_record,
+            bytes_per_sector,
+            bytes_per_cluster,
+        })
+    }
+
     pub fn initial_offset(&self) -> u64 {
         u64::from(self.bytes_per_cluster) * self.mft_start_lcn
     }
@@ -52,4 +88,50 @@ mod tests {
         };
         assert_eq!(output, VolumeData::new(input));
     }
+
+    fn boot_sector(sectors_per_cluster: u8, clusters_per_fr: u8) -> Vec<u8> {
+        let mut input = vec![0u8; BOOT_SECTOR_SIZE];
+        input[0x03..0x0B].copy_from_slice(NTFS_OEM_ID);
+        LittleEndian::write_u16(&mut input[0x0B..], 512);
+        input[0x0D] = sectors_per_cluster;
+        LittleEndian::write_u64(&mut input[0x30..], 786_432);
+        input[0x40] = clusters_per_fr;
+        input[0x1FE] = 0x55;
+        input[0x1FF] = 0xAA;
+        input
+    }
+
+    #[test]
+    fn volume_data_from_boot_sector() {
+        let output = VolumeData {
+            mft_start_lcn: 786_432,
+            bytes_per_cluster: 4096,
+            bytes_per_sector: 512,
+            bytes_per_file_record: 1024,
+        };
+        let input = boot_sector(8, 0xF6);
+        assert_eq!(output, VolumeData::from_boot_sector(&input).unwrap());
+    }
+
+    #[test]
+    fn volume_data_from_boot_sector_with_cluster_sized_records() {
+        let input = boot_sector(2, 1);
+        let volume_data = VolumeData::from_boot_sector(&input).unwrap();
+        assert_eq!(1024, volume_data.bytes_per_cluster);
+        assert_eq!(1024, volume_data.bytes_per_file_record);
+    }
+
+    #[test]
+    fn volume_data_from_boot_sector_with_large_clusters() {
+        let input = boot_sector(0xF4, 0xF6);
+        let volume_data = VolumeData::from_boot_sector(&input).unwrap();
+        assert_eq!(512 << 12, volume_data.bytes_per_cluster);
+    }
+
+    #[test]
+    fn boot_sector_without_ntfs_oem_id_is_rejected() {
+        let mut input = boot_sector(8, 0xF6);
+        input[0x03..0x0B].copy_from_slice(b"MSDOS5.0");
+        assert!(VolumeData::from_boot_sector(&input).is_err());
+    }
 }
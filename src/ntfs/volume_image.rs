@@ -0,0 +1,192 @@
+use crate::async_io::ThreadPort;
+use crate::errors::MyErrorKind::ImageReadError;
+use crate::ntfs::file_record::FileRecord;
+use crate::ntfs::mft_parser;
+use crate::ntfs::volume_data::VolumeData;
+use crate::ntfs::volume_data::BOOT_SECTOR_SIZE;
+use failure::Error;
+use failure::ResultExt;
+use slog::Logger;
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+
+pub struct VolumeImage<R> {
+    input: R,
+    volume_data: VolumeData,
+}
+
+impl<R: Read + Seek> VolumeImage<R> {
+    pub fn open(mut input: R) -> Result<Self, Error> {
+        let mut boot_sector = [0u8; BOOT_SECTOR_SIZE];
+        input.seek(SeekFrom::Start(0)).context(ImageReadError)?;
+        input.read_exact(&mut boot_sector).context(ImageReadError)?;
+        let volume_data = VolumeData::from_boot_sector(&boot_sector)?;
+        Ok(VolumeImage { input, volume_data })
+    }
+
+    pub fn volume_data(&self) -> VolumeData {
+        self.volume_data
+    }
+
+    pub fn read_mft(&mut self) -> Result<FileRecord, Error> {
+        read_mft(&mut self.input, self.volume_data)
+    }
+}
+
+fn read_at<R: Read + Seek>(input: &mut R, offset: u64, buffer: &mut [u8]) -> Result<(), Error> {
+    input
+        .seek(SeekFrom::Start(offset))
+        .context(ImageReadError)?;
+    input.read_exact(buffer).context(ImageReadError)?;
+    Ok(())
+}
+
+fn read_record<R: Read + Seek>(
+    input: &mut R,
+    offset: u64,
+    volume_data: VolumeData,
+) -> Result<FileRecord, Error> {
+    let mut buffer = vec![0u8; volume_data.bytes_per_file_record as usize];
+    read_at(input, offset, &mut buffer)?;
+    match FileRecord::parse_mft_entry(&mut buffer, volume_data) {
+        Some(record) => Ok(record),
+        None => Err(ImageReadError)?,
+    }
+}
+
+pub fn read_mft<R: Read + Seek>(
+    input: &mut R,
+    volume_data: VolumeData,
+) -> Result<FileRecord, Error> {
+    let mut mft = read_record(input, volume_data.initial_offset(), volume_data)?;
+    mft.resolve_attribute_list(input, volume_data)
+        .context(ImageReadError)?;
+    let bytes_per_cluster = u64::from(volume_data.bytes_per_cluster);
+    let mut extensions = Vec::new();
+    for reference in mft.extension_records() {
+        let record_offset =
+            u64::from(reference as u32) * u64::from(volume_data.bytes_per_file_record);
+        match mft.data_attr.disk_offset(record_offset, bytes_per_cluster) {
+            Some(offset) => extensions.push(read_record(input, offset, volume_data)?),
+            None => Err(ImageReadError)?,
+        }
+    }
+    mft.merge_extensions(extensions);
+    Ok(mft)
+}
+
+/// Parses every record of the image at `path`, the `$MFT` is read through `MftParser` like a
+/// live volume.
+pub fn parse_image<P: AsRef<Path>>(logger: Logger, path: P) -> Result<Vec<FileRecord>, Error> {
+    info!(logger, "parse image"; "status" => "started");
+    let mut image = VolumeImage::open(File::open(&path).context(ImageReadError)?)?;
+    let mft = image.read_mft()?;
+    let port = ThreadPort::new(2)?;
+    let files = mft_parser::parse_all(logger.clone(), port, path, mft, image.volume_data())?;
+    info!(logger, "parse image"; "status" => "finished", "files count"=> files.len());
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ntfs::test_utils::*;
+    use crate::test_utils::TempFile;
+    use byteorder::{ByteOrder, LittleEndian};
+    use slog::Discard;
+    use std::io::Cursor;
+
+    const MFT_LCN: usize = 4;
+    const MFT_RECORDS: usize = 4;
+
+    fn boot_sector() -> Vec<u8> {
+        let mut sector = vec![0u8; SECTOR];
+        sector[0x03..0x0B].copy_from_slice(b"NTFS    ");
+        LittleEndian::write_u16(&mut sector[0x0B..], SECTOR as u16);
+        sector[0x0D] = (RECORD / SECTOR) as u8;
+        LittleEndian::write_u64(&mut sector[0x30..], MFT_LCN as u64);
+        sector[0x40] = 1;
+        sector[0x1FE] = 0x55;
+        sector[0x1FF] = 0xAA;
+        sector
+    }
+
+    fn image() -> Vec<u8> {
+        let mut image = vec![0u8; (MFT_LCN + MFT_RECORDS) * RECORD];
+        image[..SECTOR].copy_from_slice(&boot_sector());
+        let mft_data = non_resident_data_attr(
+            (MFT_RECORDS * RECORD) as u64,
+            MFT_RECORDS as u8,
+            MFT_LCN as u8,
+        );
+        let records = [
+            file_record(0, 1, &[filename_attr("$MFT", 5), mft_data]),
+            vec![0u8; RECORD],
+            file_record(2, 1, &[filename_attr("file.txt", 5)]),
+            file_record(3, 3, &[filename_attr("dir", 5)]),
+        ];
+        for (i, record) in records.iter().enumerate() {
+            let offset = (MFT_LCN + i) * RECORD;
+            image[offset..offset + RECORD].copy_from_slice(record);
+        }
+        image
+    }
+
+    fn logger() -> Logger {
+        Logger::root(Discard, o!())
+    }
+
+    #[test]
+    fn volume_data_from_image() {
+        let image = VolumeImage::open(Cursor::new(image())).unwrap();
+        let volume_data = image.volume_data();
+        assert_eq!(MFT_LCN as u64, volume_data.mft_start_lcn);
+        assert_eq!(RECORD as u32, volume_data.bytes_per_cluster);
+        assert_eq!(RECORD as u32, volume_data.bytes_per_file_record);
+    }
+
+    #[test]
+    fn read_mft_from_image() {
+        let mut image = VolumeImage::open(Cursor::new(image())).unwrap();
+        let mft = image.read_mft().unwrap();
+        assert_eq!("$MFT", mft.name_attrs[0].name);
+        assert_eq!(1, mft.data_attr.datarun.len());
+        assert_eq!(MFT_RECORDS as u64, mft.data_attr.datarun[0].length_lcn);
+    }
+
+    fn parse(image: &[u8]) -> Result<Vec<FileRecord>, Error> {
+        let tmp = TempFile::with_content("volume_image", image);
+        let mut files = parse_image(logger(), tmp.path())?;
+        files.sort_by_key(|f| f.header.fr_number);
+        Ok(files)
+    }
+
+    fn names(files: &[FileRecord]) -> Vec<&str> {
+        files
+            .iter()
+            .map(|f| f.name_attrs[0].name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn parse_files_from_image() {
+        let files = parse(&image()).unwrap();
+        assert_eq!(vec!["$MFT", "file.txt", "dir"], names(&files));
+        assert!(files[2].is_directory());
+    }
+
+    #[test]
+    fn truncated_image_keeps_whole_records() {
+        let mut image = image();
+        image.truncate((MFT_LCN + 3) * RECORD + RECORD / 2);
+        assert_eq!(vec!["$MFT", "file.txt"], names(&parse(&image).unwrap()));
+    }
+
+    #[test]
+    fn missing_image_fails() {
+        assert!(parse_image(logger(), "no such image").is_err());
+    }
+}
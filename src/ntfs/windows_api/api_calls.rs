@@ -117,10 +117,14 @@ pub fn get_usn_journal(v_handle: &File) -> Result<UsnJournal, Error> {
     }
     if bytes_read == 80 {
         let usn_journal_id = LittleEndian::read_u64(&output);
+        let first_usn = LittleEndian::read_i64(&output[8..]);
         let next_usn = LittleEndian::read_i64(&output[16..]);
+        let lowest_valid_usn = LittleEndian::read_i64(&output[24..]);
         Ok(UsnJournal {
             usn_journal_id,
+            first_usn,
             next_usn,
+            lowest_valid_usn,
         })
     } else {
         Err(WindowsError("Failed to query usn_journal"))?
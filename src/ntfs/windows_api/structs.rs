@@ -3,7 +3,9 @@ use std::fmt;
 #[derive(Copy, Clone, Debug)]
 pub struct UsnJournal {
     pub usn_journal_id: u64,
+    pub first_usn: i64,
     pub next_usn: i64,
+    pub lowest_valid_usn: i64,
 }
 
 bitflags! {
@@ -1,12 +1,27 @@
+use crate::file_listing::sort::Sort;
 use crate::gui::event::Event;
 use std::any::Any;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 use winapi::shared::ntdef::LPWSTR;
 
 pub trait Plugin: Sync + Send {
     fn draw_item(&self, event: Event, state: &State) -> DrawResult;
     fn custom_draw_item(&self, event: Event, state: &State) -> CustomDrawResult;
     fn prepare_item(&self, item_id: usize, state: &mut State);
-    fn handle_message(&self, msg: &str, prev_state: &State) -> State;
+    /// Runs the query in `msg`, returning `None` if it was given up because `is_stale`. Partial
+    /// results of a long query are passed to `progress` as they are found.
+    fn handle_message(
+        &self,
+        msg: &str,
+        prev_state: &State,
+        is_stale: &(dyn Fn() -> bool + Sync),
+        progress: &mut dyn FnMut(State),
+    ) -> Option<State>;
+    /// Puts the results in `state` in `sort` order, back in their natural order without one.
+    /// Later queries keep the sort.
+    fn sort(&self, sort: Option<Sort>, state: &State) -> State;
     fn default_plugin_state(&self) -> Box<dyn PluginState>;
 }
 
@@ -38,15 +53,38 @@ where
     }
 }
 
+/// Counts the queries sent to the plugins, only the results of the latest one are wanted.
+#[derive(Clone, Default)]
+pub struct Generation(Arc<AtomicUsize>);
+
+impl Generation {
+    pub fn next(&self) -> usize {
+        self.0.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    pub fn current(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    pub fn is_stale(&self, generation: usize) -> bool {
+        self.0.load(Ordering::SeqCst) != generation
+    }
+}
+
 pub struct State {
     count: usize,
     query: String,
+    error: Option<String>,
+    searching: bool,
     plugin_state: Box<dyn PluginState>,
 }
 
 impl Clone for State {
     fn clone(&self) -> Self {
-        State::new(self.query(), self.count, self.plugin_state.clone_box())
+        let mut state = State::new(self.query(), self.count, self.plugin_state.clone_box());
+        state.error = self.error.clone();
+        state.searching = self.searching;
+        state
     }
 }
 
@@ -59,10 +97,33 @@ impl State {
         State {
             query: query.into(),
             count,
+            error: None,
+            searching: false,
             plugin_state,
         }
     }
 
+    /// Partial results of a query still running.
+    pub fn searching<T: Into<String>>(
+        query: T,
+        count: usize,
+        plugin_state: Box<dyn PluginState>,
+    ) -> State {
+        let mut state = State::new(query, count, plugin_state);
+        state.searching = true;
+        state
+    }
+
+    pub fn with_error<T: Into<String>>(
+        query: T,
+        error: String,
+        plugin_state: Box<dyn PluginState>,
+    ) -> State {
+        let mut state = State::new(query, 0, plugin_state);
+        state.error = Some(error);
+        state
+    }
+
     pub fn count(&self) -> usize {
         self.count
     }
@@ -71,6 +132,14 @@ impl State {
         &self.query
     }
 
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_ref().map(|e| e.as_str())
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.searching
+    }
+
     pub fn plugin_state<T: 'static>(&self) -> Option<&T> {
         let state = self.plugin_state.any_ref();
         state.downcast_ref::<T>()
@@ -1,28 +1,71 @@
 use crate::actions::Action;
 use crate::actions::SimpleAction;
 use crate::dispatcher::UiAsyncMessage;
+use crate::file_listing::sort::Sort;
 use crate::file_listing::FileListing;
+use crate::file_listing::FilesMsg;
 use crate::gui::Wnd;
 use crate::gui::WM_GUI_ACTION;
+use crate::plugin::Generation;
 use crate::plugin::Plugin;
 use crate::plugin::State;
+use crate::settings::Setting;
 use crate::settings::UserSettings;
-use crossbeam_channel::internal::channel;
+use crossbeam_channel as channel;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::thread;
 use winapi::shared::minwindef::LPARAM;
 use winapi::shared::minwindef::WPARAM;
 
 pub struct PluginHandler {
     pub files: Arc<FileListing>,
-    prev_state: State,
+    generation: Generation,
+    queries: channel::Sender<(Request, usize)>,
+    updates: channel::Sender<FilesMsg>,
     pub wnd: Wnd,
 }
 
 impl PluginHandler {
     pub fn new(wnd: Wnd, files: Arc<FileListing>, initial_state: State) -> PluginHandler {
+        let generation = Generation::default();
+        let (queries, receiver) = channel::unbounded();
+        let worker = QueryWorker {
+            files: files.clone(),
+            generation: generation.clone(),
+            prev_state: initial_state,
+            wnd,
+        };
+        thread::Builder::new()
+            .name("query worker".to_string())
+            .spawn(move || worker.run_forever(receiver))
+            .unwrap();
+        // updates wait for the search holding the files, messages keep coming meanwhile and a
+        // newer query makes that search stop
+        let (updates, receiver) = channel::unbounded();
+        let updated = files.clone();
+        let (refresh, refresh_generation) = (queries.clone(), generation.clone());
+        thread::Builder::new()
+            .name("files updater".to_string())
+            .spawn(move || {
+                while let Some(msg) = receiver.recv() {
+                    let rescan = match msg {
+                        FilesMsg::JournalError(_) => true,
+                        _ => false,
+                    };
+                    updated.on_message(msg);
+                    if rescan {
+                        // the results shown are from the files before the rescan
+                        refresh.send((Request::Refresh, refresh_generation.next()));
+                    }
+                }
+            })
+            .unwrap();
         PluginHandler {
             files,
-            prev_state: initial_state,
+            generation,
+            queries,
+            updates,
             wnd,
         }
     }
@@ -41,30 +84,105 @@ impl PluginHandler {
                 }
             };
             match msg {
-                UiAsyncMessage::Files(msg) => self.files.on_message(msg),
+                UiAsyncMessage::Files(msg) => self.updates.send(msg),
                 UiAsyncMessage::Ui(msg) => {
-                    let state = self.files.handle_message(&msg, &self.prev_state);
-                    self.prev_state = state.clone();
-                    println!("{}", state.count());
-                    let action_ptr =
-                        Box::into_raw(Box::new(Action::from(SimpleAction::NewPluginState)));
-                    let state_ptr = Box::into_raw(Box::new(state));
-                    self.wnd
-                        .post_message(WM_GUI_ACTION, state_ptr as WPARAM, action_ptr as LPARAM);
+                    // a newer query makes the one running stale, it stops at its next chunk
+                    let generation = self.generation.next();
+                    self.queries.send((Request::Query(msg), generation));
+                }
+                UiAsyncMessage::Sort(sort) => {
+                    // sorts the latest results without making a query running stale
+                    let generation = self.generation.current();
+                    self.queries.send((Request::Sort(sort), generation));
+                    self.update_settings(&mut settings, Sort::to_settings(sort));
                 }
                 UiAsyncMessage::UpdateSettings(update) => {
-                    let new_settings = settings.update_settings(update).unwrap();
-                    let new_settings_ptr = Box::into_raw(Box::new(new_settings));
-                    let action_ptr =
-                        Box::into_raw(Box::new(Action::from(SimpleAction::NewSettings)));
-                    self.wnd.post_message(
-                        WM_GUI_ACTION,
-                        new_settings_ptr as WPARAM,
-                        action_ptr as LPARAM,
-                    );
+                    self.update_settings(&mut settings, update)
+                }
+                UiAsyncMessage::Exit => {
+                    self.files.on_message(FilesMsg::SaveSnapshot);
+                    return;
                 }
                 UiAsyncMessage::Start(_) => unreachable!(),
             }
         }
     }
+
+    fn update_settings(&self, settings: &mut UserSettings, update: HashMap<Setting, String>) {
+        let new_settings = settings.update_settings(update).unwrap();
+        let new_settings_ptr = Box::into_raw(Box::new(new_settings));
+        let action_ptr = Box::into_raw(Box::new(Action::from(SimpleAction::NewSettings)));
+        self.wnd.post_message(
+            WM_GUI_ACTION,
+            new_settings_ptr as WPARAM,
+            action_ptr as LPARAM,
+        );
+    }
+}
+
+enum Request {
+    Query(String),
+    Sort(Option<Sort>),
+    /// Searches the latest query again.
+    Refresh,
+}
+
+struct QueryWorker {
+    files: Arc<FileListing>,
+    generation: Generation,
+    prev_state: State,
+    wnd: Wnd,
+}
+
+impl QueryWorker {
+    fn run_forever(mut self, receiver: channel::Receiver<(Request, usize)>) {
+        while let Some((request, generation)) = receiver.recv() {
+            match request {
+                Request::Query(msg) => self.run_query(&msg, generation),
+                Request::Sort(sort) => self.sort(sort, generation),
+                Request::Refresh => {
+                    let query = self.prev_state.query().to_string();
+                    self.run_query(&query, generation)
+                }
+            }
+        }
+    }
+
+    fn run_query(&mut self, msg: &str, generation: usize) {
+        if self.generation.is_stale(generation) {
+            return;
+        }
+        let generations = &self.generation;
+        let is_stale = || generations.is_stale(generation);
+        let wnd = self.wnd;
+        let mut progress = |state: State| {
+            if !is_stale() {
+                post_state(wnd, state);
+            }
+        };
+        let state = self
+            .files
+            .handle_message(msg, &self.prev_state, &is_stale, &mut progress);
+        if let Some(state) = state {
+            if !is_stale() {
+                post_state(self.wnd, state.clone());
+            }
+            self.prev_state = state;
+        }
+    }
+
+    /// Sorts the latest results, the queries after it keep the sort.
+    fn sort(&mut self, sort: Option<Sort>, generation: usize) {
+        let state = self.files.sort(sort, &self.prev_state);
+        if !self.generation.is_stale(generation) {
+            post_state(self.wnd, state.clone());
+        }
+        self.prev_state = state;
+    }
+}
+
+fn post_state(wnd: Wnd, state: State) {
+    let action_ptr = Box::into_raw(Box::new(Action::from(SimpleAction::NewPluginState)));
+    let state_ptr = Box::into_raw(Box::new(state));
+    wnd.post_message(WM_GUI_ACTION, state_ptr as WPARAM, action_ptr as LPARAM);
 }
@@ -1,4 +1,5 @@
 use crate::errors::MyErrorKind::*;
+use crate::store::DEFAULT_INDEX;
 use crate::windows;
 use failure::Error;
 use failure::ResultExt;
@@ -6,6 +7,7 @@ use ini::Ini;
 use slog::Logger;
 use std::collections::HashMap;
 use std::fs::OpenOptions;
+use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
 use strum::AsStaticRef;
@@ -14,6 +16,8 @@ use strum::IntoEnumIterator;
 #[derive(AsStaticStr, EnumString, EnumIter, Display, Eq, Hash, PartialEq)]
 pub enum Setting {
     DbFile,
+    Index,
+    Volume,
     WindowXPosition,
     WindowYPosition,
     WindowWidth,
@@ -21,12 +25,17 @@ pub enum Setting {
     ColumnFileNameWidth,
     ColumnFilePathWidth,
     ColumnFileSizeWidth,
+    ColumnFileModifiedWidth,
+    SortColumn,
+    SortDescending,
 }
 
 impl Setting {
     pub fn default_value(&self) -> &'static str {
         match self {
             Setting::DbFile => "cloppy.db",
+            Setting::Index => DEFAULT_INDEX,
+            Setting::Volume => "\\\\.\\C:",
             Setting::WindowXPosition => "50",
             Setting::WindowYPosition => "50",
             Setting::WindowWidth => "50",
@@ -34,6 +43,9 @@ impl Setting {
             Setting::ColumnFileNameWidth => "50",
             Setting::ColumnFilePathWidth => "50",
             Setting::ColumnFileSizeWidth => "50",
+            Setting::ColumnFileModifiedWidth => "50",
+            Setting::SortColumn => "",
+            Setting::SortDescending => "false",
         }
     }
 }
@@ -79,6 +91,11 @@ impl UserSettings {
             .collect()
     }
 
+    /// Directory of the settings file, relative paths in the settings start there.
+    pub fn directory(&self) -> &Path {
+        self.location.parent().unwrap_or_else(|| Path::new(""))
+    }
+
     fn default_settings() -> Ini {
         let mut conf = Ini::new();
         for setting in Setting::iter() {
@@ -129,8 +146,14 @@ impl UserSettings {
 }
 
 pub fn setting_to_int(setting: Setting, settings: &HashMap<Setting, String>) -> i32 {
+    setting_to_str(setting, settings)
+        .parse()
+        .expect("Setting is not an int")
+}
+
+pub fn setting_to_str<'a>(setting: Setting, settings: &'a HashMap<Setting, String>) -> &'a str {
     settings
         .get(&setting)
-        .map(|s| s.parse().expect("Setting is not an int"))
-        .expect("Setting not found")
+        .map(String::as_str)
+        .unwrap_or_else(|| setting.default_value())
 }
@@ -1,134 +1,246 @@
+use crate::errors::MyErrorKind::StoreError;
 use crate::file_listing::file_entity::FileEntity;
 use crate::file_listing::files::Files;
 use crate::ntfs::file_record::FileRecord;
+use failure::Error;
 use rusqlite::Connection;
 use rusqlite::Result;
+use rusqlite::Transaction;
+use rusqlite::NO_PARAMS;
+use std::result;
 
-const CREATE_DB: &str = "
-    CREATE TABLE IF NOT EXISTS file_entry (
-    _id           INTEGER PRIMARY KEY,
-    id            INTEGER,
-    parent_id     INTEGER,
-    dos_flags     INTEGER,
-    real_size     INTEGER,
-    name          TEXT,
-    modified_date INTEGER,
-    created_date  INTEGER,
-    flags         INTEGER,
-    base_record   INTEGER,
-    fr_number     INTEGER,
-    namespace     INTEGER );
-    ";
-const INSERT_FILE: &str = "INSERT INTO file_entry (id, parent_id, dos_flags, real_size, name, modified_date, created_date, flags, base_record, fr_number, namespace) \
-    VALUES (:id, :parent_id, :dos_flags, :real_size, :name, :modified_date, :created_date, :flags, :base_record, :fr_number, :namespace);";
-const UPSERT_FILE: &str = "INSERT OR REPLACE INTO file_entry (id, parent_id, dos_flags, real_size, name, modified_date, created_date) \
-    VALUES (:id, :parent_id, :dos_flags, :real_size, :name, :modified_date, :created_date);";
-const UPDATE_FILE: &str = "UPDATE file_entry SET \
-    id = :id, parent_id = :parent_id, dos_flags = :dos_flags, real_size = :real_size, name = :name, modified_date = :modified_date, created_date = :created_date \
-    WHERE id = :id;";
-const DELETE_FILE: &str = "DELETE FROM file_entry WHERE id = :id;";
-const COUNT_FILES: &str = "SELECT COUNT(id) FROM file_entry where name like :name";
-const SELECT_FILES: &str = "SELECT name, parent_id, real_size, id FROM file_entry where name like :name order by name limit :p_size;";
-const SELECT_COUNT_ALL: &str = "SELECT COUNT(id) FROM file_entry;";
-const SELECT_ALL_FILES: &str = "SELECT * FROM file_entry;";
-const SELECT_FILES_NEXT_PAGE: &str = "SELECT name, parent_id, real_size, id FROM file_entry where name like :name and (name, id) >= (:p_name, :p_id) order by name limit :p_size;";
-//const FILE_ENTRY_NAME_INDEX: &str = "CREATE INDEX IF NOT EXISTS file_entry_name ON file_entry(name, id);";
+/// Schema changes, `PRAGMA user_version` is the number of them already applied.
+const MIGRATIONS: &[&str] = &[
+    // 1: the flat table of the first releases, kept so old databases and new ones are upgraded
+    // the same way
+    "CREATE TABLE IF NOT EXISTS file_entry (
+        _id           INTEGER PRIMARY KEY,
+        id            INTEGER,
+        parent_id     INTEGER,
+        dos_flags     INTEGER,
+        real_size     INTEGER,
+        name          TEXT,
+        modified_date INTEGER,
+        created_date  INTEGER,
+        flags         INTEGER,
+        base_record   INTEGER,
+        fr_number     INTEGER,
+        namespace     INTEGER );",
+    // 2: the standard information read since
+    "ALTER TABLE file_entry ADD COLUMN mft_modified_date INTEGER;
+    ALTER TABLE file_entry ADD COLUMN accessed_date INTEGER;
+    ALTER TABLE file_entry ADD COLUMN attributes INTEGER;",
+    // 3: one row per record and one per name, the files of a database without volumes came
+    // from C: and their dates are unix seconds instead of FILETIMEs
+    "CREATE TABLE volumes (
+        id   INTEGER PRIMARY KEY,
+        path TEXT NOT NULL UNIQUE );
+    CREATE TABLE records (
+        volume_id         INTEGER NOT NULL REFERENCES volumes(id),
+        id                INTEGER NOT NULL,
+        fr_number         INTEGER NOT NULL,
+        base_record       INTEGER NOT NULL,
+        flags             INTEGER NOT NULL,
+        real_size         INTEGER NOT NULL,
+        modified_date     INTEGER NOT NULL,
+        created_date      INTEGER NOT NULL,
+        mft_modified_date INTEGER NOT NULL,
+        accessed_date     INTEGER NOT NULL,
+        attributes        INTEGER NOT NULL,
+        PRIMARY KEY (volume_id, id) ) WITHOUT ROWID;
+    CREATE TABLE names (
+        volume_id INTEGER NOT NULL,
+        record_id INTEGER NOT NULL,
+        parent_id INTEGER NOT NULL,
+        name      TEXT NOT NULL,
+        namespace INTEGER NOT NULL,
+        dos_flags INTEGER NOT NULL,
+        PRIMARY KEY (volume_id, record_id, parent_id, name),
+        FOREIGN KEY (volume_id, record_id) REFERENCES records(volume_id, id) ) WITHOUT ROWID;
+    CREATE INDEX names_name ON names(name);
+    CREATE INDEX names_parent ON names(volume_id, parent_id);
+    INSERT INTO volumes (id, path) SELECT 1, '\\\\.\\C:' WHERE EXISTS (SELECT 1 FROM file_entry);
+    INSERT OR IGNORE INTO records (volume_id, id, fr_number, base_record, flags, real_size, modified_date, created_date, mft_modified_date, accessed_date, attributes)
+        SELECT 1, id, IFNULL(fr_number, id), IFNULL(base_record, 0), IFNULL(flags, 0), IFNULL(real_size, 0),
+            IFNULL((modified_date + 11644473600) * 10000000, 0), IFNULL((created_date + 11644473600) * 10000000, 0),
+            IFNULL(mft_modified_date, 0), IFNULL(accessed_date, 0), IFNULL(attributes, 0)
+        FROM file_entry ORDER BY _id;
+    INSERT OR IGNORE INTO names (volume_id, record_id, parent_id, name, namespace, dos_flags)
+        SELECT 1, id, parent_id, name, IFNULL(namespace, 0), IFNULL(dos_flags, 0) FROM file_entry;
+    DROP TABLE file_entry;",
+];
 
-//const FILE_PAGE_SIZE: u32 = 3000;
+const INSERT_VOLUME: &str = "INSERT OR IGNORE INTO volumes (path) VALUES (:path);";
+const SELECT_VOLUME: &str = "SELECT id FROM volumes WHERE path = :path;";
+const DELETE_RECORDS: &str = "DELETE FROM records WHERE volume_id = :volume_id;";
+const DELETE_NAMES: &str = "DELETE FROM names WHERE volume_id = :volume_id;";
+const INSERT_RECORD: &str = "INSERT OR REPLACE INTO records (volume_id, id, fr_number, base_record, flags, real_size, modified_date, created_date, mft_modified_date, accessed_date, attributes) \
+    VALUES (:volume_id, :id, :fr_number, :base_record, :flags, :real_size, :modified_date, :created_date, :mft_modified_date, :accessed_date, :attributes);";
+const INSERT_NAME: &str =
+    "INSERT OR IGNORE INTO names (volume_id, record_id, parent_id, name, namespace, dos_flags) \
+    VALUES (:volume_id, :record_id, :parent_id, :name, :namespace, :dos_flags);";
+const COUNT_NAMES: &str = "SELECT COUNT(*) FROM names WHERE volume_id = :volume_id;";
+const SELECT_FILES: &str = "SELECT r.id, n.parent_id, n.name, r.flags, r.real_size, r.modified_date, r.created_date, r.mft_modified_date, r.accessed_date, r.attributes \
+    FROM records r JOIN names n ON n.volume_id = r.volume_id AND n.record_id = r.id \
+    WHERE r.volume_id = :volume_id;";
 
-pub fn main() -> Connection {
-    let conn = Connection::open("test.db").unwrap();
-    //    let conn = Connection::open_in_memory().unwrap();
-
-    conn.execute(CREATE_DB, params![]).unwrap();
-    conn.prepare_cached(INSERT_FILE).unwrap();
-    conn.prepare_cached(UPDATE_FILE).unwrap();
-    conn.prepare_cached(DELETE_FILE).unwrap();
-    conn.prepare_cached(UPSERT_FILE).unwrap();
-    conn.prepare_cached(COUNT_FILES).unwrap();
-    conn.prepare_cached(SELECT_FILES).unwrap();
-    conn.prepare_cached(SELECT_FILES_NEXT_PAGE).unwrap();
-    conn
+/// Brings the schema up to date, returns its version.
+pub fn migrate(conn: &mut Connection) -> result::Result<usize, Error> {
+    let version: i64 = conn.query_row("PRAGMA user_version;", NO_PARAMS, |r| r.get(0))?;
+    let version = version as usize;
+    if version > MIGRATIONS.len() {
+        Err(StoreError("the database is from a newer version"))?
+    }
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.execute_batch(&format!("PRAGMA user_version = {};", i + 1))?;
+        tx.commit()?;
+    }
+    Ok(MIGRATIONS.len())
 }
 
-//pub fn delete_file(tx: &Transaction, file_id: u32) {
-//    tx.execute_named(DELETE_FILE, &[
-//        (":id", &file_id)]).unwrap();
-//}
-
-//pub fn upsert_file(tx: &Transaction, file: &FileRecord) {
-//    tx.execute_named(UPSERT_FILE, &[
-//        (":id", &file.id),
-//        (":parent_id", &file.parent_id),
-//        (":dos_flags", &file.dos_flags),
-//        (":real_size", &file.real_size),
-//        (":name", &file.name),
-//        (":modified_date", &file.modified_date),
-//        (":created_date", &file.created_date),
-//    ]).unwrap();
-//}
-
-//pub fn update_file(tx: &Transaction, file: &FileRecord) {
-//    tx.execute_named(UPDATE_FILE, &[
-//        (":id", &file.id),
-//        (":parent_id", &file.parent_id),
-//        (":dos_flags", &file.dos_flags),
-//        (":real_size", &file.real_size),
-//        (":name", &file.name),
-//        (":modified_date", &file.modified_date),
-//        (":created_date", &file.created_date),
-//    ]).unwrap();
-//}
-
-//pub fn create_indices(con: &Connection) {
-//    con.execute(FILE_ENTRY_NAME_INDEX, &[]).unwrap();
-//}
-
-pub fn insert_files(files: &[FileRecord]) {
-    let mut conn = main();
-    let tx = conn.transaction().unwrap();
+/// Replaces the files of a previous scan of `volume`.
+pub fn insert_files(conn: &mut Connection, volume: &str, files: &[FileRecord]) -> Result<()> {
+    let tx = conn.transaction()?;
+    let volume_id = volume_id(&tx, volume)?;
+    tx.execute_named(DELETE_NAMES, &[(":volume_id", &volume_id)])?;
+    tx.execute_named(DELETE_RECORDS, &[(":volume_id", &volume_id)])?;
     {
-        let mut stmt = tx.prepare_cached(INSERT_FILE).unwrap();
+        let mut insert_record = tx.prepare_cached(INSERT_RECORD)?;
+        let mut insert_name = tx.prepare_cached(INSERT_NAME)?;
         for file in files {
-            file.name_attrs
-                .iter()
-                .filter(|n| n.namespace != 2)
-                .for_each(|name| {
-                    stmt.execute_named(&[
-                        (":id", &file.header.fr_number),
-                        (":parent_id", &(name.parent_id as u32)),
-                        (":dos_flags", &name.dos_flags),
-                        (":real_size", &file.data_attr.size),
-                        (":name", &name.name),
-                        (":modified_date", &file.standard_attr.modified),
-                        (":created_date", &file.standard_attr.created),
-                        (":base_record", &(file.header.base_record as i64)),
-                        (":fr_number", &file.fr_number()),
-                        (":namespace", &name.namespace),
-                        (":flags", &file.header.flags),
-                    ])
-                    .unwrap();
-                });
+            insert_record.execute_named(&[
+                (":volume_id", &volume_id),
+                (":id", &file.header.fr_number),
+                (":fr_number", &file.fr_number()),
+                (":base_record", &(file.header.base_record as i64)),
+                (":flags", &file.header.flags),
+                (":real_size", &file.data_attr.size),
+                (":modified_date", &file.standard_attr.modified),
+                (":created_date", &file.standard_attr.created),
+                (":mft_modified_date", &file.standard_attr.mft_modified),
+                (":accessed_date", &file.standard_attr.accessed),
+                (":attributes", &file.standard_attr.attributes.bits()),
+            ])?;
+            for name in file.name_attrs.iter().filter(|n| n.namespace != 2) {
+                insert_name.execute_named(&[
+                    (":volume_id", &volume_id),
+                    (":record_id", &file.header.fr_number),
+                    (":parent_id", &(name.parent_id as u32)),
+                    (":name", &name.name),
+                    (":namespace", &name.namespace),
+                    (":dos_flags", &name.dos_flags),
+                ])?;
+            }
         }
     }
-    tx.commit().unwrap();
+    tx.commit()
 }
 
-pub fn load_all_arena() -> Result<(Files)> {
-    let con = Connection::open("test.db").unwrap();
-    let count = con
-        .query_row(SELECT_COUNT_ALL, params![], |r| r.get::<usize, u32>(0))
-        .unwrap() as usize;
-    let mut stmt = con.prepare(SELECT_ALL_FILES).unwrap();
-    let result = stmt
-        .query_map(params![], FileEntity::from_file_row)
-        .unwrap();
+pub fn load_all_arena(con: &Connection, volume: &str) -> Result<(Files)> {
+    let volume_id: i64 =
+        match con.query_row_named(SELECT_VOLUME, &[(":path", &volume)], |r| r.get(0)) {
+            Ok(id) => id,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(Files::new()),
+            Err(e) => return Err(e),
+        };
+    let count = con.query_row_named(COUNT_NAMES, &[(":volume_id", &volume_id)], |r| {
+        r.get::<usize, u32>(0)
+    })? as usize;
+    let mut stmt = con.prepare(SELECT_FILES)?;
+    let result = stmt.query_map_named(&[(":volume_id", &volume_id)], FileEntity::from_file_row)?;
     let mut files = Vec::with_capacity(count);
     for file in result {
         let f: FileEntity = file?;
         files.push(f);
     }
-    let mut arena = Files::new(count);
+    let mut arena = Files::new();
     arena.bulk_add(files);
     Ok(arena)
 }
+
+fn volume_id(tx: &Transaction, volume: &str) -> Result<i64> {
+    tx.execute_named(INSERT_VOLUME, &[(":path", &volume)])?;
+    tx.query_row_named(SELECT_VOLUME, &[(":path", &volume)], |r| r.get(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_listing::file_entity::FileId;
+
+    const VOLUME: &str = "\\\\.\\C:";
+
+    fn user_version(conn: &Connection) -> i64 {
+        conn.query_row("PRAGMA user_version;", NO_PARAMS, |r| r.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn new_database_gets_the_latest_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(MIGRATIONS.len(), migrate(&mut conn).unwrap());
+        assert_eq!(MIGRATIONS.len() as i64, user_version(&conn));
+        assert_eq!(MIGRATIONS.len(), migrate(&mut conn).unwrap());
+        assert_eq!(
+            0,
+            load_all_arena(&conn, VOLUME)
+                .unwrap()
+                .storage()
+                .names()
+                .len()
+        );
+
+        conn.execute_batch("PRAGMA user_version = 100;").unwrap();
+        assert!(migrate(&mut conn).is_err());
+    }
+
+    #[test]
+    fn old_database_is_upgraded_in_place() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        // the schema and rows written by the first releases
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS file_entry (
+                _id           INTEGER PRIMARY KEY,
+                id            INTEGER,
+                parent_id     INTEGER,
+                dos_flags     INTEGER,
+                real_size     INTEGER,
+                name          TEXT,
+                modified_date INTEGER,
+                created_date  INTEGER,
+                flags         INTEGER,
+                base_record   INTEGER,
+                fr_number     INTEGER,
+                namespace     INTEGER );
+            INSERT INTO file_entry (id, parent_id, dos_flags, real_size, name, modified_date, created_date, flags, base_record, fr_number, namespace) VALUES
+                (5, 5, 0, 0, 'C:', 0, 0, 3, 0, 5, 3),
+                (7, 5, 0, 42, 'report.doc', 1445836384, 1445836000, 1, 0, 7, 1),
+                (7, 5, 0, 42, 'raport.doc', 1445836384, 1445836000, 1, 0, 7, 1);",
+        )
+        .unwrap();
+
+        assert_eq!(MIGRATIONS.len(), migrate(&mut conn).unwrap());
+        let count = |table: &str| -> i64 {
+            conn.query_row(
+                &format!("SELECT COUNT(*) FROM {};", table),
+                NO_PARAMS,
+                |r| r.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(1, count("volumes"));
+        assert_eq!(2, count("records"));
+        assert_eq!(3, count("names"));
+
+        let files = load_all_arena(&conn, VOLUME).unwrap();
+        let report = files.get_file(FileId::file(7)).data;
+        assert_eq!(2, report.links().len());
+        assert_eq!(42, report.size());
+        assert_eq!(130_903_099_840_000_000, report.times().modified);
+        assert_eq!(130_903_096_000_000_000, report.times().created);
+        assert_eq!(0, report.times().mft_modified);
+        assert!(files.get_file(FileId::directory(5)).data.is_directory());
+    }
+}
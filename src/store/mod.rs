@@ -0,0 +1,180 @@
+use crate::errors::MyErrorKind::StoreError;
+use crate::file_listing::files::Files;
+use crate::file_listing::snapshot;
+use crate::ntfs::file_record::FileRecord;
+use crate::settings::setting_to_str;
+use crate::settings::Setting;
+use crate::settings::UserSettings;
+use crate::sql;
+use failure::Error;
+use failure::ResultExt;
+use rusqlite::Connection;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Index kept in the `DbFile` itself, the others get their name added to it.
+pub const DEFAULT_INDEX: &str = "default";
+/// Database the first releases kept in the working directory.
+const OLD_DB_FILE: &str = "test.db";
+
+/// Everything persisted for an index: the database with the files of the last scan and the
+/// snapshot the index is loaded from on start.
+pub struct Store {
+    name: String,
+    db_path: PathBuf,
+    snapshot_path: PathBuf,
+}
+
+impl Store {
+    /// Store of the index `name` where `Setting::DbFile` points, a relative path is taken from
+    /// the settings directory. On the first run the default index takes over the database of
+    /// the first releases.
+    pub fn from_settings(settings: &UserSettings, name: &str) -> Result<Store, Error> {
+        let db_file = setting_to_str(Setting::DbFile, &settings.get_settings()).to_string();
+        let store = Store::new(settings.directory().join(db_file), name)?;
+        if name == DEFAULT_INDEX {
+            store.take_over(Path::new(OLD_DB_FILE))?;
+        }
+        Ok(store)
+    }
+
+    pub fn new(db_file: PathBuf, name: &str) -> Result<Store, Error> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        {
+            Err(StoreError("invalid index name"))?
+        }
+        let snapshot_path = index_file(db_file.with_extension("snapshot"), name);
+        let db_path = index_file(db_file, name);
+        Ok(Store {
+            name: name.to_string(),
+            db_path,
+            snapshot_path,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn db_path(&self) -> &Path {
+        &self.db_path
+    }
+
+    pub fn snapshot_path(&self) -> &Path {
+        &self.snapshot_path
+    }
+
+    /// Replaces the files of the previous scan of `volume`.
+    pub fn insert_files(&self, volume: &str, files: &[FileRecord]) -> Result<(), Error> {
+        let mut conn = self.connection()?;
+        sql::insert_files(&mut conn, volume, files)
+            .context(StoreError("failed to write the files"))?;
+        Ok(())
+    }
+
+    pub fn load_files(&self, volume: &str) -> Result<Files, Error> {
+        let conn = self.connection()?;
+        let files =
+            sql::load_all_arena(&conn, volume).context(StoreError("failed to read the files"))?;
+        Ok(files)
+    }
+
+    pub fn load_snapshot(&self) -> Result<Files, Error> {
+        snapshot::load(&self.snapshot_path)
+    }
+
+    pub fn save_snapshot(&self, files: &Files) -> Result<(), Error> {
+        if let Some(dir) = self.snapshot_path.parent() {
+            fs::create_dir_all(dir).context(StoreError("failed to create the directory"))?;
+        }
+        snapshot::save(&self.snapshot_path, files)
+    }
+
+    /// Moves `old_db` where the database goes unless there is one already, it is upgraded once
+    /// opened.
+    fn take_over(&self, old_db: &Path) -> Result<(), Error> {
+        if self.db_path.exists() || !old_db.is_file() {
+            return Ok(());
+        }
+        if let Some(dir) = self.db_path.parent() {
+            fs::create_dir_all(dir).context(StoreError("failed to create the directory"))?;
+        }
+        // renaming fails across drives
+        if fs::rename(old_db, &self.db_path).is_err() {
+            fs::copy(old_db, &self.db_path)
+                .context(StoreError("failed to copy the old database"))?;
+        }
+        Ok(())
+    }
+
+    fn connection(&self) -> Result<Connection, Error> {
+        if let Some(dir) = self.db_path.parent() {
+            fs::create_dir_all(dir).context(StoreError("failed to create the directory"))?;
+        }
+        let mut conn =
+            Connection::open(&self.db_path).context(StoreError("failed to open the database"))?;
+        sql::migrate(&mut conn).context(StoreError("failed to upgrade the database"))?;
+        Ok(conn)
+    }
+}
+
+/// `cloppy.db` holds the default index, `cloppy.work.db` the one named `work`.
+fn index_file(db_file: PathBuf, name: &str) -> PathBuf {
+    if name == DEFAULT_INDEX {
+        return db_file;
+    }
+    let mut file_name = db_file.file_stem().map(OsString::from).unwrap_or_default();
+    file_name.push(".");
+    file_name.push(name);
+    if let Some(extension) = db_file.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    db_file.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempFile;
+
+    #[test]
+    fn every_index_has_its_own_files() {
+        let default = Store::new(PathBuf::from("data/cloppy.db"), DEFAULT_INDEX).unwrap();
+        assert_eq!(Path::new("data/cloppy.db"), default.db_path());
+        assert_eq!(Path::new("data/cloppy.snapshot"), default.snapshot_path());
+
+        let work = Store::new(PathBuf::from("data/cloppy.db"), "work").unwrap();
+        assert_eq!(Path::new("data/cloppy.work.db"), work.db_path());
+        assert_eq!(Path::new("data/cloppy.work.snapshot"), work.snapshot_path());
+
+        let no_extension = Store::new(PathBuf::from("index"), "D").unwrap();
+        assert_eq!(Path::new("index.D"), no_extension.db_path());
+        assert_eq!(Path::new("index.D.snapshot"), no_extension.snapshot_path());
+
+        assert!(Store::new(PathBuf::from("cloppy.db"), "").is_err());
+        assert!(Store::new(PathBuf::from("cloppy.db"), "../other").is_err());
+    }
+
+    #[test]
+    fn old_database_is_taken_over_once() {
+        let old_db = TempFile::with_content("old_db", b"old");
+        let db_file = TempFile::with_content("db", b"");
+        fs::remove_file(db_file.path()).unwrap();
+        let store = Store::new(db_file.path().to_path_buf(), DEFAULT_INDEX).unwrap();
+
+        store.take_over(old_db.path()).unwrap();
+        assert_eq!(b"old".to_vec(), fs::read(store.db_path()).unwrap());
+        assert!(!old_db.path().exists());
+
+        let newer_db = TempFile::with_content("newer_db", b"newer");
+        store.take_over(newer_db.path()).unwrap();
+        assert_eq!(b"old".to_vec(), fs::read(store.db_path()).unwrap());
+        assert!(newer_db.path().exists());
+    }
+}
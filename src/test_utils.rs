@@ -0,0 +1,31 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// File in the temp directory, unique to the test that made it and removed once dropped.
+pub struct TempFile(PathBuf);
+
+impl TempFile {
+    pub fn with_content(prefix: &str, content: &[u8]) -> TempFile {
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        let mut path = env::temp_dir();
+        path.push(format!("cloppy_{}_{}_{}", prefix, process::id(), id));
+        fs::write(&path, content).unwrap();
+        TempFile(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::async_io::{CompletedOperation, CompletionPort};
 use crate::errors::MyErrorKind::WindowsError;
 use crate::windows::read_overlapped;
 use failure::{Error, ResultExt};
@@ -33,6 +34,8 @@ pub struct InputOperation {
     pub buffer: *mut u8,
     buffer_len: usize,
     buffer_capacity: usize,
+    // set when the read failed, the operation still comes back to return its buffer
+    os_error: i32,
 }
 
 pub struct OutputOperation(OVERLAPPED_ENTRY);
@@ -62,6 +65,14 @@ impl OutputOperation {
     pub fn completion_key(&self) -> usize {
         self.0.lpCompletionKey as usize
     }
+
+    pub fn bytes_read(&self) -> io::Result<usize> {
+        let os_error = unsafe { (*(self.0.lpOverlapped as *mut InputOperation)).os_error };
+        match os_error {
+            0 => Ok(self.0.dwNumberOfBytesTransferred as usize),
+            code => Err(io::Error::from_raw_os_error(code)),
+        }
+    }
 }
 
 pub struct AsyncFile {
@@ -87,6 +98,7 @@ impl InputOperation {
             buffer: buffer.as_mut_ptr(),
             buffer_len: buffer.len(),
             buffer_capacity: buffer.capacity(),
+            os_error: 0,
         };
         ::std::mem::forget(buffer);
         res
@@ -160,14 +172,22 @@ impl IOCompletionPort {
                 &mut overlapped,
                 INFINITE,
             ) {
-                v if v == 0 => Err(io::Error::last_os_error())
+                // a failed read still dequeues its operation, nothing was dequeued otherwise
+                v if v == 0 && overlapped.is_null() => Err(io::Error::last_os_error())
                     .context(WindowsError("GetQueuedCompletionStatus failed"))?,
-                _ => Ok(OutputOperation(OVERLAPPED_ENTRY {
-                    dwNumberOfBytesTransferred: bytes_read,
-                    lpCompletionKey: completion_key,
-                    lpOverlapped: overlapped,
-                    Internal: 0,
-                })),
+                v => {
+                    if v == 0 {
+                        let operation = &mut *(overlapped as *mut InputOperation);
+                        operation.os_error =
+                            io::Error::last_os_error().raw_os_error().unwrap_or(-1);
+                    }
+                    Ok(OutputOperation(OVERLAPPED_ENTRY {
+                        dwNumberOfBytesTransferred: bytes_read,
+                        lpCompletionKey: completion_key,
+                        lpOverlapped: overlapped,
+                        Internal: 0,
+                    }))
+                }
             }
         }
     }
@@ -196,6 +216,60 @@ impl IOCompletionPort {
     }
 }
 
+impl CompletionPort for IOCompletionPort {
+    type File = AsyncFile;
+    type Output = OutputOperation;
+
+    fn associate_file<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+        completion_key: usize,
+    ) -> Result<AsyncFile, Error> {
+        IOCompletionPort::associate_file(self, file_path, completion_key)
+    }
+
+    fn submit_read(
+        &self,
+        file: &AsyncFile,
+        buffer: Vec<u8>,
+        offset: u64,
+        content_len: usize,
+    ) -> Result<(), Error> {
+        let operation = Box::new(InputOperation::new(buffer, offset, content_len));
+        IOCompletionPort::submit(file, operation)
+    }
+
+    fn post_empty(&self, completion_key: usize) -> Result<(), Error> {
+        self.post(Box::new(InputOperation::empty()), completion_key)
+    }
+
+    fn get_completed(&self) -> Result<OutputOperation, Error> {
+        self.get()
+    }
+}
+
+impl CompletedOperation for OutputOperation {
+    fn buffer_mut(&mut self) -> &mut [u8] {
+        OutputOperation::buffer_mut(self)
+    }
+
+    fn into_buffer(self) -> Vec<u8> {
+        OutputOperation::into_buffer(self)
+    }
+
+    fn content_len(&self) -> usize {
+        OutputOperation::content_len(self)
+    }
+
+    fn bytes_read(&self) -> io::Result<usize> {
+        OutputOperation::bytes_read(self)
+    }
+
+    fn completion_key(&self) -> usize {
+        OutputOperation::completion_key(self)
+    }
+}
+
 impl Drop for IOCompletionPort {
     fn drop(&mut self) {
         unsafe { CloseHandle(self.0) };
@@ -205,25 +279,13 @@ impl Drop for IOCompletionPort {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::env;
-    use std::fs::File;
-    use std::io::Write;
-    use std::path::PathBuf;
-
-    fn temp_file() -> PathBuf {
-        let mut dir = env::temp_dir();
-        dir.push("iocp_test");
-        {
-            let mut tmp_file = File::create(&dir).unwrap();
-            write!(tmp_file, "hello world").unwrap();
-        }
-        dir
-    }
+    use crate::test_utils::TempFile;
 
     #[test]
     fn test_iocp_read() {
+        let tmp = TempFile::with_content("iocp", b"hello world");
         let iocp = IOCompletionPort::new(1).unwrap();
-        let file = iocp.associate_file(temp_file(), 42).unwrap();
+        let file = iocp.associate_file(tmp.path(), 42).unwrap();
 
         let operation = Box::new(InputOperation::new(vec![0u8; 20], 0, 0));
         IOCompletionPort::submit(&file, operation).unwrap();
@@ -1,4 +1,2 @@
-mod buffer_pool;
 mod iocp;
-pub use self::buffer_pool::BufferPool;
 pub use self::iocp::{AsyncFile, IOCompletionPort, InputOperation, OutputOperation};